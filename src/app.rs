@@ -1,7 +1,27 @@
+use std::collections::HashMap;
+
+use crate::config::Config;
 use crate::db::*;
-use crate::parser::{get_today, parse_date_str, parse_time_str};
-use chrono::{Duration, Local, NaiveDate};
+use crate::export::{StatusExport, SummaryExport, EXPORT_SCHEMA_VERSION};
+use crate::parser::{
+    get_now, get_today, parse_date_str, parse_datetime_str, parse_duration_str, parse_time_range,
+    parse_time_str,
+};
+use crate::paths::{Marker, Paths};
+use crate::period::{parse_period, Period, PeriodArgs};
+use crate::policy::{Day, Policy, Round};
+use crate::prompt::{confirm, input};
+use crate::report::{
+    breakdown_table, covered_time, day_receipt, duration_bar, format_check_out, format_delta,
+    format_duration, format_hours, format_money, format_relative_date, merge_gaps,
+    period_report_json, period_report_table, profitability, retro_markdown, round_duration,
+    slack_message, summarize_days, teams_message, typical_times, DaySummary, Dimension,
+    MessageFormat, Pivot, ProjectColors, RoundingMode, Targets,
+};
+use crate::service::TimeKeeper;
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime};
 use comfy_table::{Cell, Color, ContentArrangement, Row, Table};
+use serde_json::json;
 
 #[derive(Debug)]
 pub enum TimeKeeperError {
@@ -9,7 +29,9 @@ pub enum TimeKeeperError {
     InvalidTime(String),
     CheckOutBeforeCheckIn,
     NoCheckInRecord,
+    AlreadyCheckedIn(NaiveDateTime),
     ParseError(String),
+    IoError(std::io::Error),
 }
 
 impl From<rusqlite::Error> for TimeKeeperError {
@@ -18,60 +40,136 @@ impl From<rusqlite::Error> for TimeKeeperError {
     }
 }
 
-pub fn handle_check_in(time_str: &str, date: Option<String>) -> Result<(), TimeKeeperError> {
-    let check_in = parse_time_str(time_str)?;
-    let date = match date {
-        Some(date_str) => NaiveDate::parse_from_str(&date_str, "%Y-%m-%d")
-            .map_err(|_| TimeKeeperError::ParseError("Invalid date format".to_string()))?,
-        None => get_today(),
-    };
+impl From<std::io::Error> for TimeKeeperError {
+    fn from(err: std::io::Error) -> Self {
+        TimeKeeperError::IoError(err)
+    }
+}
 
-    // Create a new record with check_out as None
-    let record = Record {
-        id: 0,
-        check_in,
-        check_out: check_in, // Temporary value, will be updated on check-out
-        date,
+pub fn handle_check_in(
+    keeper: &TimeKeeper,
+    time_str: &str,
+    date: Option<String>,
+    switch: bool,
+    location: Option<String>,
+    travel: bool,
+    project: Option<String>,
+) -> Result<(), TimeKeeperError> {
+    let date = date
+        .map(|date_str| {
+            NaiveDate::parse_from_str(&date_str, "%Y-%m-%d")
+                .map_err(|_| TimeKeeperError::ParseError("Invalid date format".to_string()))
+        })
+        .transpose()?;
+    let check_in = parse_datetime_str(time_str, date, get_today())?;
+    let location = location.or(Config::load()?.default_location);
+    let project = project.or(Marker::from_current_dir()?.and_then(|marker| marker.project));
+
+    // Only one session may be open, whichever day it started on; --switch
+    // closes it at the new check-in time
+    let record = match keeper.open_session()? {
+        Some(open) => {
+            let prompt = format!(
+                "A session started at {} is still open. Close it and check in?",
+                open.check_in.format("%H:%M")
+            );
+            if !switch && !confirm(&prompt, false)? {
+                return Err(TimeKeeperError::AlreadyCheckedIn(open.check_in));
+            }
+
+            let (closed, opened) = keeper.switch(check_in, project, location, travel)?;
+            println!(
+                "Checked out of session started at {}",
+                closed.check_in.format("%H:%M")
+            );
+            opened
+        }
+        None => keeper.check_in(check_in, project, location, travel)?,
     };
 
-    save_entry(&record).map_err(TimeKeeperError::from)?;
-    println!("Checked in at {}", check_in.format("%H:%M"));
+    match &record.project {
+        Some(project) => println!("Checked in at {} on {}", check_in.format("%H:%M"), project),
+        None => println!("Checked in at {}", check_in.format("%H:%M")),
+    }
     Ok(())
 }
 
-pub fn handle_check_out(time_str: &str, date: Option<String>) -> Result<(), TimeKeeperError> {
-    let check_out = parse_time_str(time_str)?;
-    let date = match date {
-        Some(date_str) => NaiveDate::parse_from_str(&date_str, "%Y-%m-%d")
-            .map_err(|_| TimeKeeperError::ParseError("Invalid date format".to_string()))?,
-        None => get_today(),
-    };
-
-    // Get the latest record for today
-    let records = get_entries_by_date(date).map_err(TimeKeeperError::from)?;
-    let latest_record = records.last().ok_or(TimeKeeperError::NoCheckInRecord)?;
+// Tells apart a day with no sessions from one whose sessions are all closed
+fn no_open_session(db: &Db, date: NaiveDate) -> Result<TimeKeeperError, TimeKeeperError> {
+    let last = db
+        .get_entries_by_date(date)?
+        .into_iter()
+        .max_by_key(|record| record.end());
+    Ok(match last {
+        Some(last) => TimeKeeperError::ParseError(format!(
+            "All sessions on {} are closed, the last ended at {}",
+            date.format("%Y-%m-%d"),
+            format_check_out(&last)
+        )),
+        None => TimeKeeperError::NoCheckInRecord,
+    })
+}
 
-    // Verify check-out time is after check-in
-    if check_out <= latest_record.check_in {
-        return Err(TimeKeeperError::CheckOutBeforeCheckIn);
+// A check-out that is not after the check-in falls on the next day when the
+// entry is marked --overnight
+fn overnight_check_out(
+    check_in: NaiveDateTime,
+    check_out: NaiveDateTime,
+    overnight: bool,
+) -> NaiveDateTime {
+    if overnight && check_out <= check_in {
+        check_out + Duration::days(1)
+    } else {
+        check_out
     }
+}
 
-    // Update the record with check-out time
-    let updated_record = Record {
-        id: latest_record.id,
-        check_in: latest_record.check_in,
-        check_out,
-        date,
+// Closes the given session, or else the most recent one still open,
+// whichever day it started on. With an id the check-out time is on that session's day by default.
+pub fn handle_check_out(
+    keeper: &TimeKeeper,
+    time_str: &str,
+    date: Option<String>,
+    overnight: bool,
+    id: Option<i32>,
+) -> Result<(), TimeKeeperError> {
+    let date = match date {
+        Some(date_str) => Some(
+            NaiveDate::parse_from_str(&date_str, "%Y-%m-%d")
+                .map_err(|_| TimeKeeperError::ParseError("Invalid date format".to_string()))?,
+        ),
+        None => None,
+    };
+    // Only an open session can be checked out, never a completed record
+    let latest_record = match id {
+        Some(id) => keeper
+            .entry(id)?
+            .ok_or_else(|| TimeKeeperError::ParseError(format!("No entry with id {}", id)))?,
+        None => {
+            let day = date.unwrap_or_else(get_today);
+            match keeper.open_session()? {
+                Some(open) => open,
+                None => return Err(no_open_session(keeper.db(), day)?),
+            }
+        }
+    };
+    let day = match id {
+        Some(_) => latest_record.check_in.date(),
+        None => get_today(),
     };
+    let check_out = overnight_check_out(
+        latest_record.check_in,
+        parse_datetime_str(time_str, date, day)?,
+        overnight,
+    );
 
-    update_entry(&updated_record).map_err(TimeKeeperError::from)?;
+    // A break still running ends with the check-out
+    let updated_record = keeper.close(latest_record.id, check_out)?;
 
     // Calculate duration
-    let duration = check_out
-        .signed_duration_since(latest_record.check_in)
-        .num_minutes();
+    let duration = updated_record.duration().num_minutes();
 
-    println!("Checked out at {}", check_out.format("%H:%M"));
+    println!("Checked out at {}", format_check_out(&updated_record));
     println!(
         "Total time: {} hours {} minutes",
         duration / 60,
@@ -80,36 +178,287 @@ pub fn handle_check_out(time_str: &str, date: Option<String>) -> Result<(), Time
     Ok(())
 }
 
+// Starts a break within the open session, unpaid unless --paid is given
+pub fn handle_break_start(
+    keeper: &TimeKeeper,
+    time_str: &str,
+    paid: bool,
+) -> Result<(), TimeKeeperError> {
+    let today = get_today();
+    let open = keeper
+        .open_session()?
+        .ok_or(TimeKeeperError::NoCheckInRecord)?;
+    if let Some(running) = keeper.db().get_open_break(open.id)? {
+        return Err(TimeKeeperError::ParseError(format!(
+            "A break is already running since {}",
+            running.start.format("%H:%M")
+        )));
+    }
+
+    let start = parse_datetime_str(time_str, None, today)?;
+    if start < open.check_in {
+        return Err(TimeKeeperError::ParseError(
+            "A break cannot start before the session".to_string(),
+        ));
+    }
+
+    keeper.db().save_break(&Break {
+        id: 0,
+        record_id: open.id,
+        start,
+        end: None,
+        paid,
+    })?;
+    println!(
+        "{} break started at {}",
+        if paid { "Paid" } else { "Unpaid" },
+        start.format("%H:%M")
+    );
+    Ok(())
+}
+
+pub fn handle_break_end(keeper: &TimeKeeper, time_str: &str) -> Result<(), TimeKeeperError> {
+    let today = get_today();
+    let open = keeper
+        .open_session()?
+        .ok_or(TimeKeeperError::NoCheckInRecord)?;
+    let running = keeper
+        .db()
+        .get_open_break(open.id)?
+        .ok_or_else(|| TimeKeeperError::ParseError("No break is running".to_string()))?;
+
+    let end = parse_datetime_str(time_str, None, today)?;
+    if end <= running.start {
+        return Err(TimeKeeperError::CheckOutBeforeCheckIn);
+    }
+
+    keeper.db().end_break(running.id, end)?;
+    println!(
+        "Break ended at {} after {}",
+        end.format("%H:%M"),
+        format_duration(end - running.start)
+    );
+    Ok(())
+}
+
+// Counts an interruption against the open session
+pub fn handle_interrupt(
+    keeper: &TimeKeeper,
+    reason: Option<String>,
+) -> Result<(), TimeKeeperError> {
+    let now = Local::now().naive_local();
+    let open = keeper
+        .open_session()?
+        .ok_or(TimeKeeperError::NoCheckInRecord)?;
+
+    keeper.db().save_interruption(&Interruption {
+        id: 0,
+        record_id: open.id,
+        at: now,
+        reason,
+    })?;
+    let count = keeper
+        .db()
+        .get_interruptions_between(open.date(), open.date())?
+        .iter()
+        .filter(|interruption| interruption.record_id == open.id)
+        .count();
+    println!(
+        "Interruption {} in the session started at {}",
+        count,
+        open.check_in.format("%H:%M")
+    );
+    Ok(())
+}
+
+// Interruptions per day, project or any other dimension, against time worked
+pub fn display_interruptions(
+    db: &Db,
+    by: Dimension,
+    period: &PeriodArgs,
+) -> Result<(), TimeKeeperError> {
+    let today = get_today();
+    let period = period
+        .resolve(today)?
+        .unwrap_or_else(|| Period::month_of(today));
+
+    let records: Vec<Record> = db
+        .get_entries_between(period.from, period.to)?
+        .into_iter()
+        .filter(|r| !r.travel)
+        .collect();
+    let interruptions = db.get_interruptions_between(period.from, period.to)?;
+    if interruptions.is_empty() {
+        println!("No interruptions recorded for {}", period);
+        return Ok(());
+    }
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for interruption in &interruptions {
+        if let Some(record) = records.iter().find(|r| r.id == interruption.record_id) {
+            *counts.entry(by.label(record)).or_default() += 1;
+        }
+    }
+
+    let pivot = Pivot::new(&records, by, None);
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Row::from(vec![
+            Cell::new(by.header()).fg(Color::Cyan),
+            Cell::new("Interruptions").fg(Color::Red),
+            Cell::new("Worked").fg(Color::Yellow),
+            Cell::new("Per hour").fg(Color::Green),
+        ]));
+
+    for label in &pivot.rows {
+        let count = counts.get(label).copied().unwrap_or(0);
+        let worked = pivot.row_total(label);
+        let per_hour = if worked > Duration::zero() {
+            format!("{:.1}", count as f64 * 60.0 / worked.num_minutes() as f64)
+        } else {
+            "-".to_string()
+        };
+        table.add_row(vec![
+            Cell::new(label),
+            Cell::new(count),
+            Cell::new(format_duration(worked)),
+            Cell::new(per_hour),
+        ]);
+    }
+    println!("{table}");
+
+    // Reasons given more than once are worth a closer look
+    let mut reasons: HashMap<&str, usize> = HashMap::new();
+    for reason in interruptions.iter().filter_map(|i| i.reason.as_deref()) {
+        *reasons.entry(reason).or_default() += 1;
+    }
+    let mut reasons: Vec<(&str, usize)> = reasons.into_iter().collect();
+    reasons.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (reason, count) in reasons {
+        println!("  {:>3}  {}", count, reason);
+    }
+    Ok(())
+}
+
+// Checks in when off the clock and out when on it, so one hotkey can drive tracking
+pub fn handle_toggle(keeper: &TimeKeeper, project: Option<String>) -> Result<(), TimeKeeperError> {
+    let now = get_now();
+    match keeper.open_session()? {
+        Some(_) => handle_check_out(keeper, &now, None, false, None),
+        None => handle_check_in(keeper, &now, None, false, None, false, project),
+    }
+}
+
+// Overlapping entries would count the same time twice. With --force the
+// entry is saved anyway after a warning.
+fn check_overlaps(
+    db: &Db,
+    check_in: NaiveDateTime,
+    check_out: NaiveDateTime,
+    id: Option<i32>,
+    force: bool,
+) -> Result<(), TimeKeeperError> {
+    // Open sessions run until now
+    let now = Local::now().naive_local();
+    let overlaps: Vec<Record> = db
+        .get_entries_overlapping(check_in, check_out)?
+        .into_iter()
+        .filter(|r| Some(r.id) != id)
+        .filter(|r| r.check_in < check_out && check_in < r.check_out.unwrap_or(now))
+        .collect();
+    if overlaps.is_empty() {
+        return Ok(());
+    }
+
+    let described = overlaps
+        .iter()
+        .map(|r| {
+            format!(
+                "{} ({} {}-{})",
+                r.id,
+                r.date().format("%Y-%m-%d"),
+                r.check_in.format("%H:%M"),
+                format_check_out(r)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let noun = if overlaps.len() == 1 {
+        "entry"
+    } else {
+        "entries"
+    };
+    if !force {
+        return Err(TimeKeeperError::ParseError(format!(
+            "Overlaps {} {}, use --force to save it anyway",
+            noun, described
+        )));
+    }
+    println!(
+        "Warning: overlaps {} {}, the shared time is counted twice",
+        noun, described
+    );
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn handle_record(
+    keeper: &TimeKeeper,
     check_in_str: &str,
     check_out_str: &str,
     date_str: Option<String>,
+    location: Option<String>,
+    travel: bool,
+    project: Option<String>,
+    overnight: bool,
+    force: bool,
 ) -> Result<(), TimeKeeperError> {
-    let check_in = parse_time_str(check_in_str)?;
-    let check_out = parse_time_str(check_out_str)?;
+    let date = date_str
+        .map(|date_str| parse_date_str(&date_str))
+        .transpose()?;
+
+    let check_in = parse_datetime_str(check_in_str, date, Local::now().date_naive())?;
+    let check_out = overnight_check_out(
+        check_in,
+        parse_datetime_str(check_out_str, date, check_in.date())?,
+        overnight,
+    );
 
     if check_out <= check_in {
         return Err(TimeKeeperError::CheckOutBeforeCheckIn);
     }
-
-    let date = match date_str {
-        Some(date_str) => parse_date_str(&date_str)?,
-        None => Local::now().date_naive(),
-    };
-
     let record = Record {
         id: 0,
         check_in,
-        check_out,
-        date,
+        check_out: Some(check_out),
+        location: location.or(Config::load()?.default_location),
+        travel,
+        project: project.or(Marker::from_current_dir()?.and_then(|marker| marker.project)),
+        note: None,
+        unpaid_break: Duration::zero(),
     };
 
-    save_entry(&record)?;
+    // Checked in the same transaction as the save, so nothing written in
+    // between can slip past the check
+    keeper.db().transaction(|db| {
+        check_overlaps(db, check_in, check_out, None, force)?;
+        keeper.add(&record)
+    })?;
 
-    let duration = check_out.signed_duration_since(check_in).num_minutes();
-    println!("Saved record for {}:", date.format("%Y-%m-%d"));
+    let duration = record.duration().num_minutes();
+    println!("Saved record for {}:", check_in.format("%Y-%m-%d"));
     println!("  Check-in:  {}", check_in.format("%H:%M"));
-    println!("  Check-out: {}", check_out.format("%H:%M"));
+    println!("  Check-out: {}", format_check_out(&record));
+    if let Some(location) = &record.location {
+        println!("  Location:  {}", location);
+    }
+    if let Some(project) = &record.project {
+        println!("  Project:   {}", project);
+    }
+    if record.travel {
+        println!("  Travel:    yes");
+    }
     println!(
         "  Duration:  {} hours {} minutes",
         duration / 60,
@@ -119,104 +468,1578 @@ pub fn handle_record(
     Ok(())
 }
 
-pub fn display_summary() -> Result<(), TimeKeeperError> {
-    let mut records = get_all_entries()?;
+// Proposes an entry for a forgotten day from the median check-in and
+// check-out on the same weekday over the weeks before it, which can be
+// adjusted before it is saved
+pub fn handle_backfill(
+    keeper: &TimeKeeper,
+    date_str: &str,
+    weeks: u32,
+    project: Option<String>,
+) -> Result<(), TimeKeeperError> {
+    let date = parse_date_str(date_str)?;
+    if !keeper.db().get_entries_by_date(date)?.is_empty() {
+        return Err(TimeKeeperError::ParseError(format!(
+            "{} already has entries, use add or edit instead",
+            date.format("%Y-%m-%d")
+        )));
+    }
+
+    let from = date - Duration::weeks(weeks.into());
+    let mut records = keeper
+        .db()
+        .get_entries_between(from, date - Duration::days(1))?;
+    records.retain(|r| !r.travel && !r.is_open() && r.date().weekday() == date.weekday());
+    let days = summarize_days(&records);
+    let Some((check_in, check_out, overnight)) = typical_times(&days) else {
+        return Err(TimeKeeperError::ParseError(format!(
+            "No {} entries in the {} weeks before {} to base an entry on",
+            date.format("%A"),
+            weeks,
+            date.format("%Y-%m-%d")
+        )));
+    };
+
+    println!(
+        "Typical {} over the last {} weeks ({} days): {}-{}{}",
+        date.format("%A"),
+        weeks,
+        days.len(),
+        check_in.format("%H:%M"),
+        check_out.format("%H:%M"),
+        if overnight { " (+1)" } else { "" }
+    );
+    let valid_time = |value: &str| parse_time_str(value).map(|_| ());
+    let check_in = input(
+        "Check-in",
+        Some(check_in.format("%H:%M").to_string()),
+        valid_time,
+    )?;
+    let check_out = input(
+        "Check-out",
+        Some(check_out.format("%H:%M").to_string()),
+        valid_time,
+    )?;
+    let prompt = format!(
+        "Save {}-{} on {}?",
+        check_in,
+        check_out,
+        date.format("%Y-%m-%d")
+    );
+    if !confirm(&prompt, true)? {
+        println!("Nothing saved");
+        return Ok(());
+    }
+    // The entry is saved on the date being backfilled, so a time like
+    // now-15m only gives the time of day
+    let check_in = parse_time_str(&check_in)?.format("%H:%M").to_string();
+    let check_out = parse_time_str(&check_out)?.format("%H:%M").to_string();
+
+    // A check-out not after the check-in ends on the next day, as it does
+    // for a typical night shift
+    let overnight = parse_time_str(&check_out)? <= parse_time_str(&check_in)?;
+    handle_record(
+        keeper,
+        &check_in,
+        &check_out,
+        Some(date_str.to_string()),
+        None,
+        false,
+        project,
+        overnight,
+        false,
+    )
+}
+
+// Records within the period, or all records when no period is given
+pub(crate) fn load_records(
+    db: &Db,
+    period: Option<Period>,
+) -> Result<Vec<Record>, TimeKeeperError> {
+    Ok(match period {
+        Some(period) => db.get_entries_between(period.from, period.to)?,
+        None => db.get_all_entries()?,
+    })
+}
+
+// The rules from the config that reports count entries by, None with --raw
+// or when there are none
+pub(crate) fn load_policy(raw: bool) -> Result<Option<Policy>, TimeKeeperError> {
+    if raw {
+        return Ok(None);
+    }
+    let policy = Policy::from_config(&Config::load()?.rules())?;
+    Ok(Some(policy).filter(|policy| !policy.is_empty()))
+}
+
+fn load_project_colors() -> Result<ProjectColors, TimeKeeperError> {
+    ProjectColors::from_config(&Config::load()?.colors).map_err(TimeKeeperError::ParseError)
+}
+
+// The daily target from --target or the config, and the weekly one
+fn load_targets(daily: Option<String>) -> Result<Targets, TimeKeeperError> {
+    let config = Config::load()?;
+    let parse = |target: Option<String>| target.as_deref().map(parse_duration_str).transpose();
+    Ok(Targets {
+        daily: parse(daily.or(config.daily_target))?,
+        weekly: parse(config.weekly_target)?,
+    })
+}
+
+// Overtime balance across every day up to and including `through`, starting
+// from balance_from in the config
+fn load_balance(
+    db: &Db,
+    targets: &Targets,
+    through: NaiveDate,
+) -> Result<Duration, TimeKeeperError> {
+    let from = match Config::load()?.balance_from {
+        Some(from) => NaiveDate::parse_from_str(&from, "%Y-%m-%d").map_err(|_| {
+            TimeKeeperError::ParseError(format!("Invalid balance_from '{}', use YYYY-MM-DD", from))
+        })?,
+        None => NaiveDate::from_ymd_opt(1970, 1, 1).unwrap(),
+    };
+    Ok(targets.balance(&db.get_daily_totals(from, through)?))
+}
+
+// Records as reports count them, through the configured rules
+pub(crate) fn load_counted_records(
+    db: &Db,
+    period: Option<Period>,
+    raw: bool,
+) -> Result<Vec<Record>, TimeKeeperError> {
+    let records = load_records(db, period)?;
+    Ok(match load_policy(raw)? {
+        Some(policy) => policy.count_records(&records),
+        None => records,
+    })
+}
+
+// Below these terminal widths the summary first drops note rows, then
+// folds check-in and check-out into a single range column: 72 and 56
+// columns for the table itself, plus what the ID column takes
+const SUMMARY_ID_WIDTH: u16 = 6;
+const SUMMARY_NOTES_WIDTH: u16 = 72 + SUMMARY_ID_WIDTH;
+const SUMMARY_RANGE_WIDTH: u16 = 56 + SUMMARY_ID_WIDTH;
+
+struct SummaryLayout {
+    notes: bool,
+    range: bool,
+}
+
+impl SummaryLayout {
+    // Output that is not a terminal has no width and always gets every column
+    fn for_width(width: Option<u16>, wide: bool) -> SummaryLayout {
+        let width = width.filter(|_| !wide).unwrap_or(u16::MAX);
+        SummaryLayout {
+            notes: width >= SUMMARY_NOTES_WIDTH,
+            range: width < SUMMARY_RANGE_WIDTH,
+        }
+    }
+
+    fn header(&self) -> Row {
+        let mut header = vec![
+            Cell::new("ID").fg(Color::Cyan),
+            Cell::new("Date").fg(Color::Cyan),
+        ];
+        if self.range {
+            header.push(Cell::new("Time").fg(Color::Green));
+        } else {
+            header.push(Cell::new("Check-in").fg(Color::Green));
+            header.push(Cell::new("Check-out").fg(Color::Red));
+        }
+        header.push(Cell::new("Duration").fg(Color::Yellow));
+        header.push(Cell::new("Break").fg(Color::Yellow));
+        Row::from(header)
+    }
+
+    // Takes the six full-width columns and lays them out for the terminal
+    fn row(&self, color: Option<Color>, columns: [String; 6]) -> Row {
+        let [id, date, check_in, check_out, duration, break_time] = columns;
+        let mut cells = vec![id, date];
+        if self.range {
+            cells.push(match (check_in.is_empty(), check_out.is_empty()) {
+                (false, false) => format!("{}-{}", check_in, check_out),
+                _ => check_in + &check_out,
+            });
+        } else {
+            cells.push(check_in);
+            cells.push(check_out);
+        }
+        cells.push(duration);
+        cells.push(break_time);
+
+        Row::from(cells.into_iter().map(|cell| match color {
+            Some(color) => Cell::new(cell).fg(color),
+            None => Cell::new(cell),
+        }))
+    }
+}
+
+pub fn display_summary(
+    db: &Db,
+    period: &PeriodArgs,
+    merge_gap: Option<String>,
+    project: Option<String>,
+    wide: bool,
+    json: bool,
+) -> Result<(), TimeKeeperError> {
+    let mut records = load_records(db, period.resolve(get_today())?)?;
+    if let Some(project) = project {
+        records.retain(|r| r.project.as_ref() == Some(&project));
+    }
+    if let Some(gap) = merge_gap {
+        records = merge_gaps(&records, parse_duration_str(&gap)?);
+    }
+
+    if json {
+        records.sort_by_key(|r| r.check_in);
+        let days = summarize_days(&records);
+        let total = |f: fn(&DaySummary) -> Duration| {
+            days.iter()
+                .fold(Duration::zero(), |acc, day| acc + f(day))
+                .num_minutes()
+        };
+        let summary = SummaryExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            worked_minutes: total(|day| day.worked),
+            travel_minutes: total(|day| day.travel),
+            records,
+            days,
+        };
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+        return Ok(());
+    }
 
     if records.is_empty() {
         println!("No records found");
         return Ok(());
     }
 
-    // Sort records in descending order by date and check-in time
-    records.sort_by(|a, b| b.date.cmp(&a.date).then(b.check_in.cmp(&a.check_in)));
+    // Sort records in descending order by check-in time
+    records.sort_by_key(|r| std::cmp::Reverse(r.check_in));
 
     let mut table = Table::new();
-    table
-        .set_content_arrangement(ContentArrangement::Dynamic)
-        .set_header(Row::from(vec![
-            Cell::new("Date").fg(Color::Cyan),
-            Cell::new("Check-in").fg(Color::Green),
-            Cell::new("Check-out").fg(Color::Red),
-            Cell::new("Duration").fg(Color::Yellow),
-        ]));
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    let layout = SummaryLayout::for_width(table.width(), wide);
+    table.set_header(layout.header());
 
+    let days = summarize_days(&records);
     let mut total_duration = Duration::zero();
-    let mut current_date: Option<NaiveDate> = None;
-    let mut date_duration = Duration::zero();
+    let mut total_travel = Duration::zero();
+    let targets = load_targets(None)?;
+    let colors = load_project_colors()?;
 
-    for record in &records {
-        let duration = record.check_out.signed_duration_since(record.check_in);
-        total_duration = total_duration + duration;
-
-        // If we're on a new date, add a subtotal for the previous date
-        if let Some(prev_date) = current_date {
-            if prev_date != record.date && date_duration.num_minutes() > 0 {
-                table.add_row(vec![
-                    Cell::new("Subtotal").fg(Color::Blue),
-                    Cell::new("").fg(Color::Blue),
-                    Cell::new("").fg(Color::Blue),
-                    Cell::new(format!(
-                        "{}h {}m",
-                        date_duration.num_minutes() / 60,
-                        date_duration.num_minutes() % 60
-                    ))
-                    .fg(Color::Blue),
-                ]);
-                table.add_row(vec!["", "", "", ""]); // Empty row as separator
-                date_duration = Duration::zero();
-            }
+    let notes = match (days.first(), days.last()) {
+        (Some(first), Some(last)) if layout.notes => {
+            db.get_notes_between(first.date, last.date, None)?
         }
+        _ => Vec::new(),
+    };
+    let blank = || layout.row(None, Default::default());
 
-        date_duration = date_duration + duration;
-        current_date = Some(record.date);
-
-        let hours = duration.num_minutes() / 60;
-        let minutes = duration.num_minutes() % 60;
-        let duration_str = format!("{}h {}m", hours, minutes);
+    for (index, day) in days.iter().rev().enumerate() {
+        if index > 0 {
+            table.add_row(blank()); // Empty row as separator
+        }
 
-        table.add_row(vec![
-            record.date.format("%Y-%m-%d").to_string(),
-            record.check_in.format("%H:%M").to_string(),
-            record.check_out.format("%H:%M").to_string(),
-            duration_str,
-        ]);
-    }
+        for record in records.iter().filter(|r| r.date() == day.date) {
+            table.add_row(
+                layout.row(
+                    record
+                        .project
+                        .as_deref()
+                        .map(|project| colors.color(project)),
+                    [
+                        record.id.to_string(),
+                        record.date().format("%Y-%m-%d").to_string(),
+                        record.check_in.format("%H:%M").to_string(),
+                        format_check_out(record),
+                        if record.travel {
+                            format!("{} travel", format_duration(record.duration()))
+                        } else if record.unpaid_break > Duration::zero() {
+                            format!(
+                                "{} net of {} break",
+                                format_duration(record.duration()),
+                                format_duration(record.unpaid_break)
+                            )
+                        } else {
+                            format_duration(record.duration())
+                        },
+                        String::new(),
+                    ],
+                ),
+            );
+        }
 
-    // Add final date subtotal if there are records
-    if let Some(_) = current_date {
-        if date_duration.num_minutes() > 0 {
-            table.add_row(vec![
-                Cell::new("Subtotal").fg(Color::Blue),
-                Cell::new("").fg(Color::Blue),
-                Cell::new("").fg(Color::Blue),
-                Cell::new(format!(
-                    "{}h {}m",
-                    date_duration.num_minutes() / 60,
-                    date_duration.num_minutes() % 60
-                ))
-                .fg(Color::Blue),
-            ]);
+        // Subtotal spans the day from first check-in to last check-out
+        if day.worked.num_minutes() > 0 {
+            table.add_row(layout.row(
+                Some(Color::Blue),
+                [
+                    String::new(),
+                    "Subtotal".to_string(),
+                    day.first_check_in.format("%H:%M").to_string(),
+                    day.last_check_out.format("%H:%M").to_string(),
+                    match targets.daily {
+                        Some(target) => format!(
+                            "{} ({})",
+                            format_duration(day.worked),
+                            format_delta(day.worked - target)
+                        ),
+                        None => format_duration(day.worked),
+                    },
+                    format_duration(day.break_time()),
+                ],
+            ));
+        }
+        if day.travel.num_minutes() > 0 {
+            table.add_row(layout.row(
+                Some(Color::Blue),
+                [
+                    String::new(),
+                    "Travel".to_string(),
+                    String::new(),
+                    String::new(),
+                    format_duration(day.travel),
+                    String::new(),
+                ],
+            ));
         }
+        for note in notes.iter().filter(|n| n.date == day.date) {
+            table.add_row(layout.row(
+                None,
+                [
+                    String::new(),
+                    "Note".to_string(),
+                    note.text.clone(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                ],
+            ));
+        }
+        total_duration += day.worked;
+        total_travel += day.travel;
     }
 
     // Add grand total if there are multiple records
     if records.len() > 1 {
-        table.add_row(vec!["", "", "", ""]); // Empty row as separator
+        table.add_row(blank()); // Empty row as separator
+        table.add_row(layout.row(
+            Some(Color::Magenta),
+            [
+                String::new(),
+                "Total".to_string(),
+                String::new(),
+                String::new(),
+                format_duration(total_duration),
+                String::new(),
+            ],
+        ));
+        if total_travel.num_minutes() > 0 {
+            table.add_row(layout.row(
+                Some(Color::Magenta),
+                [
+                    String::new(),
+                    "Travel total".to_string(),
+                    String::new(),
+                    String::new(),
+                    format_duration(total_travel),
+                    String::new(),
+                ],
+            ));
+        }
+    }
+
+    // The running balance of every day up to the last one shown
+    if let (true, Some(last)) = (targets.is_set(), days.last()) {
+        table.add_row(layout.row(
+            Some(Color::Magenta),
+            [
+                String::new(),
+                "Balance".to_string(),
+                String::new(),
+                String::new(),
+                format_delta(load_balance(db, &targets, last.date)?),
+                String::new(),
+            ],
+        ));
+    }
+
+    // Worked time per project, only once entries are being tagged
+    let worked: Vec<Record> = records.iter().filter(|r| !r.travel).cloned().collect();
+    if worked.iter().any(|r| r.project.is_some()) {
+        let pivot = Pivot::new(&worked, Dimension::Project, None);
+        table.add_row(blank()); // Empty row as separator
+        for project in &pivot.rows {
+            // Entries without a project are grouped under "unset"
+            let color = match project.as_str() {
+                "unset" => Color::Magenta,
+                project => colors.color(project),
+            };
+            table.add_row(layout.row(
+                Some(color),
+                [
+                    String::new(),
+                    project.clone(),
+                    String::new(),
+                    String::new(),
+                    format_duration(pivot.row_total(project)),
+                    String::new(),
+                ],
+            ));
+        }
+    }
+
+    println!("All Records:");
+    println!("{table}");
+
+    Ok(())
+}
+
+// One line per day with its span, worked time and a bar
+pub fn display_compact_summary(
+    db: &Db,
+    period: &PeriodArgs,
+    merge_gap: Option<String>,
+    project: Option<String>,
+) -> Result<(), TimeKeeperError> {
+    let mut records = load_records(db, period.resolve(get_today())?)?;
+    if let Some(project) = project {
+        records.retain(|r| r.project.as_ref() == Some(&project));
+    }
+    if let Some(gap) = merge_gap {
+        records = merge_gaps(&records, parse_duration_str(&gap)?);
+    }
+
+    if records.is_empty() {
+        println!("No records found");
+        return Ok(());
+    }
+
+    for day in summarize_days(&records).iter().rev() {
+        println!(
+            "{}  {}\u{2013}{}  {:>7}  {}",
+            day.date.format("%Y-%m-%d"),
+            day.first_check_in.format("%H:%M"),
+            day.last_check_out.format("%H:%M"),
+            format_duration(day.worked),
+            duration_bar(day.worked)
+        );
+    }
+
+    Ok(())
+}
+
+// One day's entries, as the summary table or as a printable receipt
+pub fn display_day(db: &Db, date: Option<String>, receipt: bool) -> Result<(), TimeKeeperError> {
+    let today = get_today();
+    let date = match date {
+        Some(date) => match parse_period(&date, today)? {
+            period if period.from == period.to => period.from,
+            _ => {
+                return Err(TimeKeeperError::ParseError(format!(
+                    "'{}' covers more than one day",
+                    date
+                )))
+            }
+        },
+        None => today,
+    };
+
+    if receipt {
+        print!("{}", day_receipt(date, &db.get_entries_by_date(date)?));
+        return Ok(());
+    }
+
+    let day = date.format("%Y-%m-%d").to_string();
+    let period = PeriodArgs {
+        from: Some(day.clone()),
+        to: Some(day),
+        ..PeriodArgs::default()
+    };
+    display_summary(db, &period, None, None, false, false)
+}
+
+// Reads the cached daily totals, so it stays fast however many records exist
+pub fn display_week(db: &Db) -> Result<(), TimeKeeperError> {
+    let week = Period::week_of(get_today());
+    let days = db.get_daily_totals(week.from, week.to)?;
+
+    if days.is_empty() {
+        println!("No records found for this week");
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Row::from(vec![
+            Cell::new("Day").fg(Color::Cyan),
+            Cell::new("First in").fg(Color::Green),
+            Cell::new("Last out").fg(Color::Red),
+            Cell::new("Worked").fg(Color::Yellow),
+        ]));
+
+    let mut total = Duration::zero();
+    for day in &days {
+        total += day.worked;
         table.add_row(vec![
-            Cell::new("Total").fg(Color::Magenta),
-            Cell::new("").fg(Color::Magenta),
-            Cell::new("").fg(Color::Magenta),
-            Cell::new(format!(
-                "{}h {}m",
-                total_duration.num_minutes() / 60,
-                total_duration.num_minutes() % 60
-            ))
-            .fg(Color::Magenta),
+            day.date.format("%a %Y-%m-%d").to_string(),
+            day.first_check_in.format("%H:%M").to_string(),
+            day.last_check_out.format("%H:%M").to_string(),
+            format_duration(day.worked),
         ]);
     }
+    table.add_row(vec![
+        Cell::new("Total").fg(Color::Magenta),
+        Cell::new(""),
+        Cell::new(""),
+        Cell::new(format_duration(total)).fg(Color::Magenta),
+    ]);
 
-    println!("All Records:");
+    println!("Week {}:", week.from.format("%G-W%V"));
     println!("{table}");
 
     Ok(())
 }
+
+// Per-day totals for a period, compared with the daily target from --target
+// or the config. Totals with no rules to count by come straight from the daily_total cache.
+pub fn handle_period_report(
+    db: &Db,
+    period: Period,
+    target: Option<String>,
+    raw: bool,
+    json: bool,
+    format: Option<MessageFormat>,
+) -> Result<(), TimeKeeperError> {
+    let days = match load_policy(raw)? {
+        Some(_) => summarize_days(&load_counted_records(db, Some(period), raw)?),
+        None => db.get_daily_totals(period.from, period.to)?,
+    };
+    let targets = load_targets(target)?;
+    let balance = if targets.is_set() {
+        Some(load_balance(db, &targets, period.to)?)
+    } else {
+        None
+    };
+
+    let title = format!("Report for {}", period);
+    match format {
+        Some(MessageFormat::Slack) => {
+            println!("{:#}", slack_message(&title, &days, &targets, balance));
+            return Ok(());
+        }
+        Some(MessageFormat::Teams) => {
+            println!("{:#}", teams_message(&title, &days, &targets, balance));
+            return Ok(());
+        }
+        None => {}
+    }
+    if json {
+        let mut report = period_report_json(&days, &targets);
+        report["schema_version"] = json!(EXPORT_SCHEMA_VERSION);
+        report["from"] = json!(period.from);
+        report["to"] = json!(period.to);
+        report["balance_minutes"] = json!(balance.map(|balance| balance.num_minutes()));
+        println!("{:#}", report);
+        return Ok(());
+    }
+    if days.is_empty() {
+        println!("No records found for {}", period);
+        return Ok(());
+    }
+
+    println!("{}:", title);
+    println!("{}", period_report_table(&days, &targets));
+    if let Some(balance) = balance {
+        println!("Overtime balance: {}", format_delta(balance));
+    }
+    Ok(())
+}
+
+pub fn handle_report(
+    db: &Db,
+    by: &[Dimension],
+    csv: bool,
+    merge_gap: Option<String>,
+    period: &PeriodArgs,
+    raw: bool,
+    json: bool,
+) -> Result<(), TimeKeeperError> {
+    let (rows, columns) = match by {
+        [rows] => (*rows, None),
+        [rows, columns] if rows != columns => (*rows, Some(*columns)),
+        [_, _] => {
+            return Err(TimeKeeperError::ParseError(
+                "Report dimensions must differ".to_string(),
+            ))
+        }
+        _ => {
+            return Err(TimeKeeperError::ParseError(
+                "Use one or two --by dimensions".to_string(),
+            ))
+        }
+    };
+
+    let mut records = load_records(db, period.resolve(get_today())?)?;
+    if records.is_empty() && !json {
+        println!("No records found");
+        return Ok(());
+    }
+    if let Some(gap) = merge_gap {
+        records = merge_gaps(&records, parse_duration_str(&gap)?);
+    }
+    // Merged entries are counted as one
+    if let Some(policy) = load_policy(raw)? {
+        records = policy.count_records(&records);
+    }
+
+    // Travel stays out of the hours unless the report groups by kind
+    let mut travel = Duration::zero();
+    if !by.contains(&Dimension::Kind) {
+        travel = records
+            .iter()
+            .filter(|r| r.travel)
+            .fold(Duration::zero(), |acc, r| acc + r.duration());
+        records.retain(|r| !r.travel);
+    }
+
+    let mut pivot = Pivot::new(&records, rows, columns).with_colors(load_project_colors()?);
+    let rates = Config::load()?.rates;
+    if !rates.is_empty() {
+        pivot = pivot.with_earnings(&records, &rates);
+    }
+    if json {
+        let mut report = pivot.to_json();
+        report["schema_version"] = json!(EXPORT_SCHEMA_VERSION);
+        report["travel_minutes"] = json!(travel.num_minutes());
+        println!("{:#}", report);
+    } else if csv {
+        println!("{}", pivot.to_csv());
+    } else {
+        println!("{}", pivot.to_table());
+        if travel > Duration::zero() {
+            println!("Travel (not included): {}", format_duration(travel));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn handle_breakdown(
+    db: &Db,
+    period: &PeriodArgs,
+    by: Dimension,
+    raw: bool,
+) -> Result<(), TimeKeeperError> {
+    let today = get_today();
+    let period = period
+        .resolve(today)?
+        .unwrap_or_else(|| Period::month_of(today));
+
+    let records = load_counted_records(db, Some(period), raw)?;
+    if records.is_empty() {
+        println!("No records found for {}", period);
+        return Ok(());
+    }
+
+    let pivot = Pivot::new(&records, by, None).with_colors(load_project_colors()?);
+    println!("Breakdown for {}:", period);
+    println!("{}", breakdown_table(&pivot));
+    println!("Total: {}", format_duration(pivot.total()));
+
+    Ok(())
+}
+
+// Effective hourly earnings per project, across all entries unless a
+// period is given since fixed bids usually span the whole engagement
+pub fn handle_profitability(
+    db: &Db,
+    period: &PeriodArgs,
+    raw: bool,
+) -> Result<(), TimeKeeperError> {
+    let config = Config::load()?;
+    if config.rates.is_empty() && config.budgets.is_empty() {
+        return Err(TimeKeeperError::ParseError(
+            "No rates or budgets set, add them under [rates] or [budgets] in the config"
+                .to_string(),
+        ));
+    }
+    let records = load_counted_records(db, period.resolve(get_today())?, raw)?;
+    let projects = profitability(&records, &config.rates, &config.budgets);
+    if projects.is_empty() {
+        println!("No entries booked on a project");
+        return Ok(());
+    }
+
+    let colors = load_project_colors()?;
+    let money = |amount: Option<f64>| amount.map(format_money).unwrap_or_else(|| "-".to_string());
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Row::from(vec![
+            Cell::new("Project").fg(Color::Cyan),
+            Cell::new("Hours").fg(Color::Yellow),
+            Cell::new("Rate"),
+            Cell::new("Budget"),
+            Cell::new("Earned").fg(Color::Green),
+            Cell::new("Per hour").fg(Color::Green),
+        ]));
+    for project in &projects {
+        table.add_row(vec![
+            Cell::new(&project.project).fg(colors.color(&project.project)),
+            Cell::new(format_hours(project.hours)),
+            Cell::new(money(config.rates.get(&project.project).copied())),
+            Cell::new(money(config.budgets.get(&project.project).copied())),
+            Cell::new(money(project.earned)),
+            Cell::new(money(project.per_hour())),
+        ]);
+    }
+    println!("{table}");
+    Ok(())
+}
+
+pub fn handle_preview_rounding(
+    db: &Db,
+    round: &str,
+    mode: RoundingMode,
+    period: &PeriodArgs,
+) -> Result<(), TimeKeeperError> {
+    let increment = parse_duration_str(round)?;
+    let today = get_today();
+    let period = period
+        .resolve(today)?
+        .unwrap_or_else(|| Period::month_of(today));
+
+    let mut records = load_records(db, Some(period))?;
+    if records.is_empty() {
+        println!("No records found for {}", period);
+        return Ok(());
+    }
+    records.sort_by_key(|r| r.check_in);
+
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Row::from(vec![
+            Cell::new("Date").fg(Color::Cyan),
+            Cell::new("Check-in").fg(Color::Green),
+            Cell::new("Check-out").fg(Color::Red),
+            Cell::new("Raw").fg(Color::Yellow),
+            Cell::new("Rounded").fg(Color::Yellow),
+            Cell::new("Delta").fg(Color::Blue),
+        ]));
+
+    let mut raw_total = Duration::zero();
+    let mut rounded_total = Duration::zero();
+
+    for record in &records {
+        let raw = record.duration();
+        let rounded = round_duration(raw, increment, mode);
+        raw_total += raw;
+        rounded_total += rounded;
+
+        table.add_row(vec![
+            Cell::new(record.date().format("%Y-%m-%d")),
+            Cell::new(record.check_in.format("%H:%M")),
+            Cell::new(format_check_out(record)),
+            Cell::new(format_duration(raw)),
+            Cell::new(format_duration(rounded)),
+            Cell::new(format_delta(rounded - raw)),
+        ]);
+    }
+
+    table.add_row(vec![
+        Cell::new("Total").fg(Color::Magenta),
+        Cell::new("").fg(Color::Magenta),
+        Cell::new("").fg(Color::Magenta),
+        Cell::new(format_duration(raw_total)).fg(Color::Magenta),
+        Cell::new(format_duration(rounded_total)).fg(Color::Magenta),
+        Cell::new(format_delta(rounded_total - raw_total)).fg(Color::Magenta),
+    ]);
+
+    println!("Rounding preview for {}:", period);
+    println!("{table}");
+
+    Ok(())
+}
+
+// Walks through how a day would be counted, without saving anything
+#[allow(clippy::too_many_arguments)]
+pub fn handle_simulate(
+    db: &Db,
+    check_in_str: &str,
+    check_out_str: &str,
+    breaks: Option<String>,
+    round: Option<String>,
+    mode: RoundingMode,
+    target: Option<String>,
+    overnight: bool,
+) -> Result<(), TimeKeeperError> {
+    let day = get_today();
+    let check_in = parse_datetime_str(check_in_str, None, day)?;
+    let check_out = overnight_check_out(
+        check_in,
+        parse_datetime_str(check_out_str, None, day)?,
+        overnight,
+    );
+    if check_out <= check_in {
+        return Err(TimeKeeperError::CheckOutBeforeCheckIn);
+    }
+
+    let presence = check_out - check_in;
+    let breaks = match breaks {
+        Some(breaks) => parse_duration_str(&breaks)?,
+        None => Duration::zero(),
+    };
+    if breaks > presence {
+        return Err(TimeKeeperError::ParseError(
+            "Breaks are longer than the day itself".to_string(),
+        ));
+    }
+
+    println!("Simulated day, nothing is saved:");
+    println!("  Check-in:  {}", check_in.format("%H:%M"));
+    println!("  Check-out: {}", check_out.format("%H:%M"));
+    println!("  Presence:  {}", format_duration(presence));
+    println!("  Breaks:    {}", format_duration(breaks));
+    let mut simulated = Day::new(check_in, check_out, breaks);
+    println!("  Worked:    {}", format_duration(simulated.worked));
+
+    // The configured rules run first, --round is applied on top of them
+    let config = Config::load()?;
+    let mut policy = Policy::from_config(&config.rules())?;
+    if let Some(round) = round {
+        policy.push(Box::new(Round {
+            increment: parse_duration_str(&round)?,
+            mode,
+        }));
+    }
+    simulated.previous_check_out = db
+        .get_daily_totals(day - Duration::days(1), day - Duration::days(1))?
+        .first()
+        .map(|previous| previous.last_check_out);
+
+    let applied = policy.apply(&mut simulated);
+    for (rule, note) in &applied {
+        println!("    {:<9}{}", rule, note);
+    }
+    if !applied.is_empty() {
+        println!("  Counted:   {}", format_duration(simulated.worked));
+    }
+    let worked = simulated.worked;
+
+    if let Some(target) = target.or(config.daily_target) {
+        let target = parse_duration_str(&target)?;
+        println!("  Target:    {}", format_duration(target));
+        println!("  Overtime:  {}", format_delta(worked - target));
+    }
+
+    Ok(())
+}
+
+pub fn handle_log(
+    db: &Db,
+    limit: Option<usize>,
+    oneline: bool,
+    stat: bool,
+    period: &PeriodArgs,
+) -> Result<(), TimeKeeperError> {
+    let mut records = load_records(db, period.resolve(get_today())?)?;
+    if records.is_empty() {
+        println!("No records found");
+        return Ok(());
+    }
+
+    records.sort_by_key(|r| std::cmp::Reverse(r.check_in));
+    records.truncate(limit.unwrap_or(records.len()));
+
+    let today = get_today();
+    let longest = records
+        .iter()
+        .map(|r| r.duration().num_minutes())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    for record in &records {
+        let relative = format_relative_date(record.date(), today);
+        if oneline {
+            println!(
+                "{} {} {}",
+                record.id,
+                format_duration(record.duration()),
+                relative
+            );
+            continue;
+        }
+
+        let mut line = format!(
+            "{:>5}  {}  {}-{}  {:>7}  ({})",
+            record.id,
+            record.date().format("%a %Y-%m-%d"),
+            record.check_in.format("%H:%M"),
+            format_check_out(record),
+            format_duration(record.duration()),
+            relative
+        );
+        if let Some(location) = &record.location {
+            line = format!("{}  @{}", line, location);
+        }
+        if let Some(project) = &record.project {
+            line = format!("{}  +{}", line, project);
+        }
+        if record.travel {
+            line = format!("{}  [travel]", line);
+        }
+        if let Some(note) = &record.note {
+            line = format!("{}  \"{}\"", line, note);
+        }
+        // Like git's --stat, a bar scaled to the longest entry shown
+        if stat {
+            let width = record.duration().num_minutes() * 30 / longest;
+            line = format!("{}  {}", line, "+".repeat(width as usize));
+        }
+        println!("{}", line.trim_end());
+    }
+
+    if stat {
+        let total = records
+            .iter()
+            .fold(Duration::zero(), |acc, r| acc + r.duration());
+        let days = summarize_days(&records).len();
+        println!(
+            "{} entries, {} days, {} total",
+            records.len(),
+            days,
+            format_duration(total)
+        );
+    }
+
+    Ok(())
+}
+
+pub fn display_paths() -> Result<(), TimeKeeperError> {
+    let paths = Paths::new()?;
+
+    if let Some(marker) = Marker::from_current_dir()? {
+        println!("Marker:   {}", marker.path.display());
+    }
+    println!("Database: {}", get_db_path()?.display());
+    println!("Data:     {}", paths.data.display());
+    println!("Config:   {}", paths.config.display());
+    println!("State:    {}", paths.state.display());
+    println!("Cache:    {}", paths.cache.display());
+    println!("Logs:     {}", paths.logs.display());
+
+    Ok(())
+}
+
+// Counts the days worked at each location, a day with entries at several
+// locations counts once for each of them
+pub fn handle_locations(db: &Db, period: &PeriodArgs) -> Result<(), TimeKeeperError> {
+    let records = load_records(db, period.resolve(get_today())?)?;
+    if records.is_empty() {
+        println!("No records found");
+        return Ok(());
+    }
+
+    let pivot = Pivot::new(&records, Dimension::Location, Some(Dimension::Day));
+
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Row::from(vec![
+            Cell::new("Location").fg(Color::Cyan),
+            Cell::new("Days").fg(Color::Green),
+            Cell::new("Duration").fg(Color::Yellow),
+        ]));
+
+    for location in &pivot.rows {
+        let days = pivot
+            .columns
+            .iter()
+            .filter(|day| pivot.cell(location, day) > Duration::zero())
+            .count();
+        table.add_row(vec![
+            location.clone(),
+            days.to_string(),
+            format_duration(pivot.row_total(location)),
+        ]);
+    }
+
+    println!("{table}");
+    Ok(())
+}
+
+pub fn handle_note(db: &Db, text: &str, date: Option<String>) -> Result<(), TimeKeeperError> {
+    let date = match date {
+        Some(date_str) => parse_date_str(&date_str)?,
+        None => get_today(),
+    };
+    if text.trim().is_empty() {
+        return Err(TimeKeeperError::ParseError(
+            "Note text is empty".to_string(),
+        ));
+    }
+
+    db.save_note(&Note {
+        id: 0,
+        date,
+        text: text.trim().to_string(),
+    })?;
+
+    println!("Saved note for {}", date.format("%Y-%m-%d"));
+    Ok(())
+}
+
+// Without a period every note is searched
+pub fn display_notes(
+    db: &Db,
+    period: &PeriodArgs,
+    search: Option<String>,
+) -> Result<(), TimeKeeperError> {
+    let period = period.resolve(get_today())?.unwrap_or(Period {
+        from: NaiveDate::from_ymd_opt(1, 1, 1).unwrap(),
+        to: NaiveDate::from_ymd_opt(9999, 12, 31).unwrap(),
+    });
+    let notes = db.get_notes_between(period.from, period.to, search.as_deref())?;
+
+    if notes.is_empty() {
+        println!("No notes found");
+        return Ok(());
+    }
+
+    for note in &notes {
+        println!("{}  {}", note.date.format("%a %Y-%m-%d"), note.text);
+    }
+    Ok(())
+}
+
+// Markdown digest of the period's work and notes, this week by default
+pub fn display_retro(db: &Db, period: &PeriodArgs) -> Result<(), TimeKeeperError> {
+    let today = get_today();
+    let period = period
+        .resolve(today)?
+        .unwrap_or_else(|| Period::week_of(today));
+
+    let records = db.get_entries_between(period.from, period.to)?;
+    let notes = db.get_notes_between(period.from, period.to, None)?;
+    print!("{}", retro_markdown(&period.to_string(), &records, &notes));
+    Ok(())
+}
+
+pub fn display_usage(db: &Db) -> Result<(), TimeKeeperError> {
+    let usage = db.get_usage()?;
+    if usage.is_empty() {
+        println!("No usage recorded yet");
+        return Ok(());
+    }
+
+    let today = get_today();
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Row::from(vec![
+            Cell::new("Command").fg(Color::Cyan),
+            Cell::new("Count").fg(Color::Green),
+            Cell::new("Last used").fg(Color::Yellow),
+        ]));
+
+    for (command, count, last_used) in &usage {
+        table.add_row(vec![
+            command.clone(),
+            count.to_string(),
+            format_relative_date(last_used.date(), today),
+        ]);
+    }
+
+    println!("{table}");
+    Ok(())
+}
+
+pub fn handle_plan(
+    db: &Db,
+    date_str: &str,
+    range: &str,
+    label: &str,
+) -> Result<(), TimeKeeperError> {
+    let date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+        .map_err(|_| TimeKeeperError::ParseError("Invalid date format".to_string()))?;
+    let (start, end) = parse_time_range(range)?;
+
+    let block = PlanBlock {
+        id: 0,
+        start: date.and_time(start),
+        end: date.and_time(end),
+        label: label.to_string(),
+    };
+    db.save_plan_block(&block)?;
+
+    println!(
+        "Planned {} on {} {}-{}",
+        block.label,
+        date.format("%Y-%m-%d"),
+        start.format("%H:%M"),
+        end.format("%H:%M")
+    );
+    Ok(())
+}
+
+// Planned blocks next to the worked time inside them, defaults to this week.
+// Adherence is the share of planned time that was actually worked.
+pub fn display_plan(db: &Db, period: &PeriodArgs) -> Result<(), TimeKeeperError> {
+    let period = period
+        .resolve(get_today())?
+        .unwrap_or_else(|| Period::week_of(get_today()));
+    let blocks = db.get_plan_between(period.from, period.to)?;
+
+    if blocks.is_empty() {
+        println!("Nothing planned for {}", period);
+        return Ok(());
+    }
+
+    let records = db.get_entries_between(period.from, period.to)?;
+
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Row::from(vec![
+            Cell::new("Date").fg(Color::Cyan),
+            Cell::new("Block").fg(Color::Green),
+            Cell::new("Plan").fg(Color::Green),
+            Cell::new("Planned").fg(Color::Yellow),
+            Cell::new("Actual").fg(Color::Yellow),
+        ]));
+
+    let mut weeks: Vec<(String, Duration, Duration)> = Vec::new();
+    for block in &blocks {
+        let actual = covered_time(block.start, block.end, &records);
+        table.add_row(vec![
+            block.start.format("%a %Y-%m-%d").to_string(),
+            format!(
+                "{}-{}",
+                block.start.format("%H:%M"),
+                block.end.format("%H:%M")
+            ),
+            block.label.clone(),
+            format_duration(block.duration()),
+            format_duration(actual),
+        ]);
+
+        let week = block.start.format("%G-W%V").to_string();
+        match weeks.iter_mut().find(|(label, _, _)| *label == week) {
+            Some((_, planned, worked)) => {
+                *planned += block.duration();
+                *worked += actual;
+            }
+            None => weeks.push((week, block.duration(), actual)),
+        }
+    }
+
+    println!("{table}");
+    for (week, planned, worked) in &weeks {
+        println!(
+            "{}: {} of {} planned worked, {:.0}% adherence",
+            week,
+            format_duration(*worked),
+            format_duration(*planned),
+            worked.num_minutes() as f64 * 100.0 / planned.num_minutes().max(1) as f64
+        );
+    }
+
+    Ok(())
+}
+
+// Whether a session is open today, since when, and today's worked time
+pub fn display_status(keeper: &TimeKeeper, json: bool) -> Result<(), TimeKeeperError> {
+    let now = Local::now().naive_local();
+    let today = keeper.db().get_entries_by_date(now.date())?;
+    let worked = today
+        .iter()
+        .filter(|r| !r.travel)
+        .fold(Duration::zero(), |acc, r| acc + r.duration());
+    let open = keeper.open_session()?;
+    let on_break = match &open {
+        Some(open) => keeper.db().get_open_break(open.id)?,
+        None => None,
+    };
+    // Time since check-in less the unpaid breaks, a running break included
+    let elapsed = open.as_ref().map(|open| {
+        let running = on_break
+            .as_ref()
+            .filter(|running| !running.paid)
+            .map_or(Duration::zero(), |running| now - running.start);
+        (now.signed_duration_since(open.check_in) - open.unpaid_break - running)
+            .max(Duration::zero())
+    });
+
+    if json {
+        let status = StatusExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            open: open.is_some(),
+            check_in: open.as_ref().map(|open| open.check_in),
+            project: open.as_ref().and_then(|open| open.project.clone()),
+            elapsed_minutes: elapsed.map(|elapsed| elapsed.num_minutes()),
+            on_break: on_break.is_some(),
+            today_minutes: (worked + elapsed.unwrap_or_else(Duration::zero)).num_minutes(),
+        };
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+
+    match (open, elapsed) {
+        (Some(open), Some(elapsed)) => {
+            let mut line = format!(
+                "Checked in since {} ({} elapsed)",
+                open.check_in.format("%H:%M"),
+                format_duration(elapsed)
+            );
+            if let Some(project) = &open.project {
+                line = format!("{} on {}", line, project);
+            }
+            if let Some(running) = on_break {
+                line = format!("{}, on break since {}", line, running.start.format("%H:%M"));
+            }
+            println!("{}", line);
+            println!("Today: {}", format_duration(worked + elapsed));
+        }
+        _ => {
+            println!("Not checked in");
+            println!("Today: {}", format_duration(worked));
+        }
+    }
+
+    Ok(())
+}
+
+// Changes the given parts of an existing entry. --date moves the entry to
+// another day and keeps its times unless new ones are given as well. A new
+// check-out is on the check-in's day, or the day after with --overnight.
+#[allow(clippy::too_many_arguments)]
+pub fn handle_edit(
+    db: &Db,
+    id: i32,
+    check_in: Option<String>,
+    check_out: Option<String>,
+    date: Option<String>,
+    note: Option<String>,
+    overnight: bool,
+    force: bool,
+) -> Result<(), TimeKeeperError> {
+    // The entry is read and written back in one transaction, so nothing
+    // saved in between is lost
+    let updated = db.transaction(|db| {
+        let record = db
+            .get_entry(id)?
+            .ok_or_else(|| TimeKeeperError::ParseError(format!("No entry with id {}", id)))?;
+
+        let date = date
+            .map(|date_str| {
+                NaiveDate::parse_from_str(&date_str, "%Y-%m-%d")
+                    .map_err(|_| TimeKeeperError::ParseError("Invalid date format".to_string()))
+            })
+            .transpose()?;
+        let day = date.unwrap_or(record.check_in.date());
+        let check_in = match check_in {
+            Some(time) => parse_datetime_str(&time, date, day)?,
+            None => day.and_time(record.check_in.time()),
+        };
+        let check_out = match check_out {
+            Some(time) => Some(parse_datetime_str(&time, date, check_in.date())?),
+            // A kept check-out stays as many days after the check-in as it was
+            None => record
+                .check_out
+                .map(|out| (day + (out.date() - record.check_in.date())).and_time(out.time())),
+        }
+        .map(|out| overnight_check_out(check_in, out, overnight));
+
+        let updated = Record {
+            check_in,
+            check_out,
+            // An empty note clears it
+            note: match note {
+                Some(note) if note.trim().is_empty() => None,
+                Some(note) => Some(note.trim().to_string()),
+                None => record.note.clone(),
+            },
+            ..record
+        };
+        if let Some(check_out) = updated.check_out {
+            if check_out > updated.check_in {
+                check_overlaps(db, updated.check_in, check_out, Some(updated.id), force)?;
+            }
+        }
+        db.update_entry(&updated)?;
+        Ok(updated)
+    })?;
+
+    println!(
+        "Updated entry {}: {} {}-{}",
+        updated.id,
+        updated.date().format("%Y-%m-%d"),
+        updated.check_in.format("%H:%M"),
+        format_check_out(&updated)
+    );
+    Ok(())
+}
+
+// Reverses the latest change to entries, be it a check-in, check-out, add,
+// edit, delete or import. Each undo steps one change further back.
+pub fn handle_undo(db: &Db) -> Result<(), TimeKeeperError> {
+    let Some(Operation { before, after, .. }) = db.undo_operation()? else {
+        println!("Nothing to undo");
+        return Ok(());
+    };
+
+    let describe = |record: &Record| {
+        format!(
+            "{} {}-{}",
+            record.date().format("%Y-%m-%d"),
+            record.check_in.format("%H:%M"),
+            format_check_out(record)
+        )
+    };
+    for record in &after {
+        if !before.iter().any(|b| b.id == record.id) {
+            println!("Removed entry {}: {}", record.id, describe(record));
+        }
+    }
+    for record in &before {
+        if after.iter().any(|a| a.id == record.id) {
+            println!("Restored entry {} to {}", record.id, describe(record));
+        } else {
+            println!("Restored deleted entry {}: {}", record.id, describe(record));
+        }
+    }
+    Ok(())
+}
+
+// Every recorded change to one entry, for when an edit is questioned.
+// Changes made before the history was kept are not in it.
+pub fn handle_history(db: &Db, id: i32) -> Result<(), TimeKeeperError> {
+    let history = db.get_history(id)?;
+    if history.is_empty() {
+        println!("No history for entry {}", id);
+        return Ok(());
+    }
+
+    let describe = |record: &Record| {
+        format!(
+            "{} {}-{}",
+            record.date().format("%Y-%m-%d"),
+            record.check_in.format("%H:%M"),
+            format_check_out(record)
+        )
+    };
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Row::from(vec![
+            Cell::new("Changed").fg(Color::Cyan),
+            Cell::new("By").fg(Color::Cyan),
+            Cell::new("Change").fg(Color::Yellow),
+        ]));
+    for entry in &history {
+        let change = match (&entry.before, &entry.after) {
+            (None, Some(after)) => format!("Created {}", describe(after)),
+            (Some(before), None) => format!("Deleted {}", describe(before)),
+            (Some(before), Some(after)) => {
+                let changes: Vec<String> = after
+                    .changes_from(before)
+                    .into_iter()
+                    .map(|(field, old, new)| format!("{}: {} -> {}", field, old, new))
+                    .collect();
+                if changes.is_empty() {
+                    "No field changed".to_string()
+                } else {
+                    changes.join("\n")
+                }
+            }
+            (None, None) => continue,
+        };
+        table.add_row(vec![
+            Cell::new(entry.changed.format("%Y-%m-%d %H:%M:%S")),
+            Cell::new(entry.changed_by.as_deref().unwrap_or("-")),
+            Cell::new(change),
+        ]);
+    }
+    println!("History of entry {}:", id);
+    println!("{table}");
+    Ok(())
+}
+
+// Deletes one entry by id, or every entry of a day with --date
+pub fn handle_delete(
+    db: &Db,
+    id: Option<i32>,
+    date: Option<String>,
+    yes: bool,
+) -> Result<(), TimeKeeperError> {
+    let records = match (id, date) {
+        (Some(id), _) => db
+            .get_entry(id)?
+            .map(|record| vec![record])
+            .ok_or_else(|| TimeKeeperError::ParseError(format!("No entry with id {}", id)))?,
+        (None, Some(date_str)) => {
+            let date = NaiveDate::parse_from_str(&date_str, "%Y-%m-%d")
+                .map_err(|_| TimeKeeperError::ParseError("Invalid date format".to_string()))?;
+            db.get_entries_by_date(date)?
+        }
+        (None, None) => Vec::new(),
+    };
+    if records.is_empty() {
+        println!("No entries to delete");
+        return Ok(());
+    }
+
+    for record in &records {
+        println!(
+            "  {}  {}  {}-{}",
+            record.id,
+            record.date().format("%Y-%m-%d"),
+            record.check_in.format("%H:%M"),
+            format_check_out(record)
+        );
+    }
+    // Without a terminal to ask on, nothing is deleted unless --yes is given
+    let prompt = format!("Delete {} entries?", records.len());
+    if !yes && !confirm(&prompt, false)? {
+        println!("Nothing deleted");
+        return Ok(());
+    }
+
+    db.delete_entries(&records)?;
+    println!("Deleted {} entries", records.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_out_by_id() {
+        let keeper = TimeKeeper::open_in_memory().unwrap();
+        let at = Record::test_time;
+        let first = keeper.check_in(at(8, 9), None, None, false).unwrap();
+        let second = keeper
+            .add(&Record {
+                id: 0,
+                check_in: at(9, 8),
+                ..first.clone()
+            })
+            .unwrap();
+
+        // The time is on the picked session's day, the other stays open
+        handle_check_out(&keeper, "17:00", None, false, Some(first.id)).unwrap();
+        assert_eq!(keeper.entry(first.id).unwrap().unwrap().end(), at(8, 17));
+        assert!(keeper.entry(second.id).unwrap().unwrap().is_open());
+
+        assert!(handle_check_out(&keeper, "18:00", None, false, Some(first.id)).is_err());
+        let Err(TimeKeeperError::ParseError(message)) =
+            handle_check_out(&keeper, "18:00", None, false, Some(999))
+        else {
+            panic!("an unknown id must fail");
+        };
+        assert_eq!(message, "No entry with id 999");
+    }
+
+    #[test]
+    fn test_check_overlaps() {
+        let db = Db::open_in_memory().unwrap();
+        let at = Record::test_time;
+        let morning = db
+            .save_entry(&Record::test_entry(at(8, 9), Some(at(8, 12))))
+            .unwrap();
+
+        assert!(check_overlaps(&db, at(8, 12), at(8, 13), None, false).is_ok());
+        let Err(TimeKeeperError::ParseError(message)) =
+            check_overlaps(&db, at(8, 11), at(8, 13), None, false)
+        else {
+            panic!("an overlap must be refused");
+        };
+        assert!(message.contains("--force"), "{}", message);
+        assert!(check_overlaps(&db, at(8, 11), at(8, 13), None, true).is_ok());
+        // An entry being edited does not overlap itself
+        assert!(check_overlaps(&db, at(8, 10), at(8, 13), Some(morning.id), false).is_ok());
+
+        // An open session runs until now, here overlapping anything after it
+        db.save_entry(&Record::test_entry(at(9, 22), None)).unwrap();
+        assert!(check_overlaps(&db, at(10, 9), at(10, 10), None, false).is_err());
+        assert!(check_overlaps(&db, at(9, 20), at(9, 21), None, false).is_ok());
+
+        // A shift longer than a day overlaps every day it covers
+        db.save_entry(&Record::test_entry(at(1, 6), Some(at(4, 6))))
+            .unwrap();
+        assert!(check_overlaps(&db, at(3, 9), at(3, 10), None, false).is_err());
+        assert!(check_overlaps(&db, at(4, 6), at(4, 7), None, false).is_ok());
+    }
+
+    #[test]
+    fn test_edit_overnight() {
+        let db = Db::open_in_memory().unwrap();
+        let at = Record::test_time;
+        let shift = db
+            .save_entry(&Record::test_entry(at(8, 9), Some(at(8, 17))))
+            .unwrap();
+        let edit = |check_in: Option<&str>, check_out: Option<&str>, overnight| {
+            handle_edit(
+                &db,
+                shift.id,
+                check_in.map(str::to_string),
+                check_out.map(str::to_string),
+                None,
+                None,
+                overnight,
+                false,
+            )
+        };
+
+        // A day shift becomes a night shift ending the next morning
+        edit(Some("22:00"), Some("06:00"), true).unwrap();
+        let night = db.get_entry(shift.id).unwrap().unwrap();
+        assert_eq!(night.check_out, Some(at(9, 6)));
+        // and back to one ending on the day it started
+        edit(Some("09:00"), Some("17:00"), false).unwrap();
+        let day = db.get_entry(shift.id).unwrap().unwrap();
+        assert_eq!((day.check_in, day.check_out), (at(8, 9), Some(at(8, 17))));
+        assert!(edit(Some("22:00"), Some("06:00"), false).is_err());
+
+        // An open session closed by an edit ends on its own day
+        let open = db.save_entry(&Record::test_entry(at(10, 9), None)).unwrap();
+        handle_edit(
+            &db,
+            open.id,
+            None,
+            Some("17:00".to_string()),
+            None,
+            None,
+            false,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            db.get_entry(open.id).unwrap().unwrap().check_out,
+            Some(at(10, 17))
+        );
+    }
+
+    #[test]
+    fn test_summary_layout() {
+        let layout = |width, wide| {
+            let layout = SummaryLayout::for_width(width, wide);
+            (layout.notes, layout.range)
+        };
+        assert_eq!(layout(Some(78), false), (true, false));
+        assert_eq!(layout(Some(77), false), (false, false));
+        assert_eq!(layout(Some(62), false), (false, false));
+        assert_eq!(layout(Some(61), false), (false, true));
+        // Pipes and --wide keep every column
+        assert_eq!(layout(None, false), (true, false));
+        assert_eq!(layout(Some(40), true), (true, false));
+    }
+}
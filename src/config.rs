@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::app::TimeKeeperError;
+use crate::paths::Paths;
+use crate::policy::RuleConfig;
+use crate::report::RoundingMode;
+
+// User settings read from config.toml in the config directory. Every key is
+// optional and a missing file behaves like an empty one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // Location recorded on entries when --location is not given
+    pub default_location: Option<String>,
+    // Hours expected per working day, e.g. "8h" or "7h30m", used by reports
+    pub daily_target: Option<String>,
+    // When a logical day begins, e.g. "04:00" so a session started at 1am
+    // counts towards the day before. Days begin at midnight by default.
+    pub day_starts_at: Option<String>,
+    // Hours expected per week, e.g. "40h"
+    pub weekly_target: Option<String>,
+    // Day the overtime balance starts from as YYYY-MM-DD, by default the
+    // first day with entries
+    pub balance_from: Option<String>,
+    // How often the user is paid: weekly, biweekly, semi-monthly, monthly or
+    // a fiscal pattern like 4-4-5. The current and previous periods follow it.
+    pub pay_period: Option<String>,
+    // First day of one pay period as YYYY-MM-DD, which biweekly and fiscal
+    // pay periods count from
+    pub pay_period_start: Option<String>,
+    // Rules applied in order to each entry reports and exports count, and to
+    // the day simulate walks through, each a [[policy]] table
+    pub policy: Vec<RuleConfig>,
+    // Shorthand for a round rule after the [[policy]] ones
+    pub rounding: Option<Rounding>,
+    // Hourly rate per project, the [rates] table, e.g. acme = 85.0
+    pub rates: HashMap<String, f64>,
+    // Fixed-bid budget per project, the [budgets] table, used in place of
+    // the rate when working out what the project earned
+    pub budgets: HashMap<String, f64>,
+    // Tax added on invoices, e.g. 20 for 20%
+    pub tax_percent: Option<f64>,
+    // Shown after amounts on invoices, e.g. "EUR"
+    pub currency: Option<String>,
+    // Color per project, the [colors] table, e.g. acme = "green"
+    pub colors: HashMap<String, String>,
+}
+
+// The [rounding] table, the same as a last [[policy]] rule = "round". Entries
+// keep their recorded times in the database, e.g.
+//   [rounding]
+//   increment = "15m"
+//   mode = "nearest"
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rounding {
+    pub increment: String,
+    #[serde(default)]
+    pub mode: RoundingMode,
+}
+
+impl Config {
+    // The [[policy]] rules followed by the [rounding] one
+    pub fn rules(&self) -> Vec<RuleConfig> {
+        let mut rules = self.policy.clone();
+        if let Some(rounding) = &self.rounding {
+            rules.push(RuleConfig::Round {
+                increment: rounding.increment.clone(),
+                mode: rounding.mode,
+            });
+        }
+        rules
+    }
+
+    pub fn load() -> Result<Config, TimeKeeperError> {
+        let path = Paths::new()?.config_file();
+        if !path.is_file() {
+            return Ok(Config::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)?;
+        Config::parse(&contents)
+            .map_err(|e| TimeKeeperError::ParseError(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn parse(contents: &str) -> Result<Config, String> {
+        toml::from_str(contents).map_err(|e| e.message().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        assert!(Config::parse("").unwrap().default_location.is_none());
+        assert_eq!(
+            Config::parse("default_location = \"office\"")
+                .unwrap()
+                .default_location
+                .as_deref(),
+            Some("office")
+        );
+        assert_eq!(
+            Config::parse("daily_target = \"7h30m\"")
+                .unwrap()
+                .daily_target
+                .as_deref(),
+            Some("7h30m")
+        );
+        assert_eq!(
+            Config::parse("[[policy]]\nrule = \"cap\"\nmax = \"10h\"")
+                .unwrap()
+                .policy,
+            vec![RuleConfig::Cap {
+                max: "10h".to_string()
+            }]
+        );
+        assert_eq!(
+            Config::parse("[rounding]\nincrement = \"15m\"")
+                .unwrap()
+                .rounding,
+            Some(Rounding {
+                increment: "15m".to_string(),
+                mode: RoundingMode::Nearest
+            })
+        );
+        assert!(Config::parse("[rounding]\nincrement = \"6m\"\nmode = \"sideways\"").is_err());
+        assert_eq!(
+            Config::parse(
+                "[rounding]\nincrement = \"6m\"\n[[policy]]\nrule = \"cap\"\nmax = \"10h\""
+            )
+            .unwrap()
+            .rules(),
+            vec![
+                RuleConfig::Cap {
+                    max: "10h".to_string()
+                },
+                RuleConfig::Round {
+                    increment: "6m".to_string(),
+                    mode: RoundingMode::Nearest
+                }
+            ]
+        );
+        assert_eq!(
+            Config::parse("[rates]\nacme = 85.0\n\"big client\" = 120")
+                .unwrap()
+                .rates,
+            HashMap::from([
+                ("acme".to_string(), 85.0),
+                ("big client".to_string(), 120.0)
+            ])
+        );
+        assert!(Config::parse("[[policy]]\nrule = \"unknown\"").is_err());
+        assert!(Config::parse("unknown_key = 1").is_err());
+    }
+}
@@ -1,16 +1,193 @@
-use std::path::PathBuf;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-use chrono::{NaiveDate, NaiveTime};
-use rusqlite::{params, Connection, Result, Row};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime};
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 use crate::app::TimeKeeperError;
+use crate::paths::{ensure_dir, Marker, Paths};
+use crate::report::{deserialize_minutes, serialize_minutes, DaySummary};
 
-#[derive(Debug)]
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// Records along with the unpaid break time taken within them. Breaks still
+// running are only counted once they end.
+const RECORD_QUERY: &str = "SELECT record.*,
+        (SELECT coalesce(sum(strftime('%s', end) - strftime('%s', start)), 0)
+            FROM breaks
+            WHERE record_id = record.id AND NOT paid AND end IS NOT NULL) AS unpaid_break
+    FROM record";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Record {
     pub id: i32,
-    pub check_in: NaiveTime,
-    pub check_out: NaiveTime,
+    pub check_in: NaiveDateTime,
+    // None while the session is still open
+    pub check_out: Option<NaiveDateTime>,
+    pub location: Option<String>,
+    #[serde(default)]
+    pub travel: bool,
+    pub project: Option<String>,
+    pub note: Option<String>,
+    // Derived from the breaks table, never written back with the record
+    #[serde(
+        rename = "unpaid_break_minutes",
+        serialize_with = "serialize_minutes",
+        deserialize_with = "deserialize_minutes",
+        default
+    )]
+    #[schemars(with = "i64")]
+    pub unpaid_break: Duration,
+}
+
+// Something that broke the flow of a session, with an optional reason
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Interruption {
+    pub id: i32,
+    pub record_id: i32,
+    pub at: NaiveDateTime,
+    pub reason: Option<String>,
+}
+
+// A break within a session. Unpaid breaks are taken off the session's duration.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Break {
+    pub id: i32,
+    pub record_id: i32,
+    pub start: NaiveDateTime,
+    // None while the break is still running
+    pub end: Option<NaiveDateTime>,
+    pub paid: bool,
+}
+
+// A logged change to entries with the records it touched, as they were
+// before and after it. The breaks and interruptions of the records before
+// it are kept too, so an undo can bring them back.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Operation {
+    pub id: i64,
+    pub performed: NaiveDateTime,
+    pub before: Vec<Record>,
+    #[serde(flatten)]
+    pub children: Children,
+    pub after: Vec<Record>,
+}
+
+// The breaks and interruptions of some records, empty for operations logged
+// before they were kept
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Children {
+    pub breaks: Vec<Break>,
+    pub interruptions: Vec<Interruption>,
+}
+
+// One change to a record. before is None when the change created it and
+// after is None when it deleted it.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct HistoryEntry {
+    pub record_id: i32,
+    pub changed: NaiveDateTime,
+    pub changed_by: Option<String>,
+    pub before: Option<Record>,
+    pub after: Option<Record>,
+}
+
+// A journal note belongs to a day rather than to any single entry
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Note {
+    pub id: i32,
     pub date: NaiveDate,
+    pub text: String,
+}
+
+impl Note {
+    fn from_row(row: &Row) -> Result<Note> {
+        Ok(Note {
+            id: row.get(0)?,
+            date: NaiveDate::parse_from_str(&row.get::<_, String>(1)?, "%Y-%m-%d").unwrap(),
+            text: row.get(2)?,
+        })
+    }
+}
+
+// A block of time planned ahead, compared against the entries actually made
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PlanBlock {
+    pub id: i32,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub label: String,
+}
+
+impl PlanBlock {
+    fn from_row(row: &Row) -> Result<PlanBlock> {
+        Ok(PlanBlock {
+            id: row.get(0)?,
+            start: NaiveDateTime::parse_from_str(&row.get::<_, String>(1)?, DATETIME_FORMAT)
+                .unwrap(),
+            end: NaiveDateTime::parse_from_str(&row.get::<_, String>(2)?, DATETIME_FORMAT).unwrap(),
+            label: row.get(3)?,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.end.signed_duration_since(self.start)
+    }
+}
+
+// An invoice as recorded when it was issued. Its lines are worked out again
+// from the entries in its period.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct IssuedInvoice {
+    pub number: i64,
+    pub project: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub issued: NaiveDate,
+}
+
+impl IssuedInvoice {
+    fn from_row(row: &Row) -> Result<IssuedInvoice> {
+        let date = |index| -> Result<NaiveDate> {
+            Ok(NaiveDate::parse_from_str(&row.get::<_, String>(index)?, "%Y-%m-%d").unwrap())
+        };
+        Ok(IssuedInvoice {
+            number: row.get(0)?,
+            project: row.get(1)?,
+            from: date(2)?,
+            to: date(3)?,
+            issued: date(4)?,
+        })
+    }
+}
+
+impl Interruption {
+    fn from_row(row: &Row) -> Result<Interruption> {
+        Ok(Interruption {
+            id: row.get(0)?,
+            record_id: row.get(1)?,
+            at: NaiveDateTime::parse_from_str(&row.get::<_, String>(2)?, DATETIME_FORMAT).unwrap(),
+            reason: row.get(3)?,
+        })
+    }
+}
+
+impl Break {
+    fn from_row(row: &Row) -> Result<Break> {
+        Ok(Break {
+            id: row.get(0)?,
+            record_id: row.get(1)?,
+            start: NaiveDateTime::parse_from_str(&row.get::<_, String>(2)?, DATETIME_FORMAT)
+                .unwrap(),
+            end: row
+                .get::<_, Option<String>>(3)?
+                .map(|s| NaiveDateTime::parse_from_str(&s, DATETIME_FORMAT).unwrap()),
+            paid: row.get(4)?,
+        })
+    }
 }
 
 impl Record {
@@ -18,100 +195,673 @@ impl Record {
     fn from_row(row: &Row) -> Result<Record> {
         Ok(Record {
             id: row.get(0)?,
-            check_in: NaiveTime::parse_from_str(&row.get::<_, String>(1)?, "%H:%M:%S").unwrap(),
-            check_out: NaiveTime::parse_from_str(&row.get::<_, String>(2)?, "%H:%M:%S").unwrap(),
-            date: NaiveDate::parse_from_str(&row.get::<_, String>(3)?, "%Y-%m-%d").unwrap(),
+            check_in: NaiveDateTime::parse_from_str(&row.get::<_, String>(1)?, DATETIME_FORMAT)
+                .unwrap(),
+            check_out: row
+                .get::<_, Option<String>>(2)?
+                .map(|s| NaiveDateTime::parse_from_str(&s, DATETIME_FORMAT).unwrap()),
+            location: row.get(3)?,
+            travel: row.get(4)?,
+            project: row.get(5)?,
+            note: row.get(6)?,
+            unpaid_break: Duration::seconds(row.get(7)?),
         })
     }
+
+    // An open session has not accumulated any recorded time yet. Unpaid
+    // breaks are not part of the duration.
+    pub fn duration(&self) -> Duration {
+        (self.end().signed_duration_since(self.check_in) - self.unpaid_break).max(Duration::zero())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.check_out.is_none()
+    }
+
+    // Where the record ends on the timeline, its check-in while still open
+    pub fn end(&self) -> NaiveDateTime {
+        self.check_out.unwrap_or(self.check_in)
+    }
+
+    // Each field that differs from the earlier version, with the old and
+    // new values
+    pub fn changes_from(&self, before: &Record) -> Vec<(&'static str, String, String)> {
+        let time = |t: Option<NaiveDateTime>| {
+            t.map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "-".to_string())
+        };
+        let text = |t: &Option<String>| t.clone().unwrap_or_else(|| "-".to_string());
+        let fields = [
+            (
+                "check_in",
+                time(Some(before.check_in)),
+                time(Some(self.check_in)),
+            ),
+            ("check_out", time(before.check_out), time(self.check_out)),
+            ("location", text(&before.location), text(&self.location)),
+            ("travel", before.travel.to_string(), self.travel.to_string()),
+            ("project", text(&before.project), text(&self.project)),
+            ("note", text(&before.note), text(&self.note)),
+        ];
+        fields
+            .into_iter()
+            .filter(|(_, old, new)| old != new)
+            .collect()
+    }
+
+    // The day the record counts towards, its check-in's logical day
+    pub fn date(&self) -> NaiveDate {
+        (self.check_in - day_start()).date()
+    }
+}
+
+// Fixtures shared by the test modules
+#[cfg(test)]
+impl Record {
+    // An entry with nothing set but its times
+    pub fn test_entry(check_in: NaiveDateTime, check_out: Option<NaiveDateTime>) -> Record {
+        Record {
+            id: 0,
+            check_in,
+            check_out,
+            location: None,
+            travel: false,
+            project: None,
+            note: None,
+            unpaid_break: Duration::zero(),
+        }
+    }
+
+    // The full hour on the day of July 2024
+    pub fn test_time(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 7, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+}
+
+static DB_PATH: OnceLock<PathBuf> = OnceLock::new();
+static DAY_START: OnceLock<Duration> = OnceLock::new();
+
+// Moves the boundary between logical days from midnight to this long after
+// it, from day_starts_at in the config. Only the first call has any effect.
+pub fn set_day_start(offset: Duration) {
+    let _ = DAY_START.set(offset);
+}
+
+fn day_start() -> Duration {
+    DAY_START.get().copied().unwrap_or_else(Duration::zero)
+}
+
+// SQL for the logical day of a datetime column, matching Record::date
+fn logical_date(column: &str) -> String {
+    match day_start().num_seconds() {
+        0 => format!("date({})", column),
+        seconds => format!("date({}, '-{} seconds')", column, seconds),
+    }
 }
 
+// Pins the database for the rest of the process, as --db and TIMEKEEPER_DB do.
+// Only the first call has any effect.
+pub fn set_db_path(path: PathBuf) {
+    let _ = DB_PATH.set(path);
+}
+
+// An explicitly set path comes first, then a .timekeeper marker in the
+// working directory tree, then the platform data directory
 pub fn get_db_path() -> Result<PathBuf, TimeKeeperError> {
-    let project_dirs = directories::ProjectDirs::from("", "", "timekeeper").ok_or_else(|| {
-        TimeKeeperError::DatabaseError(rusqlite::Error::InvalidPath(PathBuf::from(
-            "Could not determine project directory",
-        )))
-    })?;
+    let db_path = match DB_PATH.get() {
+        Some(db_path) => db_path.clone(),
+        None => match Marker::from_current_dir()?.and_then(|marker| marker.db) {
+            Some(db_path) => db_path,
+            None => Paths::new()?.db_file(),
+        },
+    };
 
-    let data_dir = project_dirs.data_dir();
-    std::fs::create_dir_all(data_dir).map_err(|e| {
-        TimeKeeperError::DatabaseError(rusqlite::Error::InvalidPath(PathBuf::from(format!(
-            "Failed to create data directory: {}",
-            e
-        ))))
-    })?;
+    if let Some(dir) = db_path.parent() {
+        ensure_dir(dir)?;
+    }
+    Ok(db_path)
+}
 
-    Ok(data_dir.join("keeper.db"))
+// The one connection a process works through, opened by main and handed to
+// every command. Opening brings the schema up to date first, so a fresh or
+// older database is ready to use wherever it lives.
+pub struct Db {
+    conn: Connection,
 }
 
-fn get_connection() -> Result<Connection> {
-    let db_path =
-        get_db_path().map_err(|e| rusqlite::Error::InvalidPath(PathBuf::from(e.to_string())))?;
-    Connection::open(db_path)
+// Rolls back everything written since it began unless committed. Savepoints
+// nest, so a write that runs in its own can also be part of a transaction.
+struct Savepoint<'a> {
+    conn: &'a Connection,
+    committed: bool,
 }
 
-pub fn create_table() -> Result<()> {
-    let conn = get_connection()?;
+impl<'a> Savepoint<'a> {
+    fn begin(conn: &'a Connection) -> Result<Savepoint<'a>> {
+        conn.execute_batch("SAVEPOINT change")?;
+        Ok(Savepoint {
+            conn,
+            committed: false,
+        })
+    }
 
-    conn.execute(
-        "
+    fn commit(mut self) -> Result<()> {
+        self.conn.execute_batch("RELEASE change")?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Deref for Savepoint<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+    }
+}
+
+impl Drop for Savepoint<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = self
+                .conn
+                .execute_batch("ROLLBACK TO change; RELEASE change");
+        }
+    }
+}
+
+impl Db {
+    // The database from --db, a .timekeeper marker or the data directory
+    pub fn open_default() -> Result<Db, TimeKeeperError> {
+        Db::open(&get_db_path()?)
+    }
+
+    // Creates the database when missing
+    pub fn open(path: &Path) -> Result<Db, TimeKeeperError> {
+        Db::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Db, TimeKeeperError> {
+        Db::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Db, TimeKeeperError> {
+        migrate(&conn)?;
+        Ok(Db { conn })
+    }
+
+    // Runs the steps as one transaction, keeping all of their writes when
+    // they succeed and none of them when one fails
+    pub fn transaction<T>(
+        &self,
+        steps: impl FnOnce(&Db) -> Result<T, TimeKeeperError>,
+    ) -> Result<T, TimeKeeperError> {
+        let savepoint = Savepoint::begin(&self.conn)?;
+        let value = steps(self)?;
+        savepoint.commit()?;
+        Ok(value)
+    }
+}
+
+// Each migration brings the schema from the version before it to its own
+// version, tracked as its position in this list in SQLite's user_version
+// pragma. Migrations are only ever appended, an existing one never changes.
+const MIGRATIONS: &[&str] = &[
+    // 1: the original layout with a separate date column
+    "
         Create table if not exists record (
             id integer primary key,
             check_in text,
             check_out text,
             date text
-            )",
-        (),
-    )?;
+            );",
+    // 2: stores check-in and check-out as full datetimes
+    "
+        ALTER TABLE record RENAME TO record_v1;
+        CREATE TABLE record (
+            id integer primary key,
+            check_in text not null,
+            check_out text not null
+            );
+        INSERT INTO record (id, check_in, check_out)
+            SELECT id, date || ' ' || check_in, date || ' ' || check_out FROM record_v1;
+        DROP TABLE record_v1;",
+    // 3: adds the daily_total cache kept fresh by triggers
+    "
+        CREATE TABLE daily_total (
+            date text primary key,
+            worked integer not null,
+            first_check_in text not null,
+            last_check_out text not null
+            );
+        CREATE TRIGGER record_insert_daily_total AFTER INSERT ON record BEGIN
+            DELETE FROM daily_total WHERE date = date(NEW.check_in);
+        END;
+        CREATE TRIGGER record_update_daily_total AFTER UPDATE ON record BEGIN
+            DELETE FROM daily_total WHERE date IN (date(OLD.check_in), date(NEW.check_in));
+        END;
+        CREATE TRIGGER record_delete_daily_total AFTER DELETE ON record BEGIN
+            DELETE FROM daily_total WHERE date = date(OLD.check_in);
+        END;",
+    // 4: adds the optional location of each entry
+    "
+        ALTER TABLE record ADD COLUMN location text;",
+    // 5: flags travel entries and caches their total separately
+    "
+        ALTER TABLE record ADD COLUMN travel integer not null default 0;
+        ALTER TABLE daily_total ADD COLUMN travel integer not null default 0;
+        DELETE FROM daily_total;",
+    // 6: adds the note table for free-form per-day journal notes
+    "
+        CREATE TABLE note (
+            id integer primary key,
+            date text not null,
+            text text not null
+            );
+        CREATE INDEX note_date ON note (date);",
+    // 7: adds the local command usage counters
+    "
+        CREATE TABLE usage (
+            command text primary key,
+            count integer not null,
+            last_used text not null
+            );",
+    // 8: adds the optional project of each entry
+    "
+        ALTER TABLE record ADD COLUMN project text;",
+    // 9: adds the plan table of planned time blocks
+    "
+        CREATE TABLE plan (
+            id integer primary key,
+            start text not null,
+            end text not null,
+            label text not null
+            );",
+    // 10: stores open sessions with a NULL check_out
+    // SQLite cannot drop a NOT NULL constraint in place, so the table is
+    // rebuilt. Renaming moves the triggers along, they are recreated after.
+    "
+        ALTER TABLE record RENAME TO record_v9;
+        CREATE TABLE record (
+            id integer primary key,
+            check_in text not null,
+            check_out text,
+            location text,
+            travel integer not null default 0,
+            project text
+            );
+        INSERT INTO record (id, check_in, check_out, location, travel, project)
+            SELECT id, check_in, nullif(check_out, check_in), location, travel, project
+            FROM record_v9;
+        DROP TABLE record_v9;
+        CREATE TRIGGER record_insert_daily_total AFTER INSERT ON record BEGIN
+            DELETE FROM daily_total WHERE date = date(NEW.check_in);
+        END;
+        CREATE TRIGGER record_update_daily_total AFTER UPDATE ON record BEGIN
+            DELETE FROM daily_total WHERE date IN (date(OLD.check_in), date(NEW.check_in));
+        END;
+        CREATE TRIGGER record_delete_daily_total AFTER DELETE ON record BEGIN
+            DELETE FROM daily_total WHERE date = date(OLD.check_in);
+        END;
+        DELETE FROM daily_total;",
+    // 11: adds an optional note to each entry
+    "
+        ALTER TABLE record ADD COLUMN note text;",
+    // 12: adds the breaks table of paid and unpaid breaks within entries
+    "
+        CREATE TABLE breaks (
+            id integer primary key,
+            record_id integer not null,
+            start text not null,
+            end text,
+            paid integer not null default 0
+        );
+        CREATE INDEX breaks_record_id ON breaks (record_id);
+        CREATE TRIGGER breaks_insert_daily_total AFTER INSERT ON breaks BEGIN
+            DELETE FROM daily_total
+                WHERE date = (SELECT date(check_in) FROM record WHERE id = NEW.record_id);
+        END;
+        CREATE TRIGGER breaks_update_daily_total AFTER UPDATE ON breaks BEGIN
+            DELETE FROM daily_total
+                WHERE date = (SELECT date(check_in) FROM record WHERE id = NEW.record_id);
+        END;
+        CREATE TRIGGER breaks_delete_daily_total AFTER DELETE ON breaks BEGIN
+            DELETE FROM daily_total
+                WHERE date = (SELECT date(check_in) FROM record WHERE id = OLD.record_id);
+        END;",
+    // 13: adds the interruptions counted within entries
+    "
+        CREATE TABLE interruption (
+            id integer primary key,
+            record_id integer not null,
+            at text not null,
+            reason text
+        );
+        CREATE INDEX interruption_record_id ON interruption (record_id);",
+    // 14: adds the invoice table, its rowid is the invoice number
+    "
+        CREATE TABLE invoice (
+            number integer primary key,
+            project text not null,
+            from_date text not null,
+            to_date text not null,
+            issued text not null
+        );",
+    // 15: adds the operation log of entry changes that undo reverses
+    "
+        CREATE TABLE operation (
+            id integer primary key,
+            performed text not null,
+            before text not null,
+            after text not null
+        );",
+    // 16: adds the history of every change to each record, kept through undo
+    "
+        CREATE TABLE history (
+            id integer primary key,
+            record_id integer not null,
+            changed text not null,
+            changed_by text,
+            before text,
+            after text
+        );
+        CREATE INDEX history_record_id ON history (record_id);",
+    // 17: adds the reporting views, a stable contract for tools that query
+    // the database directly. A view's columns may be added to but never
+    // change meaning, whatever becomes of the tables underneath.
+    "
+        CREATE VIEW daily_totals AS
+            -- One row per calendar day with checked out entries. Minutes
+            -- leave out unpaid breaks, travel is kept apart from worked time.
+            SELECT date(check_in) AS day,
+                sum(iif(travel, 0, seconds)) / 60 AS worked_minutes,
+                sum(iif(travel, seconds, 0)) / 60 AS travel_minutes,
+                count(*) AS entries,
+                min(check_in) AS first_check_in,
+                max(check_out) AS last_check_out
+            FROM (
+                SELECT check_in, check_out, travel,
+                    max(strftime('%s', check_out) - strftime('%s', check_in)
+                        - (SELECT coalesce(sum(strftime('%s', end) - strftime('%s', start)), 0)
+                            FROM breaks
+                            WHERE record_id = record.id AND NOT paid AND end IS NOT NULL),
+                        0) AS seconds
+                FROM record
+                WHERE check_out IS NOT NULL
+                )
+            GROUP BY day;
+        CREATE VIEW weekly_totals AS
+            -- One row per week with entries, weeks start on Monday
+            SELECT date(day, 'weekday 0', '-6 days') AS week_start,
+                sum(worked_minutes) AS worked_minutes,
+                sum(travel_minutes) AS travel_minutes,
+                count(*) AS days,
+                sum(entries) AS entries
+            FROM daily_totals
+            GROUP BY week_start;
+        CREATE VIEW project_totals AS
+            -- One row per project, NULL for entries without one. Travel is
+            -- left out.
+            SELECT project,
+                sum(seconds) / 60 AS worked_minutes,
+                count(*) AS entries,
+                min(date(check_in)) AS first_day,
+                max(date(check_in)) AS last_day
+            FROM (
+                SELECT check_in, project,
+                    max(strftime('%s', check_out) - strftime('%s', check_in)
+                        - (SELECT coalesce(sum(strftime('%s', end) - strftime('%s', start)), 0)
+                            FROM breaks
+                            WHERE record_id = record.id AND NOT paid AND end IS NOT NULL),
+                        0) AS seconds
+                FROM record
+                WHERE check_out IS NOT NULL AND NOT travel
+                )
+            GROUP BY project;",
+    // 18: keeps the breaks and interruptions of the records an operation
+    // changed, as they were before it
+    "
+        ALTER TABLE operation ADD COLUMN children text;",
+    // 19: drops the cached totals, which took a break longer than its entry
+    // off the rest of the day
+    "
+        DELETE FROM daily_total;",
+];
+
+pub const SCHEMA_VERSION: i32 = MIGRATIONS.len() as i32;
+
+// Runs the migrations the database has not seen yet, each in its own
+// transaction. A database that has any to run is backed up first.
+fn migrate(conn: &Connection) -> Result<()> {
+    let version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version >= SCHEMA_VERSION {
+        return Ok(());
+    }
+    // Databases from before versioning are at 0 too, only a new one is empty
+    let tables: i32 = conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get(0))?;
+    if tables > 0 {
+        backup_before_migrate(conn, version)?;
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        conn.execute_batch(&format!(
+            "BEGIN;\n{}\nPRAGMA user_version = {};\nCOMMIT;",
+            migration,
+            index + 1
+        ))?;
+    }
     Ok(())
 }
 
-pub fn save_entry(record: &Record) -> Result<()> {
-    let conn = get_connection()?;
+// Copies the database next to itself, e.g. keeper.db.v11.bak, unless it only
+// lives in memory
+fn backup_before_migrate(conn: &Connection, version: i32) -> Result<()> {
+    let path = match conn.path() {
+        Some(path) if !path.is_empty() => format!("{}.v{}.bak", path, version),
+        _ => return Ok(()),
+    };
 
+    // VACUUM INTO refuses to overwrite, an older backup of this version is replaced
+    let _ = std::fs::remove_file(&path);
+    conn.execute("VACUUM INTO ?1", [path])?;
+    Ok(())
+}
+
+// Every change to entries is logged with the records it touched as JSON, as
+// they were before and after it, so the latest change can be undone. The
+// children are those of the records before it, read before the change.
+fn log_operation(
+    conn: &Connection,
+    before: &[Record],
+    children: &Children,
+    after: &[Record],
+) -> Result<()> {
     conn.execute(
-        "INSERT INTO record (check_in, check_out, date) VALUES (?1, ?2, ?3)",
+        "INSERT INTO operation (performed, before, children, after) VALUES (?1, ?2, ?3, ?4)",
         params![
-            record.check_in.format("%H:%M:%S").to_string(),
-            record.check_out.format("%H:%M:%S").to_string(),
-            record.date.format("%Y-%m-%d").to_string(),
+            Local::now()
+                .naive_local()
+                .format(DATETIME_FORMAT)
+                .to_string(),
+            to_json(before)?,
+            to_json(children)?,
+            to_json(after)?,
         ],
     )?;
+    log_history(conn, before, after)
+}
 
-    Ok(())
+const OPERATION_QUERY: &str = "SELECT id, performed, before, children, after FROM operation";
+
+type OperationRow = (i64, String, String, Option<String>, String);
+
+fn operation_row(row: &Row) -> Result<OperationRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+    ))
 }
 
-pub fn get_all_entries() -> Result<Vec<Record>> {
-    let conn = get_connection()?;
-    let mut stmt = conn.prepare("SELECT * FROM record")?;
+// The records of a logged operation are stored as JSON
+fn to_operation(
+    (id, performed, before, children, after): OperationRow,
+) -> Result<Operation, TimeKeeperError> {
+    Ok(Operation {
+        id,
+        performed: NaiveDateTime::parse_from_str(&performed, DATETIME_FORMAT).unwrap(),
+        before: serde_json::from_str(&before)?,
+        children: match children {
+            Some(children) => serde_json::from_str(&children)?,
+            None => Children::default(),
+        },
+        after: serde_json::from_str(&after)?,
+    })
+}
 
-    let records = stmt
-        .query_map([], |row| Record::from_row(row))?
-        .collect::<Result<Vec<_>>>()?;
+// The breaks and interruptions of the records as they are now
+fn children_of(conn: &Connection, records: &[Record]) -> Result<Children> {
+    let mut children = Children::default();
+    for record in records {
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, record_id, start, end, paid FROM breaks WHERE record_id = ?1 ORDER BY id",
+        )?;
+        children.breaks.extend(
+            stmt.query_map([record.id], Break::from_row)?
+                .collect::<Result<Vec<_>>>()?,
+        );
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, record_id, at, reason FROM interruption WHERE record_id = ?1 ORDER BY id",
+        )?;
+        children.interruptions.extend(
+            stmt.query_map([record.id], Interruption::from_row)?
+                .collect::<Result<Vec<_>>>()?,
+        );
+    }
+    Ok(children)
+}
+
+// Writes the breaks and interruptions back as they were, under their own ids
+fn restore_children(conn: &Connection, children: &Children) -> Result<()> {
+    for brk in &children.breaks {
+        conn.execute(
+            "INSERT OR REPLACE INTO breaks (id, record_id, start, end, paid)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                brk.id,
+                brk.record_id,
+                brk.start.format(DATETIME_FORMAT).to_string(),
+                brk.end.map(|t| t.format(DATETIME_FORMAT).to_string()),
+                brk.paid,
+            ],
+        )?;
+    }
+    for interruption in &children.interruptions {
+        conn.execute(
+            "INSERT OR REPLACE INTO interruption (id, record_id, at, reason)
+                VALUES (?1, ?2, ?3, ?4)",
+            params![
+                interruption.id,
+                interruption.record_id,
+                interruption.at.format(DATETIME_FORMAT).to_string(),
+                interruption.reason,
+            ],
+        )?;
+    }
+    Ok(())
+}
 
-    Ok(records)
+fn to_json<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
 }
 
-pub fn get_entries_by_date(date: NaiveDate) -> Result<Vec<Record>> {
-    let conn = get_connection()?;
-    let mut stmt = conn.prepare("SELECT * FROM record WHERE date = ?")?;
+// Adds a history row for each record the change touched, by the user the
+// process runs as
+fn log_history(conn: &Connection, before: &[Record], after: &[Record]) -> Result<()> {
+    let changed = Local::now()
+        .naive_local()
+        .format(DATETIME_FORMAT)
+        .to_string();
+    let changed_by = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok();
+    let mut ids: Vec<i32> = before.iter().chain(after).map(|r| r.id).collect();
+    ids.sort();
+    ids.dedup();
 
-    let date_str = date.format("%Y-%m-%d").to_string();
-    let records = stmt
-        .query_map([date_str], |row| Record::from_row(row))?
-        .collect::<Result<Vec<_>>>()?;
+    for id in ids {
+        let version = |records: &[Record]| -> Result<Option<String>> {
+            records.iter().find(|r| r.id == id).map(to_json).transpose()
+        };
+        conn.prepare_cached(
+            "INSERT INTO history (record_id, changed, changed_by, before, after)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?
+        .execute(params![
+            id,
+            changed,
+            changed_by,
+            version(before)?,
+            version(after)?
+        ])?;
+    }
+    Ok(())
+}
+
+// Returns the record as inserted, with its new id. Ids of deleted records
+// are never handed out again, so history and exports keep meaning one entry.
+// The statement is cached on the connection, so a batch prepares it once.
+fn insert_entry(conn: &Connection, record: &Record) -> Result<Record> {
+    conn.prepare_cached(
+        "INSERT INTO record (id, check_in, check_out, location, travel, project, note)
+            VALUES (
+                max((SELECT coalesce(max(id), 0) FROM record),
+                    (SELECT coalesce(max(record_id), 0) FROM history)) + 1,
+                ?1, ?2, ?3, ?4, ?5, ?6)",
+    )?
+    .execute(params![
+        record.check_in.format(DATETIME_FORMAT).to_string(),
+        record
+            .check_out
+            .map(|t| t.format(DATETIME_FORMAT).to_string()),
+        record.location,
+        record.travel,
+        record.project,
+        record.note,
+    ])?;
 
-    Ok(records)
+    Ok(Record {
+        id: conn.last_insert_rowid() as i32,
+        ..record.clone()
+    })
 }
 
-pub fn update_entry(record: &Record) -> Result<()> {
-    let conn = get_connection()?;
+// Rejects a check-out at or before the check-in, whichever command edits it
+fn write_record(conn: &Connection, record: &Record) -> Result<(), TimeKeeperError> {
+    if record
+        .check_out
+        .is_some_and(|check_out| check_out <= record.check_in)
+    {
+        return Err(TimeKeeperError::CheckOutBeforeCheckIn);
+    }
 
     conn.execute(
-        "UPDATE record SET check_in = ?1, check_out = ?2, date = ?3 WHERE id = ?4",
+        "UPDATE record SET check_in = ?1, check_out = ?2, location = ?3, travel = ?4,
+            project = ?5, note = ?6 WHERE id = ?7",
         params![
-            record.check_in.format("%H:%M:%S").to_string(),
-            record.check_out.format("%H:%M:%S").to_string(),
-            record.date.format("%Y-%m-%d").to_string(),
+            record.check_in.format(DATETIME_FORMAT).to_string(),
+            record
+                .check_out
+                .map(|t| t.format(DATETIME_FORMAT).to_string()),
+            record.location,
+            record.travel,
+            record.project,
+            record.note,
             record.id,
         ],
     )?;
@@ -119,8 +869,1027 @@ pub fn update_entry(record: &Record) -> Result<()> {
     Ok(())
 }
 
-pub fn delete_entry(id: i32) -> Result<()> {
-    let conn = get_connection()?;
+// Writes the record over its stored version, returning that version
+fn update_record(conn: &Connection, record: &Record) -> Result<Vec<Record>, TimeKeeperError> {
+    let before: Vec<Record> = entry_on(conn, record.id)?.into_iter().collect();
+    write_record(conn, record)?;
+    Ok(before)
+}
+
+fn entry_on(conn: &Connection, id: i32) -> Result<Option<Record>> {
+    let mut stmt = conn.prepare(&format!("{} WHERE id = ?1", RECORD_QUERY))?;
+
+    let mut records = stmt
+        .query_map([id], Record::from_row)?
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(records.pop())
+}
+
+// A record goes along with its breaks and interruptions
+fn remove_record(conn: &Connection, id: i32) -> Result<()> {
+    conn.execute("DELETE FROM breaks WHERE record_id = ?1", params![id])?;
+    conn.execute("DELETE FROM interruption WHERE record_id = ?1", params![id])?;
     conn.execute("DELETE FROM record WHERE id = ?1", params![id])?;
     Ok(())
 }
+
+// Writes that take more than one statement run in a transaction, so a crash
+// part way leaves the database as it was. SQLite rolls an interrupted
+// transaction back from its journal the next time the file is opened.
+impl Db {
+    // Every change to the record, oldest first
+    pub fn get_history(&self, record_id: i32) -> Result<Vec<HistoryEntry>, TimeKeeperError> {
+        self.history_where("record_id = ?1", params![record_id])
+    }
+
+    // Every change to any record, oldest first
+    pub fn get_all_history(&self) -> Result<Vec<HistoryEntry>, TimeKeeperError> {
+        self.history_where("1", params![])
+    }
+
+    fn history_where(
+        &self,
+        condition: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<HistoryEntry>, TimeKeeperError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT record_id, changed, changed_by, before, after FROM history
+            WHERE {} ORDER BY id",
+            condition
+        ))?;
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok((
+                    row.get::<_, i32>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let version = |json: Option<String>| -> Result<Option<Record>, TimeKeeperError> {
+            Ok(json.map(|json| serde_json::from_str(&json)).transpose()?)
+        };
+        rows.into_iter()
+            .map(|(record_id, changed, changed_by, before, after)| {
+                Ok(HistoryEntry {
+                    record_id,
+                    changed: NaiveDateTime::parse_from_str(&changed, DATETIME_FORMAT).unwrap(),
+                    changed_by,
+                    before: version(before)?,
+                    after: version(after)?,
+                })
+            })
+            .collect()
+    }
+
+    // Returns the record with its new id
+    pub fn save_entry(&self, record: &Record) -> Result<Record> {
+        let tx = Savepoint::begin(&self.conn)?;
+        let inserted = insert_entry(&tx, record)?;
+        log_operation(
+            &tx,
+            &[],
+            &Children::default(),
+            std::slice::from_ref(&inserted),
+        )?;
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    // Inserts the records in one transaction. After each one `progress` is told
+    // how many are in and can stop by returning false, which rolls the whole
+    // batch back. Returns whether the batch was committed.
+    pub fn save_entries(
+        &self,
+        records: &[Record],
+        mut progress: impl FnMut(usize) -> bool,
+    ) -> Result<bool> {
+        let tx = Savepoint::begin(&self.conn)?;
+        let mut inserted = Vec::new();
+        for (index, record) in records.iter().enumerate() {
+            let saved = insert_entry(&tx, record)?;
+            // Exports only carry the unpaid break total, it comes back as one
+            // break from the check-in
+            if record.unpaid_break > Duration::zero() {
+                tx.prepare_cached(
+                    "INSERT INTO breaks (record_id, start, end, paid) VALUES (?1, ?2, ?3, 0)",
+                )?
+                .execute(params![
+                    saved.id,
+                    record.check_in.format(DATETIME_FORMAT).to_string(),
+                    (record.check_in + record.unpaid_break)
+                        .format(DATETIME_FORMAT)
+                        .to_string(),
+                ])?;
+            }
+            inserted.push(saved);
+            if !progress(index + 1) {
+                return Ok(false);
+            }
+        }
+        log_operation(&tx, &[], &Children::default(), &inserted)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn get_all_entries(&self) -> Result<Vec<Record>> {
+        let mut stmt = self.conn.prepare(RECORD_QUERY)?;
+
+        let records = stmt
+            .query_map([], Record::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(records)
+    }
+
+    pub fn get_entries_by_date(&self, date: NaiveDate) -> Result<Vec<Record>> {
+        let conn = &self.conn;
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE {} = ?",
+            RECORD_QUERY,
+            logical_date("check_in")
+        ))?;
+
+        let date_str = date.format("%Y-%m-%d").to_string();
+        let records = stmt
+            .query_map([date_str], Record::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(records)
+    }
+
+    // Every project an entry has been booked on, by name
+    pub fn get_projects(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT project FROM record WHERE project IS NOT NULL ORDER BY project",
+        )?;
+
+        let projects = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(projects)
+    }
+
+    pub fn get_entries_between(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<Record>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE {} BETWEEN ?1 AND ?2",
+            RECORD_QUERY,
+            logical_date("check_in")
+        ))?;
+
+        let records = stmt
+            .query_map(
+                params![
+                    from.format("%Y-%m-%d").to_string(),
+                    to.format("%Y-%m-%d").to_string()
+                ],
+                Record::from_row,
+            )?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(records)
+    }
+
+    // Entries whose time intersects [from, to), however long they run, and
+    // every open session, which the caller bounds by the clock
+    pub fn get_entries_overlapping(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<Record>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE check_out IS NULL OR (check_in < ?2 AND check_out > ?1)",
+            RECORD_QUERY
+        ))?;
+
+        let records = stmt
+            .query_map(
+                params![
+                    from.format(DATETIME_FORMAT).to_string(),
+                    to.format(DATETIME_FORMAT).to_string()
+                ],
+                Record::from_row,
+            )?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(records)
+    }
+
+    // Per-day totals served from the daily_total cache. Days missing from the
+    // cache are aggregated from the records first; any write to a day's records
+    // drops its cached row through the triggers created in migrate. The cache is
+    // keyed on calendar days, so with a later day start totals are aggregated
+    // afresh every time.
+    pub fn get_daily_totals(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<DaySummary>> {
+        let conn = &self.conn;
+        let from = from.format("%Y-%m-%d").to_string();
+        let to = to.format("%Y-%m-%d").to_string();
+
+        let day = logical_date("check_in");
+        let aggregate = format!(
+            "SELECT {day} AS day,
+               coalesce(sum(iif(travel, 0, seconds)), 0),
+               coalesce(sum(iif(travel, seconds, 0)), 0),
+               min(check_in),
+               max(coalesce(check_out, check_in))
+        FROM (
+            SELECT *,
+                max(strftime('%s', check_out) - strftime('%s', check_in) - unpaid_break, 0)
+                AS seconds
+            FROM ({RECORD_QUERY})
+            )
+        WHERE {day} BETWEEN ?1 AND ?2",
+        );
+
+        let mut stmt = if day_start().is_zero() {
+            conn.execute(
+                &format!(
+                    "INSERT INTO daily_total (date, worked, travel, first_check_in, last_check_out)
+                {aggregate}
+                  AND {day} NOT IN (SELECT date FROM daily_total)
+                GROUP BY day"
+                ),
+                params![from, to],
+            )?;
+            conn.prepare(
+                "SELECT date, worked, travel, first_check_in, last_check_out FROM daily_total
+                WHERE date BETWEEN ?1 AND ?2 ORDER BY date",
+            )?
+        } else {
+            conn.prepare(&format!("{aggregate} GROUP BY day ORDER BY day"))?
+        };
+        let totals = stmt
+            .query_map(params![from, to], |row| {
+                Ok(DaySummary {
+                    date: NaiveDate::parse_from_str(&row.get::<_, String>(0)?, "%Y-%m-%d").unwrap(),
+                    worked: Duration::seconds(row.get(1)?),
+                    travel: Duration::seconds(row.get(2)?),
+                    first_check_in: NaiveDateTime::parse_from_str(
+                        &row.get::<_, String>(3)?,
+                        DATETIME_FORMAT,
+                    )
+                    .unwrap(),
+                    last_check_out: NaiveDateTime::parse_from_str(
+                        &row.get::<_, String>(4)?,
+                        DATETIME_FORMAT,
+                    )
+                    .unwrap(),
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(totals)
+    }
+
+    // The latest session still open, whichever day it started on. Open
+    // sessions have no check_out until checked out.
+    pub fn get_open_entry(&self) -> Result<Option<Record>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE check_out IS NULL ORDER BY check_in DESC, id DESC LIMIT 1",
+            RECORD_QUERY
+        ))?;
+
+        let mut records = stmt
+            .query_map([], Record::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(records.pop())
+    }
+
+    pub fn update_entry(&self, record: &Record) -> Result<(), TimeKeeperError> {
+        let tx = Savepoint::begin(&self.conn)?;
+        let before = update_record(&tx, record)?;
+        let children = children_of(&tx, &before)?;
+        log_operation(&tx, &before, &children, std::slice::from_ref(record))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Saves the record with its check-out, ending a break still running in it
+    // at the check-out or at the break's own start if that is later
+    pub fn close_entry(&self, record: &Record) -> Result<(), TimeKeeperError> {
+        let tx = Savepoint::begin(&self.conn)?;
+        let children = children_of(&tx, std::slice::from_ref(record))?;
+        if let Some(check_out) = record.check_out {
+            tx.execute(
+                "UPDATE breaks SET end = max(start, ?1) WHERE record_id = ?2 AND end IS NULL",
+                params![check_out.format(DATETIME_FORMAT).to_string(), record.id],
+            )?;
+        }
+        let before = update_record(&tx, record)?;
+        log_operation(&tx, &before, &children, std::slice::from_ref(record))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Closes one session and opens the next together, as --switch does
+    // Returns the opened record with its new id
+    pub fn switch_entry(
+        &self,
+        closed: &Record,
+        opened: &Record,
+    ) -> Result<Record, TimeKeeperError> {
+        let tx = Savepoint::begin(&self.conn)?;
+        let before = update_record(&tx, closed)?;
+        let children = children_of(&tx, &before)?;
+        let opened = insert_entry(&tx, opened)?;
+        log_operation(&tx, &before, &children, &[closed.clone(), opened.clone()])?;
+        tx.commit()?;
+        Ok(opened)
+    }
+
+    pub fn get_entry(&self, id: i32) -> Result<Option<Record>> {
+        entry_on(&self.conn, id)
+    }
+
+    // Deletes the records as one change, which a single undo brings back
+    pub fn delete_entries(&self, records: &[Record]) -> Result<()> {
+        let tx = Savepoint::begin(&self.conn)?;
+        let children = children_of(&tx, records)?;
+        for record in records {
+            remove_record(&tx, record.id)?;
+        }
+        log_operation(&tx, records, &children, &[])?;
+        tx.commit()
+    }
+
+    // Reverses the latest logged change and drops it from the log: records it
+    // added are deleted and the ones it changed or deleted are written back as
+    // they were, along with their breaks and interruptions.
+    // Returns the change undone, None when nothing is left to undo.
+    pub fn undo_operation(&self) -> Result<Option<Operation>, TimeKeeperError> {
+        let tx = Savepoint::begin(&self.conn)?;
+        let latest = tx
+            .query_row(
+                &format!("{} ORDER BY id DESC LIMIT 1", OPERATION_QUERY),
+                [],
+                operation_row,
+            )
+            .optional()?;
+        let Some(latest) = latest else {
+            return Ok(None);
+        };
+        let Operation {
+            id,
+            performed,
+            before,
+            children,
+            after,
+        } = to_operation(latest)?;
+
+        for record in &after {
+            if !before.iter().any(|b| b.id == record.id) {
+                remove_record(&tx, record.id)?;
+            }
+        }
+        for record in &before {
+            if entry_on(&tx, record.id)?.is_some() {
+                write_record(&tx, record)?;
+            } else {
+                tx.execute(
+                    "INSERT INTO record (id, check_in, check_out, location, travel, project, note)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        record.id,
+                        record.check_in.format(DATETIME_FORMAT).to_string(),
+                        record
+                            .check_out
+                            .map(|t| t.format(DATETIME_FORMAT).to_string()),
+                        record.location,
+                        record.travel,
+                        record.project,
+                        record.note,
+                    ],
+                )?;
+            }
+        }
+        restore_children(&tx, &children)?;
+        tx.execute("DELETE FROM operation WHERE id = ?1", params![id])?;
+        // The undo itself goes down in the history, from after back to before
+        log_history(&tx, &after, &before)?;
+        tx.commit()?;
+        Ok(Some(Operation {
+            id,
+            performed,
+            before,
+            children,
+            after,
+        }))
+    }
+
+    // The whole operation log, oldest first
+    pub fn get_operations(&self) -> Result<Vec<Operation>, TimeKeeperError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("{} ORDER BY id", OPERATION_QUERY))?;
+        let rows = stmt
+            .query_map([], operation_row)?
+            .collect::<Result<Vec<_>>>()?;
+        rows.into_iter().map(to_operation).collect()
+    }
+
+    pub fn save_interruption(&self, interruption: &Interruption) -> Result<()> {
+        let conn = &self.conn;
+
+        conn.execute(
+            "INSERT INTO interruption (record_id, at, reason) VALUES (?1, ?2, ?3)",
+            params![
+                interruption.record_id,
+                interruption.at.format(DATETIME_FORMAT).to_string(),
+                interruption.reason,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    // Interruptions within entries checked in between the two days
+    pub fn get_interruptions_between(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Interruption>> {
+        let conn = &self.conn;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT interruption.id, record_id, at, reason FROM interruption
+            JOIN record ON record.id = record_id
+            WHERE {} BETWEEN ?1 AND ?2
+            ORDER BY at",
+            logical_date("record.check_in")
+        ))?;
+
+        let interruptions = stmt
+            .query_map(
+                params![
+                    from.format("%Y-%m-%d").to_string(),
+                    to.format("%Y-%m-%d").to_string()
+                ],
+                Interruption::from_row,
+            )?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(interruptions)
+    }
+
+    // Records an invoice and returns its number, one past the highest so far
+    pub fn save_invoice(
+        &self,
+        project: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+        issued: NaiveDate,
+    ) -> Result<i64> {
+        let conn = &self.conn;
+
+        conn.execute(
+            "INSERT INTO invoice (project, from_date, to_date, issued) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                project,
+                from.format("%Y-%m-%d").to_string(),
+                to.format("%Y-%m-%d").to_string(),
+                issued.format("%Y-%m-%d").to_string(),
+            ],
+        )?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn get_invoices(&self) -> Result<Vec<IssuedInvoice>> {
+        let conn = &self.conn;
+        let mut stmt = conn.prepare(
+            "SELECT number, project, from_date, to_date, issued FROM invoice ORDER BY number",
+        )?;
+
+        let invoices = stmt
+            .query_map([], IssuedInvoice::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(invoices)
+    }
+
+    pub fn get_all_interruptions(&self) -> Result<Vec<Interruption>> {
+        let conn = &self.conn;
+        let mut stmt =
+            conn.prepare("SELECT id, record_id, at, reason FROM interruption ORDER BY at, id")?;
+
+        let interruptions = stmt
+            .query_map([], Interruption::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(interruptions)
+    }
+
+    pub fn save_break(&self, brk: &Break) -> Result<()> {
+        let conn = &self.conn;
+
+        conn.execute(
+            "INSERT INTO breaks (record_id, start, end, paid) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                brk.record_id,
+                brk.start.format(DATETIME_FORMAT).to_string(),
+                brk.end.map(|t| t.format(DATETIME_FORMAT).to_string()),
+                brk.paid,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_all_breaks(&self) -> Result<Vec<Break>> {
+        let conn = &self.conn;
+        let mut stmt =
+            conn.prepare("SELECT id, record_id, start, end, paid FROM breaks ORDER BY start, id")?;
+
+        let breaks = stmt
+            .query_map([], Break::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(breaks)
+    }
+
+    // The break still running within a record, if any
+    pub fn get_open_break(&self, record_id: i32) -> Result<Option<Break>> {
+        let conn = &self.conn;
+        let mut stmt = conn.prepare(
+            "SELECT id, record_id, start, end, paid FROM breaks
+            WHERE record_id = ?1 AND end IS NULL ORDER BY id DESC LIMIT 1",
+        )?;
+
+        let mut breaks = stmt
+            .query_map([record_id], Break::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(breaks.pop())
+    }
+
+    pub fn end_break(&self, id: i32, end: NaiveDateTime) -> Result<()> {
+        let conn = &self.conn;
+        conn.execute(
+            "UPDATE breaks SET end = ?1 WHERE id = ?2",
+            params![end.format(DATETIME_FORMAT).to_string(), id],
+        )?;
+        Ok(())
+    }
+
+    pub fn save_note(&self, note: &Note) -> Result<()> {
+        let conn = &self.conn;
+
+        conn.execute(
+            "INSERT INTO note (date, text) VALUES (?1, ?2)",
+            params![note.date.format("%Y-%m-%d").to_string(), note.text],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_all_notes(&self) -> Result<Vec<Note>> {
+        let conn = &self.conn;
+        let mut stmt = conn.prepare("SELECT id, date, text FROM note ORDER BY date, id")?;
+
+        let notes = stmt
+            .query_map([], Note::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(notes)
+    }
+
+    // Notes between the two dates, optionally only those containing the query
+    pub fn get_notes_between(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        query: Option<&str>,
+    ) -> Result<Vec<Note>> {
+        let conn = &self.conn;
+        let mut stmt = conn.prepare(
+            "SELECT id, date, text FROM note
+            WHERE date BETWEEN ?1 AND ?2 AND instr(lower(text), lower(?3)) > 0
+            ORDER BY date, id",
+        )?;
+
+        let notes = stmt
+            .query_map(
+                params![
+                    from.format("%Y-%m-%d").to_string(),
+                    to.format("%Y-%m-%d").to_string(),
+                    query.unwrap_or(""),
+                ],
+                Note::from_row,
+            )?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(notes)
+    }
+
+    // Usage counters never leave keeper.db, they only show which commands get used
+    pub fn record_usage(&self, command: &str, at: NaiveDateTime) -> Result<()> {
+        let conn = &self.conn;
+
+        conn.execute(
+            "INSERT INTO usage (command, count, last_used) VALUES (?1, 1, ?2)
+            ON CONFLICT (command) DO UPDATE SET count = count + 1, last_used = ?2",
+            params![command, at.format(DATETIME_FORMAT).to_string()],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_usage(&self) -> Result<Vec<(String, i64, NaiveDateTime)>> {
+        let conn = &self.conn;
+        let mut stmt = conn
+            .prepare("SELECT command, count, last_used FROM usage ORDER BY count DESC, command")?;
+
+        let usage = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    NaiveDateTime::parse_from_str(&row.get::<_, String>(2)?, DATETIME_FORMAT)
+                        .unwrap(),
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(usage)
+    }
+
+    pub fn save_plan_block(&self, block: &PlanBlock) -> Result<()> {
+        let conn = &self.conn;
+
+        conn.execute(
+            "INSERT INTO plan (start, end, label) VALUES (?1, ?2, ?3)",
+            params![
+                block.start.format(DATETIME_FORMAT).to_string(),
+                block.end.format(DATETIME_FORMAT).to_string(),
+                block.label,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_all_plan(&self) -> Result<Vec<PlanBlock>> {
+        let conn = &self.conn;
+        let mut stmt = conn.prepare("SELECT id, start, end, label FROM plan ORDER BY start, id")?;
+
+        let blocks = stmt
+            .query_map([], PlanBlock::from_row)?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(blocks)
+    }
+
+    pub fn get_plan_between(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<PlanBlock>> {
+        let conn = &self.conn;
+        let mut stmt = conn.prepare(
+        "SELECT id, start, end, label FROM plan WHERE date(start) BETWEEN ?1 AND ?2 ORDER BY start",
+    )?;
+
+        let blocks = stmt
+            .query_map(
+                params![
+                    from.format("%Y-%m-%d").to_string(),
+                    to.format("%Y-%m-%d").to_string()
+                ],
+                PlanBlock::from_row,
+            )?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_is_idempotent() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn).unwrap();
+        migrate(&conn).unwrap();
+
+        let version: i32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+
+        conn.execute(
+            "INSERT INTO record (check_in, check_out) VALUES ('2024-07-08 09:00:00', NULL)",
+            [],
+        )
+        .unwrap();
+        let record = conn.query_row(RECORD_QUERY, [], Record::from_row).unwrap();
+        assert!(record.is_open());
+        assert_eq!(record.unpaid_break, Duration::zero());
+    }
+
+    #[test]
+    fn test_history() {
+        let db = Db::open_in_memory().unwrap();
+        let conn = &db.conn;
+
+        let check_in = Record::test_time(8, 9);
+        let opened = insert_entry(conn, &Record::test_entry(check_in, None)).unwrap();
+        let none = Children::default();
+        log_operation(conn, &[], &none, std::slice::from_ref(&opened)).unwrap();
+        let closed = Record {
+            check_out: Some(check_in + Duration::hours(3)),
+            project: Some("acme".to_string()),
+            ..opened.clone()
+        };
+        log_operation(
+            conn,
+            std::slice::from_ref(&opened),
+            &none,
+            std::slice::from_ref(&closed),
+        )
+        .unwrap();
+        log_operation(conn, std::slice::from_ref(&closed), &none, &[]).unwrap();
+
+        let history = db.get_history(opened.id).unwrap();
+        assert_eq!(history.len(), 3);
+        assert!(history[0].before.is_none());
+        assert!(history[2].after.is_none());
+        let (before, after) = (
+            history[1].before.as_ref().unwrap(),
+            history[1].after.as_ref().unwrap(),
+        );
+        assert_eq!(
+            after.changes_from(before),
+            vec![
+                ("check_out", "-".to_string(), "2024-07-08 12:00".to_string()),
+                ("project", "-".to_string(), "acme".to_string()),
+            ]
+        );
+        assert!(db.get_history(opened.id + 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_undo() {
+        let db = Db::open_in_memory().unwrap();
+        let at = Record::test_time;
+        let entry = Record::test_entry;
+        assert!(db.undo_operation().unwrap().is_none());
+
+        // An add is undone by deleting the entry, its id is not handed out again
+        let added = db.save_entry(&entry(at(8, 9), Some(at(8, 12)))).unwrap();
+        let undone = db.undo_operation().unwrap().unwrap();
+        assert!(undone.before.is_empty());
+        assert_eq!(undone.after[0].id, added.id);
+        assert!(db.get_entry(added.id).unwrap().is_none());
+        let added = db.save_entry(&entry(at(8, 9), Some(at(8, 12)))).unwrap();
+        assert_eq!(added.id, undone.after[0].id + 1);
+
+        // An edit is undone by writing the entry back as it was
+        db.update_entry(&Record {
+            check_out: Some(at(8, 13)),
+            project: Some("acme".to_string()),
+            ..added.clone()
+        })
+        .unwrap();
+        db.undo_operation().unwrap().unwrap();
+        let restored = db.get_entry(added.id).unwrap().unwrap();
+        assert_eq!(restored.check_out, Some(at(8, 12)));
+        assert!(restored.project.is_none());
+
+        // A whole day deleted comes back with the ids it had
+        let second = db.save_entry(&entry(at(8, 13), Some(at(8, 17)))).unwrap();
+        let day = db.get_entries_by_date(at(8, 0).date()).unwrap();
+        db.delete_entries(&day).unwrap();
+        assert!(db.get_all_entries().unwrap().is_empty());
+        db.undo_operation().unwrap().unwrap();
+        let ids: Vec<i32> = db.get_all_entries().unwrap().iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![added.id, second.id]);
+
+        // A switch reopens the closed session and drops the one it opened
+        let open = db.save_entry(&entry(at(9, 9), None)).unwrap();
+        let opened = db
+            .switch_entry(
+                &Record {
+                    check_out: Some(at(9, 11)),
+                    ..open.clone()
+                },
+                &entry(at(9, 11), None),
+            )
+            .unwrap();
+        db.undo_operation().unwrap().unwrap();
+        assert!(db.get_entry(opened.id).unwrap().is_none());
+        assert!(db.get_entry(open.id).unwrap().unwrap().is_open());
+        assert_eq!(db.get_open_entry().unwrap().unwrap().id, open.id);
+
+        // A check-out is undone with the break it ended running again
+        db.save_break(&Break {
+            id: 0,
+            record_id: open.id,
+            start: at(9, 10),
+            end: None,
+            paid: false,
+        })
+        .unwrap();
+        db.close_entry(&Record {
+            check_out: Some(at(9, 12)),
+            ..open.clone()
+        })
+        .unwrap();
+        assert!(db.get_open_break(open.id).unwrap().is_none());
+        db.undo_operation().unwrap().unwrap();
+        assert_eq!(
+            db.get_open_break(open.id).unwrap().unwrap().start,
+            at(9, 10)
+        );
+
+        // A deleted entry comes back with its breaks and interruptions
+        db.save_interruption(&Interruption {
+            id: 0,
+            record_id: open.id,
+            at: at(9, 11),
+            reason: Some("call".to_string()),
+        })
+        .unwrap();
+        db.delete_entries(std::slice::from_ref(&open)).unwrap();
+        assert!(db.get_all_breaks().unwrap().is_empty());
+        db.undo_operation().unwrap().unwrap();
+        assert_eq!(db.get_all_breaks().unwrap()[0].record_id, open.id);
+        let interruptions = db.get_all_interruptions().unwrap();
+        assert_eq!(interruptions[0].reason.as_deref(), Some("call"));
+
+        // Each undo is logged in the history, newest last
+        let history = db.get_history(added.id).unwrap();
+        assert!(history.last().unwrap().after.is_some());
+    }
+
+    #[test]
+    fn test_daily_total_cache() {
+        let db = Db::open_in_memory().unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 7, 8).unwrap();
+        let at = |hour| Record::test_time(8, hour);
+        let cached = || -> i64 {
+            db.conn
+                .query_row("SELECT count(*) FROM daily_total", [], |row| row.get(0))
+                .unwrap()
+        };
+        let worked = || db.get_daily_totals(day, day).unwrap()[0].worked;
+
+        let record = db
+            .save_entry(&Record::test_entry(at(9), Some(at(12))))
+            .unwrap();
+        assert_eq!(worked(), Duration::hours(3));
+        assert_eq!(cached(), 1);
+
+        // Every write to the day's records or their breaks drops its row
+        db.save_entry(&Record {
+            id: 0,
+            check_in: at(13),
+            check_out: Some(at(14)),
+            ..record.clone()
+        })
+        .unwrap();
+        assert_eq!(cached(), 0);
+        assert_eq!(worked(), Duration::hours(4));
+
+        db.update_entry(&Record {
+            check_out: Some(at(13)),
+            ..record.clone()
+        })
+        .unwrap();
+        assert_eq!(cached(), 0);
+        assert_eq!(worked(), Duration::hours(5));
+
+        db.save_break(&Break {
+            id: 0,
+            record_id: record.id,
+            start: at(10),
+            end: Some(at(11)),
+            paid: false,
+        })
+        .unwrap();
+        assert_eq!(cached(), 0);
+        assert_eq!(worked(), Duration::hours(4));
+
+        // A break longer than its entry takes nothing off the others
+        let short = db
+            .save_entry(&Record {
+                id: 0,
+                check_in: at(15),
+                check_out: Some(at(16)),
+                ..record.clone()
+            })
+            .unwrap();
+        db.save_break(&Break {
+            id: 0,
+            record_id: short.id,
+            start: at(14),
+            end: Some(at(17)),
+            paid: false,
+        })
+        .unwrap();
+        assert_eq!(worked(), Duration::hours(4));
+        db.delete_entries(&[short]).unwrap();
+
+        db.delete_entries(&[db.get_entry(record.id).unwrap().unwrap()])
+            .unwrap();
+        assert_eq!(cached(), 0);
+        assert_eq!(worked(), Duration::hours(1));
+    }
+
+    #[test]
+    fn test_transaction() {
+        let db = Db::open_in_memory().unwrap();
+        let record = Record::test_entry(Record::test_time(8, 9), None);
+
+        // A failing step rolls back the writes before it, even those that
+        // ran in their own savepoint
+        let failed = db.transaction(|db| {
+            db.save_entry(&record)?;
+            Err::<(), _>(TimeKeeperError::NoCheckInRecord)
+        });
+        assert!(failed.is_err());
+        assert!(db.get_all_entries().unwrap().is_empty());
+
+        let saved = db.transaction(|db| Ok(db.save_entry(&record)?)).unwrap();
+        assert_eq!(db.get_all_entries().unwrap().len(), 1);
+        assert!(db.get_entry(saved.id).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_reporting_views() {
+        let db = Db::open_in_memory().unwrap();
+        db.conn
+            .execute_batch(
+                "INSERT INTO record (check_in, check_out, travel, project) VALUES
+                    ('2024-07-08 09:00:00', '2024-07-08 12:00:00', 0, 'acme'),
+                    ('2024-07-08 13:00:00', '2024-07-08 14:00:00', 1, NULL),
+                    ('2024-07-14 09:00:00', '2024-07-14 11:00:00', 0, NULL),
+                    ('2024-07-15 09:00:00', NULL, 0, 'acme');
+                INSERT INTO breaks (record_id, start, end, paid)
+                    VALUES (1, '2024-07-08 10:00:00', '2024-07-08 10:30:00', 0);",
+            )
+            .unwrap();
+
+        let day: (i64, i64, i64) = db
+            .conn
+            .query_row(
+                "SELECT worked_minutes, travel_minutes, entries FROM daily_totals
+                    WHERE day = '2024-07-08'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(day, (150, 60, 2));
+
+        // The Sunday belongs to the week that started on the Monday before
+        let week: (String, i64, i64) = db
+            .conn
+            .query_row(
+                "SELECT week_start, worked_minutes, days FROM weekly_totals",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(week, ("2024-07-08".to_string(), 270, 2));
+
+        let acme: (i64, i64) = db
+            .conn
+            .query_row(
+                "SELECT worked_minutes, entries FROM project_totals WHERE project = 'acme'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(acme, (150, 1));
+    }
+
+    #[test]
+    fn test_upgrade_backs_up_first() {
+        let path =
+            std::env::temp_dir().join(format!("timekeeper-migrate-{}.db", std::process::id()));
+        let backup = PathBuf::from(format!("{}.v0.bak", path.display()));
+        let _ = std::fs::remove_file(&path);
+
+        // A database from before versioning, with its separate date column
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE record (id integer primary key, check_in text, check_out text, date text);
+            INSERT INTO record (check_in, check_out, date) VALUES ('09:00:00', '12:00:00', '2024-07-08');",
+        )
+        .unwrap();
+        migrate(&conn).unwrap();
+
+        let record = conn.query_row(RECORD_QUERY, [], Record::from_row).unwrap();
+        assert_eq!(record.duration(), Duration::hours(3));
+        let old: String = Connection::open(&backup)
+            .unwrap()
+            .query_row("SELECT date FROM record", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(old, "2024-07-08");
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&backup).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::Duration;
+
+use crate::app::TimeKeeperError;
+use crate::db::Record;
+use crate::import::read_records;
+use crate::report::{format_delta, format_duration, format_hours};
+
+// What changed between two exports of the same entries
+#[derive(Debug, Default)]
+pub struct RecordDiff {
+    pub added: Vec<Record>,
+    pub removed: Vec<Record>,
+    // The old version of each entry, then the new one
+    pub changed: Vec<(Record, Record)>,
+}
+
+impl RecordDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+// Entries are the same when their ids match. Exports without ids, like CSV,
+// are matched on the check-in instead.
+fn same_entry(a: &Record, b: &Record) -> bool {
+    if a.id != 0 && b.id != 0 {
+        a.id == b.id
+    } else {
+        a.check_in == b.check_in
+    }
+}
+
+pub fn diff_records(old: &[Record], new: &[Record]) -> RecordDiff {
+    let mut diff = RecordDiff::default();
+    for record in new {
+        match old.iter().find(|o| same_entry(o, record)) {
+            Some(previous) => {
+                if !record.changes_from(previous).is_empty()
+                    || record.duration() != previous.duration()
+                {
+                    diff.changed.push((previous.clone(), record.clone()));
+                }
+            }
+            None => diff.added.push(record.clone()),
+        }
+    }
+    diff.removed = old
+        .iter()
+        .filter(|o| !new.iter().any(|record| same_entry(o, record)))
+        .cloned()
+        .collect();
+
+    diff.added.sort_by_key(|r| r.check_in);
+    diff.removed.sort_by_key(|r| r.check_in);
+    diff.changed.sort_by_key(|(_, r)| r.check_in);
+    diff
+}
+
+// Worked time per project, with "unset" for entries without one
+fn project_totals(records: &[Record]) -> BTreeMap<String, Duration> {
+    let mut totals = BTreeMap::new();
+    for record in records.iter().filter(|r| !r.travel) {
+        let project = record
+            .project
+            .clone()
+            .unwrap_or_else(|| "unset".to_string());
+        *totals.entry(project).or_insert_with(Duration::zero) += record.duration();
+    }
+    totals
+}
+
+// CSV exports carry no ids, their records are shown without one
+fn describe(record: &Record) -> String {
+    let id = match record.id {
+        0 => String::new(),
+        id => format!("{}  ", id),
+    };
+    format!(
+        "{}{} {}-{}  {}h  {}",
+        id,
+        record.check_in.format("%Y-%m-%d"),
+        record.check_in.format("%H:%M"),
+        record
+            .check_out
+            .map(|t| t.format("%H:%M").to_string())
+            .unwrap_or_else(|| "open".to_string()),
+        format_hours(record.duration()),
+        record.project.as_deref().unwrap_or("unset")
+    )
+}
+
+// Compares two exports, e.g. the timesheet last sent to a client and one
+// taken after corrections. Either may be JSON or CSV written by TimeKeeper.
+pub fn handle_report_diff(old: &Path, new: &Path) -> Result<(), TimeKeeperError> {
+    let (old, new) = (read_records(old)?, read_records(new)?);
+    let diff = diff_records(&old, &new);
+    if diff.is_empty() {
+        println!("No differences");
+        return Ok(());
+    }
+
+    if !diff.added.is_empty() {
+        println!("Added:");
+        for record in &diff.added {
+            println!("  + {}", describe(record));
+        }
+    }
+    if !diff.removed.is_empty() {
+        println!("Removed:");
+        for record in &diff.removed {
+            println!("  - {}", describe(record));
+        }
+    }
+    if !diff.changed.is_empty() {
+        println!("Changed:");
+        for (previous, record) in &diff.changed {
+            println!("  ~ {}", describe(record));
+            for (field, before, after) in record.changes_from(previous) {
+                println!("      {}: {} -> {}", field, before, after);
+            }
+            if record.duration() != previous.duration() {
+                println!(
+                    "      duration: {}h -> {}h",
+                    format_hours(previous.duration()),
+                    format_hours(record.duration())
+                );
+            }
+        }
+    }
+
+    let (old_totals, new_totals) = (project_totals(&old), project_totals(&new));
+    let total = |totals: &BTreeMap<String, Duration>| totals.values().copied().sum::<Duration>();
+    let (old_total, new_total) = (total(&old_totals), total(&new_totals));
+    println!();
+    println!(
+        "Worked: {} -> {} ({})",
+        format_duration(old_total),
+        format_duration(new_total),
+        format_delta(new_total - old_total)
+    );
+    let mut projects: Vec<&String> = old_totals.keys().chain(new_totals.keys()).collect();
+    projects.sort();
+    projects.dedup();
+    for project in projects {
+        let before = old_totals
+            .get(project)
+            .copied()
+            .unwrap_or_else(Duration::zero);
+        let after = new_totals
+            .get(project)
+            .copied()
+            .unwrap_or_else(Duration::zero);
+        if before != after {
+            println!("  {}: {}", project, format_delta(after - before));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_records() {
+        let at = |hour| Record::test_time(12, hour);
+        let record = |id, start, end| Record {
+            id,
+            ..Record::test_entry(at(start), Some(at(end)))
+        };
+
+        let old = [record(1, 9, 12), record(2, 13, 17), record(3, 18, 19)];
+        let new = [record(1, 9, 12), record(2, 13, 18), record(4, 19, 20)];
+        let diff = diff_records(&old, &new);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].id, 4);
+        assert_eq!(diff.removed[0].id, 3);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].1.duration(), Duration::hours(5));
+
+        // Without ids, as from CSV, entries are matched on their check-in
+        let (old, new) = ([record(0, 9, 12)], [record(0, 9, 13)]);
+        let diff = diff_records(&old, &new);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+
+        assert!(diff_records(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,741 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use clap::ValueEnum;
+use parquet::basic::Compression;
+use parquet::column::writer::ColumnWriterImpl;
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DataType, Int32Type, Int64Type};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use schemars::{schema_for, JsonSchema};
+use serde::Serialize;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::app::{load_policy, load_records, TimeKeeperError};
+use crate::config::Config;
+use crate::db::*;
+use crate::parser::get_today;
+use crate::paths::{ensure_dir, Paths};
+use crate::period::PeriodArgs;
+use crate::policy::Policy;
+use crate::report::{format_hours, format_money, record_earnings, DaySummary};
+
+// Shipped inside every bundle so the archive explains itself
+const BUNDLE_README: &str = "\
+TimeKeeper data export
+
+Each JSON file is an object with a schema_version and its list of items.
+'timekeeper schema <kind>' prints the JSON Schema for each of them.
+
+entries.json  every time entry, under records
+              id         entry id
+              check_in   start, local time as YYYY-MM-DDTHH:MM:SS
+              check_out  end, same format; null while the session is open
+              location   where the work happened, or null
+              travel     true for travel time kept out of worked totals
+              project    the project the time is booked on, or null
+              note       a free-form note on the entry, or null
+              unpaid_break_minutes
+                         unpaid breaks taken within the entry
+notes.json    per-day journal notes, under notes
+              id, date (YYYY-MM-DD), text
+breaks.json   breaks within entries, under breaks
+              id, record_id, start, end (null while running), paid
+interruptions.json
+              interruptions within entries, under interruptions
+              id, record_id, at, reason (or null)
+plan.json     planned time blocks, under plan
+              id, start, end, label
+invoices.json issued invoices, under invoices
+              number, project, from and to (YYYY-MM-DD), issued
+operations.json
+              the log of entry changes that undo reverses, under
+              operations: id, performed, and the entries touched as
+              they were before and after
+history.json  every change to each entry, kept through undo, under
+              history: record_id, changed, changed_by, and the entry
+              before and after (null when created or deleted)
+usage.json    local command usage counters, under usage
+              command, count, last_used (YYYY-MM-DDTHH:MM:SS)
+config.toml   the settings file, when one exists
+";
+
+// Version of the JSON written by exports and --json. It goes up whenever a
+// field is removed or changes meaning; added fields keep the version.
+pub const EXPORT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, JsonSchema)]
+pub struct Usage {
+    pub command: String,
+    pub count: i64,
+    pub last_used: NaiveDateTime,
+}
+
+// entries.json in the bundle
+#[derive(Serialize, JsonSchema)]
+pub struct EntriesExport {
+    pub schema_version: u32,
+    pub records: Vec<Record>,
+}
+
+// notes.json in the bundle
+#[derive(Serialize, JsonSchema)]
+pub struct NotesExport {
+    pub schema_version: u32,
+    pub notes: Vec<Note>,
+}
+
+// breaks.json in the bundle
+#[derive(Serialize, JsonSchema)]
+pub struct BreaksExport {
+    pub schema_version: u32,
+    pub breaks: Vec<Break>,
+}
+
+// interruptions.json in the bundle
+#[derive(Serialize, JsonSchema)]
+pub struct InterruptionsExport {
+    pub schema_version: u32,
+    pub interruptions: Vec<Interruption>,
+}
+
+// plan.json in the bundle
+#[derive(Serialize, JsonSchema)]
+pub struct PlanExport {
+    pub schema_version: u32,
+    pub plan: Vec<PlanBlock>,
+}
+
+// invoices.json in the bundle
+#[derive(Serialize, JsonSchema)]
+pub struct InvoicesExport {
+    pub schema_version: u32,
+    pub invoices: Vec<IssuedInvoice>,
+}
+
+// operations.json in the bundle
+#[derive(Serialize, JsonSchema)]
+pub struct OperationsExport {
+    pub schema_version: u32,
+    pub operations: Vec<Operation>,
+}
+
+// history.json in the bundle
+#[derive(Serialize, JsonSchema)]
+pub struct HistoryExport {
+    pub schema_version: u32,
+    pub history: Vec<HistoryEntry>,
+}
+
+// usage.json in the bundle
+#[derive(Serialize, JsonSchema)]
+pub struct UsageExport {
+    pub schema_version: u32,
+    pub usage: Vec<Usage>,
+}
+
+// Output of 'summary --json'
+#[derive(Serialize, JsonSchema)]
+pub struct SummaryExport {
+    pub schema_version: u32,
+    pub records: Vec<Record>,
+    pub days: Vec<DaySummary>,
+    pub worked_minutes: i64,
+    pub travel_minutes: i64,
+}
+
+// Output of 'status --json'
+#[derive(Serialize, JsonSchema)]
+pub struct StatusExport {
+    pub schema_version: u32,
+    pub open: bool,
+    pub check_in: Option<NaiveDateTime>,
+    pub project: Option<String>,
+    pub elapsed_minutes: Option<i64>,
+    pub on_break: bool,
+    pub today_minutes: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SchemaKind {
+    Entries,
+    Notes,
+    Breaks,
+    Interruptions,
+    Plan,
+    Invoices,
+    Operations,
+    History,
+    Usage,
+    Summary,
+    Status,
+}
+
+// Prints the JSON Schema derived from the types the output is written from
+pub fn print_schema(kind: SchemaKind) -> Result<(), TimeKeeperError> {
+    let schema = match kind {
+        SchemaKind::Entries => schema_for!(EntriesExport),
+        SchemaKind::Notes => schema_for!(NotesExport),
+        SchemaKind::Breaks => schema_for!(BreaksExport),
+        SchemaKind::Interruptions => schema_for!(InterruptionsExport),
+        SchemaKind::Plan => schema_for!(PlanExport),
+        SchemaKind::Invoices => schema_for!(InvoicesExport),
+        SchemaKind::Operations => schema_for!(OperationsExport),
+        SchemaKind::History => schema_for!(HistoryExport),
+        SchemaKind::Usage => schema_for!(UsageExport),
+        SchemaKind::Summary => schema_for!(SummaryExport),
+        SchemaKind::Status => schema_for!(StatusExport),
+    };
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
+
+impl From<zip::result::ZipError> for TimeKeeperError {
+    fn from(err: zip::result::ZipError) -> Self {
+        TimeKeeperError::IoError(std::io::Error::other(err))
+    }
+}
+
+impl From<serde_json::Error> for TimeKeeperError {
+    fn from(err: serde_json::Error) -> Self {
+        TimeKeeperError::IoError(err.into())
+    }
+}
+
+impl From<ParquetError> for TimeKeeperError {
+    fn from(err: ParquetError) -> Self {
+        TimeKeeperError::IoError(std::io::Error::other(err))
+    }
+}
+
+fn write_json<T: Serialize>(
+    zip: &mut ZipWriter<File>,
+    name: &str,
+    value: &T,
+) -> Result<(), TimeKeeperError> {
+    zip.start_file(name, SimpleFileOptions::default())?;
+    serde_json::to_writer_pretty(&mut *zip, value)?;
+    zip.write_all(b"\n")?;
+    Ok(())
+}
+
+// Writes everything TimeKeeper stores about the user into one zip archive
+pub fn export_everything(db: &Db, output: &Path) -> Result<(), TimeKeeperError> {
+    let entries = db.get_all_entries()?;
+    let notes = db.get_all_notes()?;
+    let usage: Vec<Usage> = db
+        .get_usage()?
+        .into_iter()
+        .map(|(command, count, last_used)| Usage {
+            command,
+            count,
+            last_used,
+        })
+        .collect();
+
+    let mut zip = ZipWriter::new(File::create(output)?);
+    zip.start_file("README.txt", SimpleFileOptions::default())?;
+    zip.write_all(BUNDLE_README.as_bytes())?;
+    let (entry_count, note_count) = (entries.len(), notes.len());
+    write_json(
+        &mut zip,
+        "entries.json",
+        &EntriesExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            records: entries,
+        },
+    )?;
+    write_json(
+        &mut zip,
+        "notes.json",
+        &NotesExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            notes,
+        },
+    )?;
+    write_json(
+        &mut zip,
+        "breaks.json",
+        &BreaksExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            breaks: db.get_all_breaks()?,
+        },
+    )?;
+    write_json(
+        &mut zip,
+        "interruptions.json",
+        &InterruptionsExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            interruptions: db.get_all_interruptions()?,
+        },
+    )?;
+    write_json(
+        &mut zip,
+        "plan.json",
+        &PlanExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            plan: db.get_all_plan()?,
+        },
+    )?;
+    write_json(
+        &mut zip,
+        "invoices.json",
+        &InvoicesExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            invoices: db.get_invoices()?,
+        },
+    )?;
+    write_json(
+        &mut zip,
+        "operations.json",
+        &OperationsExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            operations: db.get_operations()?,
+        },
+    )?;
+    write_json(
+        &mut zip,
+        "history.json",
+        &HistoryExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            history: db.get_all_history()?,
+        },
+    )?;
+    write_json(
+        &mut zip,
+        "usage.json",
+        &UsageExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            usage,
+        },
+    )?;
+
+    let config = Paths::new()?.config_file();
+    if config.is_file() {
+        zip.start_file("config.toml", SimpleFileOptions::default())?;
+        zip.write_all(&std::fs::read(config)?)?;
+    }
+    zip.finish()?;
+
+    println!(
+        "Exported {} entries and {} notes to {}",
+        entry_count,
+        note_count,
+        output.display()
+    );
+    Ok(())
+}
+
+// Quotes a field only when it holds a delimiter, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// One row per entry in check-in order, durations in decimal hours like the
+// CSV reports so payroll spreadsheets can sum them directly. With a policy
+// the duration is the one its rules count, check-in and check-out stay as
+// recorded.
+// With hourly rates an earnings column follows the others.
+pub fn records_to_csv(
+    records: &[Record],
+    policy: Option<&Policy>,
+    rates: Option<&HashMap<String, f64>>,
+) -> String {
+    let mut sorted = records.to_vec();
+    sorted.sort_by_key(|record| record.check_in);
+
+    let mut header =
+        "date,check_in,check_out,duration,project,location,travel,note,unpaid_break_minutes"
+            .to_string();
+    if rates.is_some() {
+        header.push_str(",earnings");
+    }
+    let mut lines = vec![header];
+    for record in &sorted {
+        let duration = match policy {
+            Some(policy) => policy.count(record).duration(),
+            None => record.duration(),
+        };
+        let mut fields = vec![
+            // The calendar day, which import combines with the times
+            record.check_in.date().format("%Y-%m-%d").to_string(),
+            record.check_in.format("%H:%M").to_string(),
+            record
+                .check_out
+                .map(|t| t.format("%H:%M").to_string())
+                .unwrap_or_default(),
+            format_hours(duration),
+            csv_field(record.project.as_deref().unwrap_or("")),
+            csv_field(record.location.as_deref().unwrap_or("")),
+            record.travel.to_string(),
+            csv_field(record.note.as_deref().unwrap_or("")),
+            // So import can tell the break apart from the time worked
+            record.unpaid_break.num_minutes().to_string(),
+        ];
+        if let Some(rates) = rates {
+            fields.push(format_money(record_earnings(record, duration, rates)));
+        }
+        lines.push(fields.join(","));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+// Writes to the output file, or to stdout so the CSV can be piped
+pub fn export_csv(
+    db: &Db,
+    period: &PeriodArgs,
+    output: Option<&Path>,
+    raw: bool,
+) -> Result<(), TimeKeeperError> {
+    let records = load_records(db, period.resolve(get_today())?)?;
+    let rates = Config::load()?.rates;
+    let csv = records_to_csv(
+        &records,
+        load_policy(raw)?.as_ref(),
+        Some(&rates).filter(|rates| !rates.is_empty()),
+    );
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, csv)?;
+            println!("Exported {} entries to {}", records.len(), path.display());
+        }
+        None => print!("{}", csv),
+    }
+    Ok(())
+}
+
+// Times are local wall-clock times like everywhere else in TimeKeeper, so
+// the timestamps are not adjusted to UTC
+const ENTRIES_SCHEMA: &str = "
+    message entries {
+        REQUIRED INT32 id;
+        REQUIRED INT32 day (DATE);
+        REQUIRED INT64 check_in (TIMESTAMP(MILLIS,false));
+        OPTIONAL INT64 check_out (TIMESTAMP(MILLIS,false));
+        OPTIONAL INT64 duration_minutes;
+        REQUIRED INT64 unpaid_break_minutes;
+        REQUIRED BOOLEAN travel;
+        OPTIONAL INT32 project_id;
+        OPTIONAL INT32 location_id;
+        OPTIONAL BYTE_ARRAY note (UTF8);
+    }";
+
+const DIMENSION_SCHEMA: &str = "
+    message dimension {
+        REQUIRED INT32 id;
+        REQUIRED BYTE_ARRAY name (UTF8);
+    }";
+
+// A column of a Parquet table, None for NULL in an optional column
+enum ParquetColumn {
+    Int32(Vec<Option<i32>>),
+    Int64(Vec<Option<i64>>),
+    Boolean(Vec<Option<bool>>),
+    Text(Vec<Option<String>>),
+}
+
+fn write_column<T: DataType>(
+    writer: &mut ColumnWriterImpl<T>,
+    values: Vec<Option<T::T>>,
+) -> Result<(), TimeKeeperError> {
+    let levels: Vec<i16> = values.iter().map(|value| value.is_some() as i16).collect();
+    let present: Vec<T::T> = values.into_iter().flatten().collect();
+    // Required columns have no definition levels to write
+    let optional = writer.get_descriptor().max_def_level() > 0;
+    writer.write_batch(&present, optional.then_some(&levels[..]), None)?;
+    Ok(())
+}
+
+// Writes a single row group, the columns in the order of the schema
+fn write_parquet(
+    path: &Path,
+    schema: &str,
+    columns: Vec<ParquetColumn>,
+) -> Result<(), TimeKeeperError> {
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = SerializedFileWriter::new(
+        File::create(path)?,
+        Arc::new(parse_message_type(schema)?),
+        Arc::new(properties),
+    )?;
+    let mut row_group = writer.next_row_group()?;
+    for column in columns {
+        let mut writer = row_group
+            .next_column()?
+            .expect("the schema has a field for every column");
+        match column {
+            ParquetColumn::Int32(values) => write_column(writer.typed::<Int32Type>(), values)?,
+            ParquetColumn::Int64(values) => write_column(writer.typed::<Int64Type>(), values)?,
+            ParquetColumn::Boolean(values) => write_column(writer.typed::<BoolType>(), values)?,
+            ParquetColumn::Text(values) => write_column(
+                writer.typed::<ByteArrayType>(),
+                values
+                    .into_iter()
+                    .map(|value| value.map(|value| ByteArray::from(value.as_str())))
+                    .collect(),
+            )?,
+        }
+        writer.close()?;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+// Distinct names in order, each one's id being its position counted from 1
+fn dimension<'a>(names: impl Iterator<Item = Option<&'a str>>) -> Vec<String> {
+    let mut names: Vec<String> = names.flatten().map(str::to_string).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn dimension_id(names: &[String], name: Option<&str>) -> Option<i32> {
+    let name = name?;
+    names
+        .iter()
+        .position(|n| n == name)
+        .map(|index| index as i32 + 1)
+}
+
+fn column<T>(records: &[Record], value: impl Fn(&Record) -> Option<T>) -> Vec<Option<T>> {
+    records.iter().map(value).collect()
+}
+
+fn write_dimension(path: &Path, names: &[String]) -> Result<(), TimeKeeperError> {
+    write_parquet(
+        path,
+        DIMENSION_SCHEMA,
+        vec![
+            ParquetColumn::Int32((1..=names.len() as i32).map(Some).collect()),
+            ParquetColumn::Text(names.iter().cloned().map(Some).collect()),
+        ],
+    )
+}
+
+// Writes entries.parquet into the directory along with projects.parquet and
+// locations.parquet, the dimension tables its project_id and location_id
+// refer to. Durations are as recorded, ignoring any rounding.
+pub fn write_parquet_export(records: &[Record], dir: &Path) -> Result<(), TimeKeeperError> {
+    let mut records = records.to_vec();
+    records.sort_by_key(|record| record.check_in);
+    let projects = dimension(records.iter().map(|r| r.project.as_deref()));
+    let locations = dimension(records.iter().map(|r| r.location.as_deref()));
+
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("the epoch is a valid date");
+    let millis = |time: NaiveDateTime| time.and_utc().timestamp_millis();
+    ensure_dir(dir)?;
+    write_parquet(
+        &dir.join("entries.parquet"),
+        ENTRIES_SCHEMA,
+        vec![
+            ParquetColumn::Int32(column(&records, |r| Some(r.id))),
+            ParquetColumn::Int32(column(&records, |r| {
+                Some((r.date() - epoch).num_days() as i32)
+            })),
+            ParquetColumn::Int64(column(&records, |r| Some(millis(r.check_in)))),
+            ParquetColumn::Int64(column(&records, |r| r.check_out.map(millis))),
+            ParquetColumn::Int64(column(&records, |r| {
+                r.check_out.map(|_| r.duration().num_minutes())
+            })),
+            ParquetColumn::Int64(column(&records, |r| Some(r.unpaid_break.num_minutes()))),
+            ParquetColumn::Boolean(column(&records, |r| Some(r.travel))),
+            ParquetColumn::Int32(column(&records, |r| {
+                dimension_id(&projects, r.project.as_deref())
+            })),
+            ParquetColumn::Int32(column(&records, |r| {
+                dimension_id(&locations, r.location.as_deref())
+            })),
+            ParquetColumn::Text(column(&records, |r| r.note.clone())),
+        ],
+    )?;
+    write_dimension(&dir.join("projects.parquet"), &projects)?;
+    write_dimension(&dir.join("locations.parquet"), &locations)?;
+    Ok(())
+}
+
+pub fn export_parquet(db: &Db, period: &PeriodArgs, dir: &Path) -> Result<(), TimeKeeperError> {
+    let records = load_records(db, period.resolve(get_today())?)?;
+    write_parquet_export(&records, dir)?;
+    println!(
+        "Exported {} entries to {}",
+        records.len(),
+        dir.join("entries.parquet").display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Round;
+    use crate::report::RoundingMode;
+    use chrono::Duration;
+
+    #[test]
+    fn test_records_to_csv() {
+        let at = |hour| Record::test_time(12, hour);
+        let record = |start, end: Option<u32>, project: Option<&str>| Record {
+            project: project.map(str::to_string),
+            ..Record::test_entry(at(start), end.map(|end| at(end) + Duration::minutes(30)))
+        };
+
+        let records = [
+            record(13, None, None),
+            record(9, Some(12), Some("acme, inc")),
+        ];
+        assert_eq!(
+            records_to_csv(&records, None, None),
+            "date,check_in,check_out,duration,project,location,travel,note,unpaid_break_minutes\n\
+             2024-07-12,09:00,12:30,3.50,\"acme, inc\",,false,,0\n\
+             2024-07-12,13:00,,0.00,,,false,,0\n"
+        );
+        let mut round_down = Policy::default();
+        round_down.push(Box::new(Round {
+            increment: Duration::hours(1),
+            mode: RoundingMode::Down,
+        }));
+        assert_eq!(
+            records_to_csv(&records, Some(&round_down), None)
+                .lines()
+                .nth(1),
+            Some("2024-07-12,09:00,12:30,3.00,\"acme, inc\",,false,,0")
+        );
+        let rates = HashMap::from([("acme, inc".to_string(), 100.0)]);
+        assert_eq!(
+            records_to_csv(&records, None, Some(&rates)).lines().nth(1),
+            Some("2024-07-12,09:00,12:30,3.50,\"acme, inc\",,false,,0,350.00")
+        );
+    }
+
+    #[test]
+    fn test_parquet_export() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let at = |hour| Record::test_time(12, hour);
+        let record = |start, end: Option<u32>, project: Option<&str>| Record {
+            id: start as i32,
+            location: Some("office".to_string()),
+            project: project.map(str::to_string),
+            ..Record::test_entry(at(start), end.map(at))
+        };
+        let records = [
+            record(9, Some(12), Some("acme")),
+            record(13, Some(15), Some("globex")),
+            record(16, None, Some("acme")),
+        ];
+
+        let dir = std::env::temp_dir().join(format!("timekeeper-parquet-{}", std::process::id()));
+        write_parquet_export(&records, &dir).unwrap();
+        let rows = |name: &str| {
+            SerializedFileReader::new(File::open(dir.join(name)).unwrap())
+                .unwrap()
+                .metadata()
+                .file_metadata()
+                .num_rows()
+        };
+        assert_eq!(rows("entries.parquet"), 3);
+        assert_eq!(rows("projects.parquet"), 2);
+        assert_eq!(rows("locations.parquet"), 1);
+
+        let reader =
+            SerializedFileReader::new(File::open(dir.join("entries.parquet")).unwrap()).unwrap();
+        let rows: Vec<String> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().to_string())
+            .collect();
+        assert!(rows[0].contains("duration_minutes: 180"), "{}", rows[0]);
+        assert!(rows[1].contains("project_id: 2"), "{}", rows[1]);
+        assert!(rows[2].contains("check_out: null"), "{}", rows[2]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_everything() {
+        use std::io::Read;
+
+        let db = Db::open_in_memory().unwrap();
+        let at = |hour| Record::test_time(12, hour);
+        let date = at(0).date();
+        let record = db
+            .save_entry(&Record {
+                project: Some("acme".to_string()),
+                ..Record::test_entry(at(9), Some(at(17)))
+            })
+            .unwrap();
+        db.save_break(&Break {
+            id: 0,
+            record_id: record.id,
+            start: at(12),
+            end: Some(at(13)),
+            paid: false,
+        })
+        .unwrap();
+        db.save_invoice("acme", date, date, date).unwrap();
+
+        let file =
+            std::env::temp_dir().join(format!("timekeeper-bundle-{}.zip", std::process::id()));
+        export_everything(&db, &file).unwrap();
+        let mut zip = zip::ZipArchive::new(File::open(&file).unwrap()).unwrap();
+        let mut names: Vec<String> = zip
+            .file_names()
+            .map(|name| name.unwrap().into_owned())
+            .collect();
+        names.sort();
+        let mut expected = vec![
+            "README.txt",
+            "breaks.json",
+            "entries.json",
+            "history.json",
+            "interruptions.json",
+            "invoices.json",
+            "notes.json",
+            "operations.json",
+            "plan.json",
+            "usage.json",
+        ];
+        if names.iter().any(|name| name == "config.toml") {
+            expected.push("config.toml");
+            expected.sort();
+        }
+        assert_eq!(names, expected);
+
+        let mut json = |name: &str| {
+            let mut contents = String::new();
+            zip.by_name(name)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            serde_json::from_str::<serde_json::Value>(&contents).unwrap()
+        };
+        let breaks = json("breaks.json");
+        assert_eq!(breaks["schema_version"], EXPORT_SCHEMA_VERSION);
+        assert_eq!(breaks["breaks"][0]["record_id"], record.id);
+        assert_eq!(json("invoices.json")["invoices"][0]["number"], 1);
+        assert_eq!(
+            json("operations.json")["operations"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(json("history.json")["history"][0]["record_id"], record.id);
+        std::fs::remove_file(&file).unwrap();
+    }
+}
@@ -0,0 +1,397 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Deserialize;
+
+use crate::app::TimeKeeperError;
+use crate::db::*;
+use crate::export::EXPORT_SCHEMA_VERSION;
+use crate::parser::parse_time_str;
+
+// Splits one CSV line, honouring double-quoted fields with "" escapes
+pub(crate) fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+// Reads the CSV written by 'export csv'. Columns are matched by header name,
+// only date and check_in are required and unknown columns are ignored. The
+// duration column is not read back, unpaid_break_minutes gives the break.
+pub fn parse_csv_records(contents: &str) -> Result<Vec<Record>, TimeKeeperError> {
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<String> = lines
+        .next()
+        .map(split_csv_line)
+        .unwrap_or_default()
+        .iter()
+        .map(|name| name.trim().to_lowercase())
+        .collect();
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let (Some(date_column), Some(check_in_column)) = (column("date"), column("check_in")) else {
+        return Err(TimeKeeperError::ParseError(
+            "CSV needs a header with at least date and check_in columns".to_string(),
+        ));
+    };
+
+    let mut records = Vec::new();
+    for (index, line) in lines.enumerate() {
+        let fields = split_csv_line(line);
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|c| fields.get(c))
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+        };
+        let line_error =
+            |e: TimeKeeperError| TimeKeeperError::ParseError(format!("Line {}: {}", index + 2, e));
+
+        let date = field(Some(date_column))
+            .and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok())
+            .ok_or_else(|| line_error(TimeKeeperError::ParseError("invalid date".to_string())))?;
+        let time = |value: Option<String>| -> Result<Option<NaiveDateTime>, TimeKeeperError> {
+            match value {
+                Some(value) => Ok(Some(
+                    date.and_time(parse_time_str(&value).map_err(line_error)?),
+                )),
+                None => Ok(None),
+            }
+        };
+        let check_in = time(field(Some(check_in_column)))?.ok_or_else(|| {
+            line_error(TimeKeeperError::ParseError("missing check_in".to_string()))
+        })?;
+        // Times carry no date, so a check-out not after the check-in is on
+        // the next day, as for an overnight entry
+        let check_out = time(field(column("check_out")))?.map(|check_out| {
+            if check_out <= check_in {
+                check_out + Duration::days(1)
+            } else {
+                check_out
+            }
+        });
+
+        records.push(Record {
+            id: 0,
+            check_in,
+            check_out,
+            location: field(column("location")),
+            travel: field(column("travel")).is_some_and(|travel| travel == "true"),
+            project: field(column("project")),
+            note: field(column("note")),
+            unpaid_break: match field(column("unpaid_break_minutes")) {
+                Some(minutes) => Duration::minutes(minutes.parse().map_err(|_| {
+                    line_error(TimeKeeperError::ParseError(format!(
+                        "invalid unpaid_break_minutes '{}'",
+                        minutes
+                    )))
+                })?),
+                None => Duration::zero(),
+            },
+        });
+    }
+
+    Ok(records)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonRecords {
+    List(Vec<Record>),
+    // entries.json from 'export --everything' and 'summary --json'
+    Versioned {
+        #[serde(default)]
+        schema_version: u32,
+        records: Vec<Record>,
+    },
+}
+
+// Accepts a plain list of records, as older exports wrote, or an object with
+// them under records, e.g. entries.json from 'export --everything' or the
+// output of 'summary --json'
+pub fn parse_json_records(contents: &str) -> Result<Vec<Record>, TimeKeeperError> {
+    Ok(read_json_records(contents)?
+        .into_iter()
+        .map(|record| Record { id: 0, ..record })
+        .collect())
+}
+
+// Like parse_json_records but keeps the ids the records were exported with
+fn read_json_records(contents: &str) -> Result<Vec<Record>, TimeKeeperError> {
+    let records = match serde_json::from_str(contents)
+        .map_err(|e| TimeKeeperError::ParseError(format!("Invalid JSON records: {}", e)))?
+    {
+        JsonRecords::List(records) => records,
+        JsonRecords::Versioned {
+            schema_version,
+            records,
+        } => {
+            if schema_version > EXPORT_SCHEMA_VERSION {
+                return Err(TimeKeeperError::ParseError(format!(
+                    "Records use schema version {}, this version of timekeeper reads up to {}",
+                    schema_version, EXPORT_SCHEMA_VERSION
+                )));
+            }
+            records
+        }
+    };
+    Ok(records)
+}
+
+// Reads records exported as JSON or CSV, telling them apart by the file's
+// extension or else its first character. JSON records keep their ids, CSV
+// has none and its records come back with id 0.
+pub fn read_records(file: &Path) -> Result<Vec<Record>, TimeKeeperError> {
+    let contents = std::fs::read_to_string(file)?;
+    let is_json = match file.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("json"),
+        None => contents.trim_start().starts_with(['[', '{']),
+    };
+    if is_json {
+        read_json_records(&contents)
+    } else {
+        parse_csv_records(&contents)
+    }
+}
+
+// Records whose check-in already exists, locally or earlier in the file, are
+// skipped as duplicates. Only one session may be open, so an open record is
+// refused while another one is. With dry_run nothing is written. The new
+// records go in as one transaction, so Ctrl-C part way through leaves none
+// of them.
+pub fn handle_import(db: &Db, file: &Path, dry_run: bool) -> Result<(), TimeKeeperError> {
+    let records = read_records(file)?;
+
+    let existing = db.get_all_entries()?;
+    let mut seen: HashSet<NaiveDateTime> = existing.iter().map(|r| r.check_in).collect();
+    let mut open = existing.into_iter().find(|r| r.is_open());
+    let mut new_records = Vec::new();
+    let mut skipped = 0;
+    for record in records {
+        if let Some(check_out) = record.check_out {
+            if check_out <= record.check_in {
+                return Err(TimeKeeperError::CheckOutBeforeCheckIn);
+            }
+        }
+        if !seen.insert(record.check_in) {
+            skipped += 1;
+            continue;
+        }
+        if record.is_open() {
+            if let Some(open) = &open {
+                return Err(TimeKeeperError::ParseError(format!(
+                    "The entry from {} is open while the session started at {} still is, \
+                     check out of one first",
+                    record.check_in.format("%Y-%m-%d %H:%M"),
+                    open.check_in.format("%Y-%m-%d %H:%M")
+                )));
+            }
+            open = Some(record.clone());
+        }
+        new_records.push(record);
+    }
+
+    if dry_run {
+        for record in &new_records {
+            println!(
+                "Would import {} {}-{}",
+                record.date().format("%Y-%m-%d"),
+                record.check_in.format("%H:%M"),
+                record
+                    .check_out
+                    .map(|t| t.format("%H:%M").to_string())
+                    .unwrap_or_else(|| "open".to_string())
+            );
+        }
+        println!(
+            "Would import {} entries, skipped {} duplicates",
+            new_records.len(),
+            skipped
+        );
+        return Ok(());
+    }
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&cancelled);
+    // Fails only when a handler is already set, which then keeps its own
+    let _ = ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst));
+
+    // Drawn on stderr and hidden when that is not a terminal
+    let progress = ProgressBar::new(new_records.len() as u64).with_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} entries")
+            .expect("progress template is valid"),
+    );
+    let committed = db.save_entries(&new_records, |done| {
+        progress.set_position(done as u64);
+        !cancelled.load(Ordering::SeqCst)
+    })?;
+    progress.finish_and_clear();
+
+    if !committed {
+        println!("Import cancelled, nothing was imported");
+        return Ok(());
+    }
+    println!(
+        "Imported {} entries, skipped {} duplicates",
+        new_records.len(),
+        skipped
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_records() {
+        let records = parse_csv_records(
+            "date,check_in,check_out,duration,project,location,travel\n\
+             2024-07-12,09:00,12:30,3.50,\"acme, inc\",,false\n\
+             2024-07-12,13:00,,0.00,,home,true\n\
+             2024-07-12,22:00,06:00,8.00,,,false\n",
+        )
+        .unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].project.as_deref(), Some("acme, inc"));
+        assert_eq!(records[0].duration().num_minutes(), 210);
+        assert!(records[1].is_open());
+        assert!(records[1].travel);
+        assert_eq!(records[1].location.as_deref(), Some("home"));
+        // An overnight check-out is on the next day
+        assert_eq!(records[2].duration(), Duration::hours(8));
+        assert_eq!(records[2].end().date().to_string(), "2024-07-13");
+
+        assert!(parse_csv_records("day,start\n2024-07-12,09:00\n").is_err());
+        assert!(parse_csv_records("date,check_in\n2024-07-12,later\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_json_records() {
+        let entry = r#"{"id": 7, "check_in": "2024-07-12T09:00:00",
+            "check_out": null, "location": null, "travel": false, "project": "acme"}"#;
+
+        let records = parse_json_records(&format!("[{}]", entry)).unwrap();
+        assert_eq!(records[0].id, 0);
+        assert!(records[0].is_open());
+        assert_eq!(records[0].project.as_deref(), Some("acme"));
+
+        let summary = parse_json_records(&format!(r#"{{"records": [{}], "days": []}}"#, entry));
+        assert_eq!(summary.unwrap().len(), 1);
+
+        let versioned = |version| {
+            format!(
+                r#"{{"schema_version": {}, "records": [{}]}}"#,
+                version, entry
+            )
+        };
+        assert!(parse_json_records(&versioned(EXPORT_SCHEMA_VERSION)).is_ok());
+        assert!(parse_json_records(&versioned(EXPORT_SCHEMA_VERSION + 1)).is_err());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        use crate::export::EntriesExport;
+
+        let at = |hour| Record::test_time(12, hour);
+        let entry = |check_in, check_out| Record {
+            location: Some("office".to_string()),
+            project: Some("acme".to_string()),
+            ..Record::test_entry(check_in, check_out)
+        };
+        let source = Db::open_in_memory().unwrap();
+        let worked = source.save_entry(&entry(at(9), Some(at(17)))).unwrap();
+        source
+            .save_break(&Break {
+                id: 0,
+                record_id: worked.id,
+                start: at(12),
+                end: Some(at(13)),
+                paid: false,
+            })
+            .unwrap();
+        source.save_entry(&entry(at(18), None)).unwrap();
+
+        let file =
+            std::env::temp_dir().join(format!("timekeeper-import-{}.json", std::process::id()));
+        let exported = EntriesExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            records: source.get_all_entries().unwrap(),
+        };
+        std::fs::write(&file, serde_json::to_string(&exported).unwrap()).unwrap();
+
+        let target = Db::open_in_memory().unwrap();
+        handle_import(&target, &file, false).unwrap();
+        let imported = target.get_all_entries().unwrap();
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[0].unpaid_break, Duration::hours(1));
+        assert_eq!(imported[0].duration(), Duration::hours(7));
+        assert_eq!(imported[0].project.as_deref(), Some("acme"));
+        assert!(imported[1].is_open());
+
+        // Importing again skips every entry as a duplicate
+        handle_import(&target, &file, false).unwrap();
+        assert_eq!(target.get_all_entries().unwrap().len(), 2);
+
+        // An open entry is refused while another session is open
+        let busy = Db::open_in_memory().unwrap();
+        busy.save_entry(&entry(at(8) - Duration::days(1), None))
+            .unwrap();
+        assert!(handle_import(&busy, &file, false).is_err());
+        assert_eq!(busy.get_all_entries().unwrap().len(), 1);
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        use crate::export::records_to_csv;
+
+        let at = |hour| Record::test_time(12, hour);
+        let source = Db::open_in_memory().unwrap();
+        let shift = source
+            .save_entry(&Record::test_entry(at(6), Some(at(8))))
+            .unwrap();
+        source
+            .save_break(&Break {
+                id: 0,
+                record_id: shift.id,
+                start: at(7),
+                end: Some(at(8)),
+                paid: false,
+            })
+            .unwrap();
+
+        let csv = records_to_csv(&source.get_all_entries().unwrap(), None, None);
+        let file =
+            std::env::temp_dir().join(format!("timekeeper-import-{}.csv", std::process::id()));
+        std::fs::write(&file, &csv).unwrap();
+
+        // The unpaid break comes back, so the entry still counts 1h, not 2h
+        let target = Db::open_in_memory().unwrap();
+        handle_import(&target, &file, false).unwrap();
+        let imported = target.get_all_entries().unwrap();
+        assert_eq!(imported[0].unpaid_break, Duration::hours(1));
+        assert_eq!(imported[0].duration(), Duration::hours(1));
+        assert_eq!(records_to_csv(&imported, None, None), csv);
+        std::fs::remove_file(&file).unwrap();
+    }
+}
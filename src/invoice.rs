@@ -0,0 +1,312 @@
+use std::path::Path;
+
+use chrono::{Duration, NaiveDate};
+use clap::ValueEnum;
+
+use crate::app::{load_counted_records, TimeKeeperError};
+use crate::config::Config;
+use crate::db::Db;
+use crate::parser::get_today;
+use crate::period::{Period, PeriodArgs};
+use crate::report::{format_hours, format_money, summarize_days};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InvoiceFormat {
+    Text,
+    Markdown,
+    Html,
+}
+
+#[derive(Debug)]
+pub struct Invoice {
+    // None for a draft, which does not use up a number
+    pub number: Option<i64>,
+    pub issued: NaiveDate,
+    pub project: String,
+    pub period: Period,
+    pub rate: f64,
+    pub tax_percent: f64,
+    pub currency: Option<String>,
+    // Hours worked on the project per day
+    pub lines: Vec<(NaiveDate, Duration)>,
+}
+
+impl Invoice {
+    fn amount(&self, hours: Duration) -> f64 {
+        hours.num_seconds() as f64 / 3600.0 * self.rate
+    }
+
+    pub fn subtotal(&self) -> f64 {
+        self.lines
+            .iter()
+            .map(|(_, hours)| self.amount(*hours))
+            .sum()
+    }
+
+    pub fn tax(&self) -> f64 {
+        self.subtotal() * self.tax_percent / 100.0
+    }
+
+    pub fn total(&self) -> f64 {
+        self.subtotal() + self.tax()
+    }
+
+    fn title(&self) -> String {
+        match self.number {
+            Some(number) => format!("Invoice {:04}", number),
+            None => "Invoice DRAFT".to_string(),
+        }
+    }
+
+    fn money(&self, amount: f64) -> String {
+        match &self.currency {
+            Some(currency) => format!("{} {}", format_money(amount), currency),
+            None => format_money(amount),
+        }
+    }
+
+    // Label and amount of the subtotal, tax and total lines
+    fn totals(&self) -> Vec<(String, String)> {
+        let mut totals = vec![("Subtotal".to_string(), self.money(self.subtotal()))];
+        if self.tax_percent != 0.0 {
+            totals.push((format!("Tax {}%", self.tax_percent), self.money(self.tax())));
+        }
+        totals.push(("Total".to_string(), self.money(self.total())));
+        totals
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![
+            self.title().to_uppercase(),
+            format!("Issued:  {}", self.issued.format("%Y-%m-%d")),
+            format!("Project: {}", self.project),
+            format!("Period:  {}", self.period),
+            String::new(),
+            format!("{:<12}{:>8}{:>10}{:>14}", "Date", "Hours", "Rate", "Amount"),
+        ];
+        for (date, hours) in &self.lines {
+            lines.push(format!(
+                "{:<12}{:>8}{:>10}{:>14}",
+                date.format("%Y-%m-%d"),
+                format_hours(*hours),
+                format_money(self.rate),
+                format_money(self.amount(*hours))
+            ));
+        }
+        lines.push("-".repeat(44));
+        for (label, amount) in self.totals() {
+            lines.push(format!("{:<30}{:>14}", label, amount));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut lines = vec![
+            format!("# {}", self.title()),
+            String::new(),
+            format!("- Issued: {}", self.issued.format("%Y-%m-%d")),
+            format!("- Project: {}", self.project),
+            format!("- Period: {}", self.period),
+            String::new(),
+            "| Date | Hours | Rate | Amount |".to_string(),
+            "|------|------:|-----:|-------:|".to_string(),
+        ];
+        for (date, hours) in &self.lines {
+            lines.push(format!(
+                "| {} | {} | {} | {} |",
+                date.format("%Y-%m-%d"),
+                format_hours(*hours),
+                format_money(self.rate),
+                format_money(self.amount(*hours))
+            ));
+        }
+        for (label, amount) in self.totals() {
+            lines.push(format!("| **{}** | | | **{}** |", label, amount));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    // A standalone page that prints cleanly from a browser
+    pub fn to_html(&self) -> String {
+        let mut rows = String::new();
+        for (date, hours) in &self.lines {
+            rows.push_str(&format!(
+                "      <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                date.format("%Y-%m-%d"),
+                format_hours(*hours),
+                format_money(self.rate),
+                format_money(self.amount(*hours))
+            ));
+        }
+        for (label, amount) in self.totals() {
+            rows.push_str(&format!(
+                "      <tr class=\"total\"><th colspan=\"3\">{}</th><td>{}</td></tr>\n",
+                escape_html(&label),
+                escape_html(&amount)
+            ));
+        }
+
+        format!(
+            "<!DOCTYPE html>
+<html>
+<head>
+  <meta charset=\"utf-8\">
+  <title>{title}</title>
+  <style>
+    body {{ font-family: sans-serif; margin: 2em; }}
+    table {{ border-collapse: collapse; }}
+    th, td {{ padding: 0.3em 1em; border-bottom: 1px solid #ccc; }}
+    td {{ text-align: right; }}
+    td:first-child, th {{ text-align: left; }}
+    tr.total th, tr.total td {{ font-weight: bold; }}
+  </style>
+</head>
+<body>
+  <h1>{title}</h1>
+  <p>Issued: {issued}<br>Project: {project}<br>Period: {period}</p>
+  <table>
+    <thead>
+      <tr><th>Date</th><th>Hours</th><th>Rate</th><th>Amount</th></tr>
+    </thead>
+    <tbody>
+{rows}    </tbody>
+  </table>
+</body>
+</html>
+",
+            title = escape_html(&self.title()),
+            issued = self.issued.format("%Y-%m-%d"),
+            project = escape_html(&self.project),
+            period = self.period,
+            rows = rows,
+        )
+    }
+
+    pub fn render(&self, format: InvoiceFormat) -> String {
+        match format {
+            InvoiceFormat::Text => self.to_text(),
+            InvoiceFormat::Markdown => self.to_markdown(),
+            InvoiceFormat::Html => self.to_html(),
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Bills the project's closed, non-travel entries in the period at its rate
+// from the config. Each invoice takes the next number unless it is a draft.
+pub fn handle_invoice(
+    db: &Db,
+    project: &str,
+    period: &PeriodArgs,
+    format: InvoiceFormat,
+    output: Option<&Path>,
+    draft: bool,
+    raw: bool,
+) -> Result<(), TimeKeeperError> {
+    let today = get_today();
+    let period = period
+        .resolve(today)?
+        .unwrap_or_else(|| Period::month_of(today));
+    let config = Config::load()?;
+    let rate = *config.rates.get(project).ok_or_else(|| {
+        TimeKeeperError::ParseError(format!(
+            "No hourly rate for project '{}', add it under [rates] in the config",
+            project
+        ))
+    })?;
+
+    let mut records = load_counted_records(db, Some(period), raw)?;
+    records.retain(|r| !r.travel && !r.is_open() && r.project.as_deref() == Some(project));
+    if records.is_empty() {
+        println!("No entries for {} in {}", project, period);
+        return Ok(());
+    }
+
+    let number = if draft {
+        None
+    } else {
+        Some(db.save_invoice(project, period.from, period.to, today)?)
+    };
+    let invoice = Invoice {
+        number,
+        issued: today,
+        project: project.to_string(),
+        period,
+        rate,
+        tax_percent: config.tax_percent.unwrap_or_default(),
+        currency: config.currency,
+        lines: summarize_days(&records)
+            .iter()
+            .map(|day| (day.date, day.worked))
+            .collect(),
+    };
+
+    let rendered = invoice.render(format);
+    match output {
+        Some(path) => {
+            std::fs::write(path, rendered)?;
+            println!("Wrote {} to {}", invoice.title(), path.display());
+        }
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice() -> Invoice {
+        let date = |day| NaiveDate::from_ymd_opt(2024, 7, day).unwrap();
+        Invoice {
+            number: Some(7),
+            issued: date(31),
+            project: "R&D".to_string(),
+            period: Period {
+                from: date(1),
+                to: date(31),
+            },
+            rate: 80.0,
+            tax_percent: 20.0,
+            currency: Some("EUR".to_string()),
+            lines: vec![
+                (date(8), Duration::hours(3)),
+                (date(9), Duration::minutes(90)),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_invoice_totals() {
+        let invoice = invoice();
+        assert_eq!(invoice.subtotal(), 360.0);
+        assert_eq!(invoice.tax(), 72.0);
+        assert_eq!(invoice.total(), 432.0);
+
+        let text = invoice.to_text();
+        assert!(text.starts_with("INVOICE 0007\n"));
+        assert!(text.contains("2024-07-09      1.50     80.00        120.00\n"));
+        assert!(text.ends_with("Total                             432.00 EUR\n"));
+
+        assert!(invoice
+            .to_markdown()
+            .contains("| 2024-07-08 | 3.00 | 80.00 | 240.00 |"));
+        assert!(invoice.to_html().contains("Project: R&amp;D"));
+
+        let draft = Invoice {
+            number: None,
+            tax_percent: 0.0,
+            ..invoice
+        };
+        assert!(draft.to_text().starts_with("INVOICE DRAFT\n"));
+        assert!(!draft.to_text().contains("Tax"));
+    }
+}
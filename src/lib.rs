@@ -1,3 +1,19 @@
 pub mod app;
+pub mod config;
 pub mod db;
+pub mod diff;
+pub mod export;
+pub mod import;
+pub mod invoice;
+pub mod onboard;
 pub mod parser;
+pub mod paths;
+pub mod period;
+pub mod policy;
+pub mod prompt;
+pub mod reconcile;
+pub mod report;
+pub mod service;
+pub mod shell;
+
+pub use service::TimeKeeper;
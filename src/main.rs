@@ -1,39 +1,828 @@
-use clap::Parser;
-use std::fs;
-use timekeeper::{app::*, db::*};
+use chrono::{Local, NaiveTime};
+use clap::builder::PossibleValuesParser;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use clap_complete::Shell;
+use std::path::PathBuf;
+use timekeeper::{
+    app::*,
+    config::Config,
+    db::*,
+    diff::handle_report_diff,
+    export::{export_csv, export_everything, export_parquet, print_schema, SchemaKind},
+    import::handle_import,
+    invoice::{handle_invoice, InvoiceFormat},
+    onboard::handle_onboard,
+    parser::{get_now, get_today, parse_duration_str, parse_time_str},
+    paths::set_data_dir,
+    period::{set_pay_cycle, PayCycle, Period, PeriodArgs},
+    reconcile::handle_reconcile,
+    report::{Dimension, MessageFormat, RoundingMode},
+    shell::run_shell,
+    TimeKeeper,
+};
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     check_in: Option<String>,
 
     check_out: Option<String>,
 
     #[arg(short, long)]
     date: Option<String>,
+
+    /// Close an already open session at the new check-in time
+    #[arg(long)]
+    switch: bool,
+
+    /// Where the work happens, e.g. office or home
+    #[arg(short, long)]
+    location: Option<String>,
+
+    /// Mark the entry as travel, kept out of worked totals
+    #[arg(long)]
+    travel: bool,
+
+    /// Project the time is booked on
+    #[arg(short, long)]
+    project: Option<String>,
+
+    /// The shift ends after midnight, on the day after it started
+    #[arg(long)]
+    overnight: bool,
+
+    /// Database file to use instead of the default location
+    #[arg(long, global = true, env = "TIMEKEEPER_DB", value_name = "FILE")]
+    db: Option<PathBuf>,
+
+    /// Keep the database, config and every other file in this directory
+    #[arg(long, global = true, env = "TIMEKEEPER_DATA_DIR", value_name = "DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Print status, summary and report output as JSON
+    #[arg(long, global = true)]
+    json: bool,
 }
 
-fn ensure_db_exists() -> Result<(), TimeKeeperError> {
-    if !fs::metadata("keeper.db").is_ok() {
-        create_table().map_err(TimeKeeperError::from)?;
-    }
+#[derive(Subcommand, Debug)]
+enum BreakAction {
+    /// Start a break, at the current time unless one is given
+    Start {
+        time: Option<String>,
+
+        /// The break is paid and stays part of the worked time
+        #[arg(long)]
+        paid: bool,
+    },
+    /// End the running break, at the current time unless one is given
+    End { time: Option<String> },
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check in, at the current time unless one is given
+    In {
+        /// HH:MM, 530pm or relative like now-15m, -15m or "20 minutes ago"
+        #[arg(allow_hyphen_values = true)]
+        time: Option<String>,
+
+        /// Day as YYYY-MM-DD, defaults to today
+        #[arg(short, long)]
+        date: Option<String>,
+
+        /// Close an already open session at the new check-in time
+        #[arg(long)]
+        switch: bool,
+
+        /// Where the work happens, e.g. office or home
+        #[arg(short, long)]
+        location: Option<String>,
+
+        /// Mark the entry as travel, kept out of worked totals
+        #[arg(long)]
+        travel: bool,
+
+        /// Project the time is booked on
+        #[arg(short, long)]
+        project: Option<String>,
+    },
+    /// Check out of the open session, at the current time unless one is given
+    Out {
+        /// HH:MM, 530pm or relative like now-15m, -15m or "20 minutes ago"
+        #[arg(allow_hyphen_values = true)]
+        time: Option<String>,
+
+        /// Day as YYYY-MM-DD, defaults to today
+        #[arg(short, long)]
+        date: Option<String>,
+
+        /// The shift ends after midnight, on the day after it started
+        #[arg(long)]
+        overnight: bool,
+
+        /// Check out of this session instead of the latest open one
+        #[arg(long)]
+        id: Option<i32>,
+    },
+    /// Record a completed entry
+    Add {
+        check_in: String,
+
+        check_out: String,
+
+        /// Day of the entry as MMDD, defaults to today
+        #[arg(short, long)]
+        date: Option<String>,
+
+        /// Where the work happens, e.g. office or home
+        #[arg(short, long)]
+        location: Option<String>,
+
+        /// Mark the entry as travel, kept out of worked totals
+        #[arg(long)]
+        travel: bool,
+
+        /// Project the time is booked on
+        #[arg(short, long)]
+        project: Option<String>,
+
+        /// The shift ends after midnight, on the day after it started
+        #[arg(long)]
+        overnight: bool,
+
+        /// Save the entry even if it overlaps another one
+        #[arg(long)]
+        force: bool,
+    },
+    /// Bill a project's hours at its configured rate, defaults to the current month
+    Invoice {
+        /// Project to bill
+        #[arg(short, long)]
+        project: String,
+
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        #[arg(long, value_enum, default_value = "text")]
+        format: InvoiceFormat,
+
+        /// File to write, prints to stdout when not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Preview the invoice without using up an invoice number
+        #[arg(long)]
+        draft: bool,
+
+        /// Bill recorded durations, ignoring the configured rules
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Propose an entry for a forgotten day from that weekday's usual hours
+    Backfill {
+        /// Day to fill in as MMDD
+        #[arg(short, long)]
+        date: String,
+
+        /// How many weeks back to take the usual hours from
+        #[arg(long, default_value_t = 8)]
+        weeks: u32,
+
+        /// Project the time is booked on
+        #[arg(short, long)]
+        project: Option<String>,
+    },
+    /// Markdown digest of projects, entry notes and day notes for a weekly review
+    Retro {
+        /// Period to cover, defaults to the current week
+        #[command(flatten)]
+        period: PeriodArgs,
+    },
+    /// Show a single day, today unless another is given
+    Day {
+        /// Day as YYYY-MM-DD, or an expression like yesterday
+        date: Option<String>,
+
+        /// Print a narrow plain-text receipt for a thermal printer
+        #[arg(long)]
+        receipt: bool,
+    },
+    /// Start or end a break within the open session
+    Break {
+        #[command(subcommand)]
+        action: BreakAction,
+    },
+    /// Whether a session is open today and how long it has been running
+    Status,
+    /// Show records with daily subtotals
+    Summary {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// Treat entries separated by at most this gap (e.g. 5m) as continuous
+        #[arg(long, value_name = "GAP")]
+        merge_gaps: Option<String>,
+
+        /// Keep every column however narrow the terminal is
+        #[arg(long, conflicts_with = "compact")]
+        wide: bool,
+
+        /// One line per day instead of one row per entry
+        #[arg(long)]
+        compact: bool,
+
+        /// Only entries booked on this project
+        #[arg(short, long)]
+        project: Option<String>,
+    },
+    /// Total tracked time grouped by one or two dimensions. With a period and
+    /// no --by, per-day totals with weekly averages and the daily target
+    Report {
+        /// Grouping dimension, pass twice for a pivot table (rows, then columns)
+        #[arg(long, value_enum)]
+        by: Vec<Dimension>,
+
+        /// Hours expected per day, e.g. 8h, overriding daily_target in the config
+        #[arg(long, value_name = "DURATION")]
+        target: Option<String>,
+
+        /// Print the report as CSV
+        #[arg(long)]
+        csv: bool,
+
+        /// Print the report as a chat message payload to post to a webhook,
+        /// covering this week unless a period is given
+        #[arg(long, value_enum, conflicts_with_all = ["by", "csv", "merge_gaps"])]
+        format: Option<MessageFormat>,
+
+        /// Treat entries separated by at most this gap (e.g. 5m) as continuous
+        #[arg(long, value_name = "GAP")]
+        merge_gaps: Option<String>,
+
+        /// Report recorded durations, ignoring the configured rules
+        #[arg(long)]
+        raw: bool,
+
+        #[command(flatten)]
+        period: PeriodArgs,
+    },
+    /// Percentage of tracked time per group, defaults to the current month
+    Breakdown {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// Grouping dimension
+        #[arg(long, value_enum, default_value = "weekday")]
+        by: Dimension,
+
+        /// Report recorded durations, ignoring the configured rules
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Effective hourly earnings per project from its rate or fixed-bid budget
+    Profitability {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// Count recorded durations, ignoring the configured rules
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Compare raw and rounded durations before applying a rounding policy
+    PreviewRounding {
+        /// Rounding increment, e.g. 15m
+        #[arg(long, default_value = "15m")]
+        round: String,
+
+        /// Rounding direction
+        #[arg(long, value_enum, default_value = "nearest")]
+        mode: RoundingMode,
+
+        /// Period to preview, defaults to the current month
+        #[command(flatten)]
+        period: PeriodArgs,
+    },
+    /// Show how a day would be counted without recording it
+    Simulate {
+        /// Check-in time
+        #[arg(long = "in", value_name = "TIME")]
+        check_in: String,
+
+        /// Check-out time
+        #[arg(long = "out", value_name = "TIME")]
+        check_out: String,
+
+        /// Total break time taken during the day, e.g. 45m
+        #[arg(long)]
+        breaks: Option<String>,
+
+        /// Rounding increment to apply to the worked time, e.g. 15m
+        #[arg(long)]
+        round: Option<String>,
+
+        /// Rounding direction
+        #[arg(long, value_enum, default_value = "nearest")]
+        mode: RoundingMode,
+
+        /// Hours expected for the day, defaults to daily_target from the config
+        #[arg(long)]
+        target: Option<String>,
+
+        /// The shift ends after midnight, on the day after it started
+        #[arg(long)]
+        overnight: bool,
+    },
+    /// Interactive prompt for quick check-ins and lookups
+    Shell,
+    /// List entries newest first, one per line
+    Log {
+        /// Show at most this many entries
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+
+        /// Only id, duration and relative date
+        #[arg(long, conflicts_with = "stat")]
+        oneline: bool,
+
+        /// Add duration bars and a totals line
+        #[arg(long)]
+        stat: bool,
+
+        #[command(flatten)]
+        period: PeriodArgs,
+    },
+    /// Days and time worked per location
+    Locations {
+        #[command(flatten)]
+        period: PeriodArgs,
+    },
+    /// Print where data, config, state, cache and logs are stored
+    Paths,
+    /// Add a journal note to a day, or list notes when no text is given
+    Note {
+        text: Option<String>,
+
+        /// Day of the note as MMDD, defaults to today
+        #[arg(short, long, requires = "text")]
+        date: Option<String>,
+
+        /// Only list notes containing this text
+        #[arg(long, value_name = "QUERY", conflicts_with = "text")]
+        search: Option<String>,
+
+        #[command(flatten)]
+        period: PeriodArgs,
+    },
+    /// Plan a block of time, or compare planned blocks with actual entries
+    Plan {
+        /// Day of the block as YYYY-MM-DD
+        #[arg(requires_all = ["range", "label"])]
+        date: Option<String>,
+
+        /// Planned time as START-END, e.g. 09:00-11:00
+        range: Option<String>,
+
+        /// What the block is for
+        label: Option<String>,
+
+        /// Period to compare, defaults to the current week
+        #[command(flatten)]
+        period: PeriodArgs,
+    },
+    /// How often each command has been used, counted locally only
+    Usage,
+    /// Import a timesheet kept in a spreadsheet (xlsx, xls, ods or csv),
+    /// guessing its columns and suggesting targets from its averages
+    Onboard {
+        file: PathBuf,
+
+        /// Import without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Compare per-day totals from an external DATE,HOURS CSV with local records
+    Reconcile {
+        file: PathBuf,
+
+        /// Differences up to this much are not reported, e.g. 15m
+        #[arg(long, default_value = "5m")]
+        tolerance: String,
+    },
+    /// List entries added, removed or changed between two JSON or CSV exports
+    ReportDiff { old: PathBuf, new: PathBuf },
+    /// Fix the times, day or note of an existing entry, by its id
+    Edit {
+        id: i32,
+
+        /// New check-in time
+        #[arg(long = "in", value_name = "TIME")]
+        check_in: Option<String>,
+
+        /// New check-out time
+        #[arg(long = "out", value_name = "TIME")]
+        check_out: Option<String>,
+
+        /// Move the entry to this day, as YYYY-MM-DD
+        #[arg(short, long)]
+        date: Option<String>,
+
+        /// Note on the entry, an empty string removes it
+        #[arg(long)]
+        note: Option<String>,
+
+        /// The shift ends after midnight, on the day after it started
+        #[arg(long)]
+        overnight: bool,
+
+        /// Save the change even if the entry then overlaps another one
+        #[arg(long)]
+        force: bool,
+    },
+    /// Count an interruption against the open session
+    Interrupt {
+        /// What caused it, e.g. slack or phone
+        reason: Option<String>,
+    },
+    /// Interruptions compared to the time worked, per day by default
+    Interruptions {
+        /// Dimension to group by
+        #[arg(long, value_enum, default_value = "day")]
+        by: Dimension,
+
+        /// Period to cover, defaults to the current month
+        #[command(flatten)]
+        period: PeriodArgs,
+    },
+    /// Check in if off the clock, check out if on it, at the current time
+    #[command(visible_alias = "now")]
+    Toggle {
+        /// Project to book the time on when checking in
+        #[arg(short, long)]
+        project: Option<String>,
+    },
+    /// Delete an entry by its id, or all entries of a day
+    Delete {
+        #[arg(required_unless_present = "date", conflicts_with = "date")]
+        id: Option<i32>,
+
+        /// Delete every entry of this day, as YYYY-MM-DD
+        #[arg(short, long)]
+        date: Option<String>,
+
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Reverse the latest check-in, check-out, add, edit, delete or import
+    Undo,
+    /// Every change made to an entry, when and by whom
+    History { id: i32 },
+    /// Print a shell completion script, e.g. source <(timekeeper completions bash)
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the JSON Schema of an export or --json output
+    Schema {
+        #[arg(value_enum)]
+        kind: SchemaKind,
+    },
+    /// Import entries from a CSV or JSON file, skipping ones already recorded
+    Import {
+        file: PathBuf,
+
+        /// Only report what would be imported
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Export stored data to a file
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Export {
+        #[command(subcommand)]
+        format: Option<ExportFormat>,
+
+        /// Every entry, note, usage counter and the config as a zip of JSON files
+        #[arg(long, required = true, requires = "output")]
+        everything: bool,
+
+        /// File to write
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ExportFormat {
+    /// Entries as CSV, one row per entry
+    Csv {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// File to write, prints to stdout when not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Export recorded durations, ignoring the configured rules
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Entries as Parquet with project and location tables, for DuckDB,
+    /// pandas or Polars
+    Parquet {
+        #[command(flatten)]
+        period: PeriodArgs,
+
+        /// Directory to write the files to
+        #[arg(short, long, value_name = "DIR")]
+        output: PathBuf,
+    },
+}
+
+// Offers the projects as values wherever a project is asked for
+fn with_projects(command: clap::Command, projects: &[String]) -> clap::Command {
+    command
+        .mut_args(|arg| match arg.get_id().as_str() {
+            "project" => arg.value_parser(PossibleValuesParser::new(projects.iter().cloned())),
+            _ => arg,
+        })
+        .mut_subcommands(|subcommand| with_projects(subcommand, projects))
+}
+
+// Project names are completed from the ones in the database as the script
+// is generated, so sourcing it at shell startup keeps them current
+fn print_completions(db: &Db, shell: Shell) -> Result<(), TimeKeeperError> {
+    let mut command = with_projects(Args::command(), &db.get_projects()?);
+    clap_complete::generate(shell, &mut command, "timekeeper", &mut std::io::stdout());
     Ok(())
 }
 
-fn main() -> Result<(), TimeKeeperError> {
-    ensure_db_exists()?;
+// Errors are shown in their readable form rather than the Debug one main
+// would print when returning them
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), TimeKeeperError> {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    if let Some(dir) = &args.data_dir {
+        set_data_dir(dir.clone());
+    }
+    if let Some(db) = &args.db {
+        set_db_path(db.clone());
+    }
+    let config = Config::load()?;
+    if let Some(start) = &config.day_starts_at {
+        set_day_start(parse_time_str(start)? - NaiveTime::MIN);
+    }
+    if let Some(kind) = &config.pay_period {
+        set_pay_cycle(PayCycle::parse(kind, config.pay_period_start.as_deref())?);
+    }
+    // One connection serves the whole command
+    let keeper = TimeKeeper::new(Db::open_default()?);
+    let db = keeper.db();
+
+    let name = match (matches.subcommand_name(), &args.check_in, &args.check_out) {
+        (Some(name), _, _) => name,
+        (None, Some(_), None) => "in",
+        (None, None, Some(_)) => "out",
+        (None, Some(_), Some(_)) => "add",
+        (None, None, None) => "summary",
+    };
+    // Counting is best effort and must never get in the way of the command
+    let _ = db.record_usage(name, Local::now().naive_local());
+
+    if let Some(command) = args.command {
+        return match command {
+            Command::In {
+                time,
+                date,
+                switch,
+                location,
+                travel,
+                project,
+            } => handle_check_in(
+                &keeper,
+                &time.unwrap_or_else(get_now),
+                date,
+                switch,
+                location,
+                travel,
+                project,
+            ),
+            Command::Out {
+                time,
+                date,
+                overnight,
+                id,
+            } => handle_check_out(&keeper, &time.unwrap_or_else(get_now), date, overnight, id),
+            Command::Add {
+                check_in,
+                check_out,
+                date,
+                location,
+                travel,
+                project,
+                overnight,
+                force,
+            } => handle_record(
+                &keeper, &check_in, &check_out, date, location, travel, project, overnight, force,
+            ),
+            Command::Invoice {
+                project,
+                period,
+                format,
+                output,
+                draft,
+                raw,
+            } => handle_invoice(db, &project, &period, format, output.as_deref(), draft, raw),
+            Command::Backfill {
+                date,
+                weeks,
+                project,
+            } => handle_backfill(&keeper, &date, weeks, project),
+            Command::Summary {
+                period,
+                merge_gaps,
+                compact: true,
+                project,
+                ..
+            } if !args.json => display_compact_summary(db, &period, merge_gaps, project),
+            Command::Summary {
+                period,
+                merge_gaps,
+                project,
+                wide,
+                ..
+            } => display_summary(db, &period, merge_gaps, project, wide, args.json),
+            Command::Report {
+                by,
+                target,
+                csv,
+                format,
+                merge_gaps,
+                raw,
+                period,
+            } => match period.resolve(get_today())? {
+                Some(resolved) if by.is_empty() && !csv && merge_gaps.is_none() => {
+                    handle_period_report(db, resolved, target, raw, args.json, format)
+                }
+                None if format.is_some() => handle_period_report(
+                    db,
+                    Period::week_of(get_today()),
+                    target,
+                    raw,
+                    args.json,
+                    format,
+                ),
+                _ if by.is_empty() => handle_report(
+                    db,
+                    &[Dimension::Day],
+                    csv,
+                    merge_gaps,
+                    &period,
+                    raw,
+                    args.json,
+                ),
+                _ => handle_report(db, &by, csv, merge_gaps, &period, raw, args.json),
+            },
+            Command::Breakdown { period, by, raw } => handle_breakdown(db, &period, by, raw),
+            Command::Profitability { period, raw } => handle_profitability(db, &period, raw),
+            Command::PreviewRounding {
+                round,
+                mode,
+                period,
+            } => handle_preview_rounding(db, &round, mode, &period),
+            Command::Simulate {
+                check_in,
+                check_out,
+                breaks,
+                round,
+                mode,
+                target,
+                overnight,
+            } => handle_simulate(
+                db, &check_in, &check_out, breaks, round, mode, target, overnight,
+            ),
+            Command::Shell => run_shell(&keeper),
+            Command::Log {
+                limit,
+                oneline,
+                stat,
+                period,
+            } => handle_log(db, limit, oneline, stat, &period),
+            Command::Locations { period } => handle_locations(db, &period),
+            Command::Paths => display_paths(),
+            Command::Note {
+                text: Some(text),
+                date,
+                ..
+            } => handle_note(db, &text, date),
+            Command::Note {
+                text: None,
+                search,
+                period,
+                ..
+            } => display_notes(db, &period, search),
+            Command::Status => display_status(&keeper, args.json),
+            Command::Plan {
+                date: Some(date),
+                range: Some(range),
+                label: Some(label),
+                ..
+            } => handle_plan(db, &date, &range, &label),
+            Command::Plan { period, .. } => display_plan(db, &period),
+            Command::Usage => display_usage(db),
+            Command::Edit {
+                id,
+                check_in,
+                check_out,
+                date,
+                note,
+                overnight,
+                force,
+            } => handle_edit(db, id, check_in, check_out, date, note, overnight, force),
+            Command::Retro { period } => display_retro(db, &period),
+            Command::Day { date, receipt } => display_day(db, date, receipt),
+            Command::Break {
+                action: BreakAction::Start { time, paid },
+            } => handle_break_start(&keeper, &time.unwrap_or_else(get_now), paid),
+            Command::Break {
+                action: BreakAction::End { time },
+            } => handle_break_end(&keeper, &time.unwrap_or_else(get_now)),
+            Command::Interrupt { reason } => handle_interrupt(&keeper, reason),
+            Command::Interruptions { by, period } => display_interruptions(db, by, &period),
+            Command::Toggle { project } => handle_toggle(&keeper, project),
+            Command::Delete { id, date, yes } => handle_delete(db, id, date, yes),
+            Command::Undo => handle_undo(db),
+            Command::History { id } => handle_history(db, id),
+            Command::Completions { shell } => print_completions(db, shell),
+            Command::Schema { kind } => print_schema(kind),
+            Command::Import { file, dry_run } => handle_import(db, &file, dry_run),
+            Command::Onboard { file, yes } => handle_onboard(db, &file, yes),
+            Command::Reconcile { file, tolerance } => {
+                handle_reconcile(db, &file, parse_duration_str(&tolerance)?)
+            }
+            Command::ReportDiff { old, new } => handle_report_diff(&old, &new),
+            Command::Export {
+                format:
+                    Some(ExportFormat::Csv {
+                        period,
+                        output,
+                        raw,
+                    }),
+                ..
+            } => export_csv(db, &period, output.as_deref(), raw),
+            Command::Export {
+                format: Some(ExportFormat::Parquet { period, output }),
+                ..
+            } => export_parquet(db, &period, &output),
+            Command::Export {
+                output: Some(output),
+                ..
+            } => export_everything(db, &output),
+            Command::Export { .. } => unreachable!("clap requires --output with --everything"),
+        };
+    }
 
-    let args = Args::parse();
+    // The positional form predates the in, out and add subcommands and is
+    // kept so existing scripts and habits keep working
     match (args.check_in, args.check_out) {
         (Some(time), None) => {
-            handle_check_in(&time, args.date)?;
+            handle_check_in(
+                &keeper,
+                &time,
+                args.date,
+                args.switch,
+                args.location,
+                args.travel,
+                args.project,
+            )?;
         }
         (None, Some(time)) => {
-            handle_check_out(&time, args.date)?;
+            handle_check_out(&keeper, &time, args.date, args.overnight, None)?;
         }
         (Some(check_in), Some(check_out)) => {
-            handle_record(&check_in, &check_out, args.date)?;
+            handle_record(
+                &keeper,
+                &check_in,
+                &check_out,
+                args.date,
+                args.location,
+                args.travel,
+                args.project,
+                args.overnight,
+                false,
+            )?;
         }
-        (None, None) => display_summary()?,
+        (None, None) => display_summary(db, &PeriodArgs::default(), None, None, false, args.json)?,
     }
 
     Ok(())
@@ -0,0 +1,524 @@
+use std::path::Path;
+
+use calamine::{open_workbook_auto, Data, Reader};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::app::TimeKeeperError;
+use crate::db::{Db, Record};
+use crate::import::split_csv_line;
+use crate::parser::parse_time_str;
+use crate::prompt::confirm;
+use crate::report::{format_duration, format_hours};
+
+// Rows that give hours but no start time begin at this time of day
+const DEFAULT_START: NaiveTime = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+
+// Day orders tried on text cells, ISO first
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%d.%m.%Y", "%d/%m/%Y", "%m/%d/%Y", "%Y/%m/%d"];
+
+// A spreadsheet cell, read from a workbook or from CSV text
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Empty,
+    Text(String),
+    Number(f64),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    DateTime(NaiveDateTime),
+}
+
+impl Value {
+    pub fn from_text(text: &str) -> Value {
+        let text = text.trim();
+        if text.is_empty() {
+            return Value::Empty;
+        }
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S"))
+            .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M"))
+        {
+            return Value::DateTime(datetime);
+        }
+        for format in DATE_FORMATS {
+            if let Ok(date) = NaiveDate::parse_from_str(text, format) {
+                return Value::Date(date);
+            }
+        }
+        let lower = text.to_lowercase();
+        if text.contains(':') || lower.ends_with("am") || lower.ends_with("pm") {
+            if let Ok(time) = NaiveTime::parse_from_str(text, "%H:%M:%S")
+                .map_err(|_| ())
+                .or_else(|_| parse_time_str(text).map_err(|_| ()))
+            {
+                return Value::Time(time);
+            }
+        }
+        match text.replace(',', ".").parse() {
+            Ok(number) => Value::Number(number),
+            Err(_) => Value::Text(text.to_string()),
+        }
+    }
+
+    // Excel keeps dates and times as day counts, times alone below one day
+    fn from_cell(cell: &Data) -> Value {
+        match cell {
+            Data::Empty | Data::Error(_) => Value::Empty,
+            Data::Int(number) => Value::Number(*number as f64),
+            Data::Float(number) => Value::Number(*number),
+            Data::Bool(flag) => Value::Text(flag.to_string()),
+            Data::String(text) | Data::DateTimeIso(text) => Value::from_text(text),
+            Data::DurationIso(text) => Value::Text(text.clone()),
+            Data::DateTime(datetime) if datetime.is_duration() => {
+                Value::Number(datetime.as_f64() * 24.0)
+            }
+            Data::DateTime(datetime) => {
+                let (year, month, day, hour, minute, second, _) = datetime.to_ymd_hms_milli();
+                let time = NaiveTime::from_hms_opt(hour as u32, minute as u32, second as u32);
+                let date = NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32);
+                match (date, time) {
+                    (_, Some(time)) if datetime.as_f64() < 1.0 => Value::Time(time),
+                    (Some(date), Some(time)) if time == NaiveTime::MIN => Value::Date(date),
+                    (Some(date), Some(time)) => Value::DateTime(date.and_time(time)),
+                    _ => Value::Empty,
+                }
+            }
+        }
+    }
+
+    fn date(&self) -> Option<NaiveDate> {
+        match self {
+            Value::Date(date) => Some(*date),
+            Value::DateTime(datetime) => Some(datetime.date()),
+            _ => None,
+        }
+    }
+
+    fn time(&self) -> Option<NaiveTime> {
+        match self {
+            Value::Time(time) => Some(*time),
+            Value::DateTime(datetime) => Some(datetime.time()),
+            _ => None,
+        }
+    }
+
+    // A number of hours, or a time cell formatted as H:MM
+    fn hours(&self) -> Option<Duration> {
+        match self {
+            Value::Number(hours) if *hours > 0.0 && *hours <= 24.0 => {
+                Some(Duration::minutes((hours * 60.0).round() as i64))
+            }
+            Value::Time(time) => Some(*time - NaiveTime::MIN),
+            _ => None,
+        }
+    }
+
+    fn text(&self) -> Option<String> {
+        match self {
+            Value::Text(text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+}
+
+// Which column holds what, by index
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Columns {
+    pub date: Option<usize>,
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    pub hours: Option<usize>,
+    pub project: Option<usize>,
+    pub note: Option<usize>,
+}
+
+fn hinted(name: &str, hints: &[&str]) -> bool {
+    let name = name.to_lowercase();
+    name.split(|c: char| !c.is_alphanumeric())
+        .any(|word| hints.contains(&word))
+}
+
+// A column is of a kind when most of its filled cells are
+fn mostly(rows: &[Vec<Value>], column: usize, test: impl Fn(&Value) -> bool) -> bool {
+    let cells: Vec<&Value> = rows
+        .iter()
+        .filter_map(|row| row.get(column))
+        .filter(|value| **value != Value::Empty)
+        .collect();
+    !cells.is_empty() && cells.iter().filter(|value| test(value)).count() * 2 > cells.len()
+}
+
+// Guesses the columns from the header names where they help and otherwise
+// from what the cells hold: the first date column is the day, time columns
+// are the start and end in that order, and a number column of up to 24 is
+// the hours worked
+pub fn detect_columns(header: &[String], rows: &[Vec<Value>]) -> Columns {
+    let width = rows
+        .iter()
+        .map(Vec::len)
+        .chain([header.len()])
+        .max()
+        .unwrap_or(0);
+    let name = |column: usize| header.get(column).map(String::as_str).unwrap_or("");
+
+    let dates: Vec<usize> = (0..width)
+        .filter(|c| mostly(rows, *c, |v| v.date().is_some()))
+        .collect();
+    let times: Vec<usize> = (0..width)
+        .filter(|c| mostly(rows, *c, |v| v.time().is_some()))
+        .collect();
+    let texts: Vec<usize> = (0..width)
+        .filter(|c| mostly(rows, *c, |v| v.text().is_some()))
+        .collect();
+    let pick = |candidates: &[usize], hints: &[&str]| {
+        candidates.iter().copied().find(|c| hinted(name(*c), hints))
+    };
+
+    let mut columns = Columns {
+        date: pick(&dates, &["date", "day"]).or(dates.first().copied()),
+        hours: (0..width).find(|c| {
+            hinted(name(*c), &["hours", "duration", "worked", "total"])
+                && mostly(rows, *c, |v| v.hours().is_some())
+        }),
+        project: pick(&texts, &["project", "client", "customer", "job"]),
+        note: pick(
+            &texts,
+            &[
+                "note",
+                "notes",
+                "description",
+                "task",
+                "comment",
+                "comments",
+            ],
+        ),
+        ..Default::default()
+    };
+
+    let times: Vec<usize> = times
+        .into_iter()
+        .filter(|c| Some(*c) != columns.hours && Some(*c) != columns.date)
+        .collect();
+    columns.start = pick(
+        &times,
+        &["start", "in", "from", "begin", "began", "arrived"],
+    );
+    columns.end = pick(&times, &["end", "out", "to", "finish", "finished", "left"]);
+    let unnamed: Vec<usize> = times
+        .iter()
+        .copied()
+        .filter(|c| Some(*c) != columns.start && Some(*c) != columns.end)
+        .collect();
+    let mut unnamed = unnamed.into_iter();
+    if columns.start.is_none() {
+        columns.start = unnamed.next();
+    }
+    if columns.end.is_none() {
+        columns.end = unnamed.next();
+    }
+    if columns.hours.is_none() && columns.end.is_none() {
+        columns.hours = (0..width).find(|c| {
+            Some(*c) != columns.date
+                && Some(*c) != columns.start
+                && mostly(rows, *c, |v| {
+                    matches!(v, Value::Number(_)) && v.hours().is_some()
+                })
+        });
+    }
+    columns
+}
+
+// Turns each row into an entry. Rows without a day or without an end or
+// hours, such as blank or total lines, are skipped and their numbers
+// returned. An end before the start runs into the next day.
+pub fn interpret(rows: &[Vec<Value>], columns: &Columns) -> (Vec<Record>, Vec<usize>) {
+    let mut records = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, row) in rows.iter().enumerate() {
+        let cell = |column: Option<usize>| column.and_then(|c| row.get(c));
+        let start = cell(columns.start);
+        let date = cell(columns.date)
+            .and_then(Value::date)
+            .or_else(|| start.and_then(Value::date));
+        let Some(date) = date else {
+            skipped.push(index);
+            continue;
+        };
+
+        let check_in = date.and_time(start.and_then(Value::time).unwrap_or(DEFAULT_START));
+        let check_out = match (
+            cell(columns.end).and_then(Value::time),
+            cell(columns.hours).and_then(Value::hours),
+        ) {
+            (Some(end), _) => {
+                let end = date.and_time(end);
+                if end > check_in {
+                    end
+                } else {
+                    end + Duration::days(1)
+                }
+            }
+            (None, Some(hours)) => check_in + hours,
+            (None, None) => {
+                skipped.push(index);
+                continue;
+            }
+        };
+
+        records.push(Record {
+            id: 0,
+            check_in,
+            check_out: Some(check_out),
+            location: None,
+            travel: false,
+            project: cell(columns.project).and_then(Value::text),
+            note: cell(columns.note).and_then(Value::text),
+            unpaid_break: Duration::zero(),
+        });
+    }
+    (records, skipped)
+}
+
+// The first row is the header unless it already holds data
+fn split_header(mut rows: Vec<Vec<Value>>) -> (Vec<String>, Vec<Vec<Value>>) {
+    rows.retain(|row| row.iter().any(|value| *value != Value::Empty));
+    match rows.first() {
+        Some(first)
+            if first
+                .iter()
+                .all(|value| matches!(value, Value::Text(_) | Value::Empty)) =>
+        {
+            let header = rows
+                .remove(0)
+                .iter()
+                .map(|value| value.text().unwrap_or_default())
+                .collect();
+            (header, rows)
+        }
+        _ => (Vec::new(), rows),
+    }
+}
+
+// CSV is read as text, anything else as a workbook whose first sheet is used
+fn read_sheet(file: &Path) -> Result<Vec<Vec<Value>>, TimeKeeperError> {
+    let is_csv = file
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    if is_csv {
+        return Ok(std::fs::read_to_string(file)?
+            .lines()
+            .map(|line| {
+                split_csv_line(line)
+                    .iter()
+                    .map(|field| Value::from_text(field))
+                    .collect()
+            })
+            .collect());
+    }
+
+    let unreadable = |e: calamine::Error| {
+        TimeKeeperError::ParseError(format!("Cannot read {}: {}", file.display(), e))
+    };
+    let mut workbook = open_workbook_auto(file).map_err(unreadable)?;
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| TimeKeeperError::ParseError(format!("{} has no sheets", file.display())))?
+        .map_err(unreadable)?;
+    Ok(range
+        .rows()
+        .map(|row| row.iter().map(Value::from_cell).collect())
+        .collect())
+}
+
+// A, B, ... Z, AA like the spreadsheet labels them
+fn column_label(column: usize) -> String {
+    let mut label = String::new();
+    let mut n = column + 1;
+    while n > 0 {
+        label.insert(0, (b'A' + ((n - 1) % 26) as u8) as char);
+        n = (n - 1) / 26;
+    }
+    label
+}
+
+// Durations as the config writes them, e.g. 7h45m
+fn config_duration(duration: Duration) -> String {
+    match duration.num_minutes() % 60 {
+        0 => format!("{}h", duration.num_hours()),
+        minutes => format!("{}h{}m", duration.num_hours(), minutes),
+    }
+}
+
+// Average worked per day and per week with entries, to the quarter hour
+pub fn suggest_targets(records: &[Record]) -> Option<(Duration, Duration)> {
+    let mut days: Vec<NaiveDate> = records.iter().map(|r| r.check_in.date()).collect();
+    days.sort();
+    days.dedup();
+    let mut weeks: Vec<_> = days.iter().map(|d| d.iso_week()).collect();
+    weeks.dedup();
+    if days.is_empty() {
+        return None;
+    }
+
+    let total: Duration = records.iter().map(Record::duration).sum();
+    let quarter = |minutes: i64| Duration::minutes((minutes as f64 / 15.0).round() as i64 * 15);
+    Some((
+        quarter(total.num_minutes() / days.len() as i64),
+        quarter(total.num_minutes() / weeks.len() as i64),
+    ))
+}
+
+// Reads a sheet kept by hand, shows how its columns were understood and what
+// they turn into, then imports the entries once confirmed. Rows whose
+// check-in is already recorded are skipped as duplicates.
+pub fn handle_onboard(db: &Db, file: &Path, yes: bool) -> Result<(), TimeKeeperError> {
+    let (header, rows) = split_header(read_sheet(file)?);
+    let columns = detect_columns(&header, &rows);
+    if columns.date.is_none() || (columns.end.is_none() && columns.hours.is_none()) {
+        return Err(TimeKeeperError::ParseError(
+            "Could not find a date column with end times or hours in the sheet".to_string(),
+        ));
+    }
+
+    println!("Columns found:");
+    let detected = [
+        ("date", columns.date),
+        ("start", columns.start),
+        ("end", columns.end),
+        ("hours", columns.hours),
+        ("project", columns.project),
+        ("note", columns.note),
+    ];
+    for (field, column) in detected {
+        if let Some(column) = column {
+            match header.get(column).filter(|name| !name.is_empty()) {
+                Some(name) => println!("  {:<8} column {} ({})", field, column_label(column), name),
+                None => println!("  {:<8} column {}", field, column_label(column)),
+            }
+        }
+    }
+    if columns.start.is_none() {
+        println!(
+            "  No start times, entries begin at {}",
+            DEFAULT_START.format("%H:%M")
+        );
+    }
+
+    let (records, skipped) = interpret(&rows, &columns);
+    let existing: Vec<NaiveDateTime> = db.get_all_entries()?.iter().map(|r| r.check_in).collect();
+    let (duplicates, records): (Vec<Record>, Vec<Record>) = records
+        .into_iter()
+        .partition(|record| existing.contains(&record.check_in));
+
+    println!();
+    for record in records.iter().take(5) {
+        println!(
+            "  {} {}-{}  {}h  {}",
+            record.check_in.format("%Y-%m-%d"),
+            record.check_in.format("%H:%M"),
+            record.end().format("%H:%M"),
+            format_hours(record.duration()),
+            record.project.as_deref().unwrap_or("")
+        );
+    }
+    if records.len() > 5 {
+        println!("  ... and {} more", records.len() - 5);
+    }
+    println!(
+        "{} entries, {} rows skipped, {} already recorded",
+        records.len(),
+        skipped.len(),
+        duplicates.len()
+    );
+
+    if let Some((daily, weekly)) = suggest_targets(&records) {
+        println!();
+        println!(
+            "On average {} a day and {} a week. Suggested for config.toml:",
+            format_duration(daily),
+            format_duration(weekly)
+        );
+        println!("  daily_target = \"{}\"", config_duration(daily));
+        println!("  weekly_target = \"{}\"", config_duration(weekly));
+    }
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    println!();
+    // Without a terminal to ask on, nothing is imported unless --yes is given
+    if !yes && !confirm(&format!("Import {} entries?", records.len()), false)? {
+        println!("Nothing imported");
+        return Ok(());
+    }
+    db.save_entries(&records, |_| true)?;
+    println!("Imported {} entries", records.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet(csv: &str) -> (Vec<String>, Vec<Vec<Value>>) {
+        split_header(
+            csv.lines()
+                .map(|line| {
+                    split_csv_line(line)
+                        .iter()
+                        .map(|f| Value::from_text(f))
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_detect_columns() {
+        let (header, rows) = sheet(
+            "Day,Client,Arrived,Left,Task\n\
+             12/07/2024,acme,9:00,17:30,planning\n\
+             13/07/2024,acme,22:00,02:00,\n\
+             ,,,,\n\
+             Total,,,,\n",
+        );
+        let columns = detect_columns(&header, &rows);
+        assert_eq!(
+            columns,
+            Columns {
+                date: Some(0),
+                start: Some(2),
+                end: Some(3),
+                hours: None,
+                project: Some(1),
+                note: Some(4),
+            }
+        );
+
+        let (records, skipped) = interpret(&rows, &columns);
+        assert_eq!(records.len(), 2);
+        assert_eq!(skipped, vec![2]);
+        assert_eq!(records[0].project.as_deref(), Some("acme"));
+        assert_eq!(records[0].note.as_deref(), Some("planning"));
+        assert_eq!(records[0].duration(), Duration::minutes(510));
+        assert_eq!(records[1].duration(), Duration::hours(4));
+
+        // Only a day and the hours, without a header
+        let (header, rows) = sheet("2024-07-12,7.5\n2024-07-13,8\n");
+        let columns = detect_columns(&header, &rows);
+        assert_eq!((columns.date, columns.hours), (Some(0), Some(1)));
+        let (records, _) = interpret(&rows, &columns);
+        assert_eq!(records[0].check_in.time(), DEFAULT_START);
+        assert_eq!(
+            suggest_targets(&records),
+            Some((Duration::minutes(465), Duration::minutes(930)))
+        );
+    }
+
+    #[test]
+    fn test_column_label() {
+        assert_eq!(column_label(0), "A");
+        assert_eq!(column_label(25), "Z");
+        assert_eq!(column_label(26), "AA");
+    }
+}
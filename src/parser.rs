@@ -1,5 +1,6 @@
 use crate::app::TimeKeeperError;
-use chrono::{Datelike, Local, NaiveDate, NaiveTime, Timelike};
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use regex::Regex;
 
 impl std::fmt::Display for TimeKeeperError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -9,8 +10,14 @@ impl std::fmt::Display for TimeKeeperError {
             TimeKeeperError::CheckOutBeforeCheckIn => {
                 write!(f, "Check-out time before check-in time")
             }
-            TimeKeeperError::NoCheckInRecord => write!(f, "No check-in record found"),
+            TimeKeeperError::NoCheckInRecord => write!(f, "No open check-in session found"),
+            TimeKeeperError::AlreadyCheckedIn(time) => write!(
+                f,
+                "Already checked in since {}. Check out first or use --switch",
+                time.format("%H:%M")
+            ),
             TimeKeeperError::ParseError(msg) => write!(f, "Parse error: {}", msg),
+            TimeKeeperError::IoError(e) => write!(f, "IO error: {}", e),
         }
     }
 }
@@ -20,7 +27,7 @@ impl std::error::Error for TimeKeeperError {}
 pub fn parse_date_str(date_str: &str) -> Result<NaiveDate, TimeKeeperError> {
     let cleaned_date = date_str.replace('/', "");
 
-    if cleaned_date.len() != 4 || !cleaned_date.chars().all(|c| c.is_digit(10)) {
+    if cleaned_date.len() != 4 || !cleaned_date.chars().all(|c| c.is_ascii_digit()) {
         return Err(TimeKeeperError::ParseError(
             "Invalid date format. Use MMDD or MM/DD".to_string(),
         ));
@@ -40,8 +47,95 @@ pub fn parse_date_str(date_str: &str) -> Result<NaiveDate, TimeKeeperError> {
     })
 }
 
+// Parses short durations like 5m, 1h or 1h30m
+pub fn parse_duration_str(val: &str) -> Result<Duration, TimeKeeperError> {
+    let re = Regex::new(r"^(?:(\d+)h)?(?:(\d+)m)?$").unwrap();
+    let invalid = || {
+        TimeKeeperError::ParseError(format!(
+            "Invalid duration '{}'. Use minutes and/or hours like 5m, 1h or 1h30m",
+            val
+        ))
+    };
+
+    let caps = re
+        .captures(val.trim())
+        .filter(|caps| caps.get(1).is_some() || caps.get(2).is_some())
+        .ok_or_else(invalid)?;
+    let part = |index| {
+        caps.get(index)
+            .map_or(Ok(0), |m| m.as_str().parse::<i64>())
+            .map_err(|_| invalid())
+    };
+
+    Ok(Duration::hours(part(1)?) + Duration::minutes(part(2)?))
+}
+
+// An offset like 15m or 1h30m, or spelled out like 5 minutes or 2 hrs
+fn parse_offset(val: &str) -> Option<Duration> {
+    if let Ok(offset) = parse_duration_str(val) {
+        return Some(offset);
+    }
+    let re = Regex::new(r"^(\d+)\s*(minutes?|mins?|hours?|hrs?)$").unwrap();
+    let caps = re.captures(val)?;
+    let count: i64 = caps[1].parse().ok()?;
+    if caps[2].starts_with('h') {
+        Some(Duration::hours(count))
+    } else {
+        Some(Duration::minutes(count))
+    }
+}
+
+// Times relative to the clock: now, now-15m, now+1h, +30m, -15m or
+// 5 minutes ago. They carry their own date, so 20 minutes ago just after
+// midnight lands on the day before.
+fn parse_relative_time(val: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    if val == "now" {
+        return Some(now);
+    }
+    let (sign, offset) = match val.strip_suffix(" ago") {
+        Some(offset) => (-1, offset.trim()),
+        None => match val.strip_prefix("now").unwrap_or(val).trim_start() {
+            offset if offset.starts_with('+') => (1, offset[1..].trim()),
+            offset if offset.starts_with('-') => (-1, offset[1..].trim()),
+            _ => return None,
+        },
+    };
+    Some(now + parse_offset(offset)? * sign)
+}
+
+// The current minute, which relative times count from as the default of
+// now does
+fn current_minute() -> NaiveDateTime {
+    let now = Local::now().naive_local();
+    now.date().and_hms_opt(now.hour(), now.minute(), 0).unwrap()
+}
+
+// A time on the day given with --date, or else on the default day. A time
+// relative to the clock carries its own date, so it cannot be combined with
+// --date.
+pub fn parse_datetime_str(
+    val: &str,
+    date: Option<NaiveDate>,
+    default: NaiveDate,
+) -> Result<NaiveDateTime, TimeKeeperError> {
+    match parse_relative_time(&val.trim().to_lowercase(), current_minute()) {
+        Some(_) if date.is_some() => Err(TimeKeeperError::ParseError(format!(
+            "'{}' is relative to now and cannot be combined with --date",
+            val
+        ))),
+        Some(at) => Ok(at),
+        None => Ok(date.unwrap_or(default).and_time(parse_time_str(val)?)),
+    }
+}
+
+// A time of day like 17:30, 530pm or one relative to the clock like now-15m,
+// of which only the time is kept
 pub fn parse_time_str(val: &str) -> Result<NaiveTime, TimeKeeperError> {
-    let time_str = val.to_lowercase();
+    let time_str = val.trim().to_lowercase();
+
+    if let Some(at) = parse_relative_time(&time_str, current_minute()) {
+        return Ok(at.time());
+    }
 
     // Handle military time
     if let Ok(time) = NaiveTime::parse_from_str(&time_str, "%H:%M") {
@@ -49,7 +143,7 @@ pub fn parse_time_str(val: &str) -> Result<NaiveTime, TimeKeeperError> {
     }
 
     // compact military time 1900
-    if time_str.len() == 4 && time_str.chars().all(|c| c.is_digit(10)) {
+    if time_str.len() == 4 && time_str.chars().all(|c| c.is_ascii_digit()) {
         if let Ok(time) =
             NaiveTime::parse_from_str(&format!("{}:{}", &time_str[0..2], &time_str[2..4]), "%H:%M")
         {
@@ -90,15 +184,36 @@ pub fn parse_time_str(val: &str) -> Result<NaiveTime, TimeKeeperError> {
         }
     }
     Err(TimeKeeperError::ParseError(format!(
-        "Invalid time format '{}'. Use HH:MM (24-hour) or HHMM(am/pm) like 530pm",
+        "Invalid time format '{}'. Use HH:MM (24-hour), HHMM(am/pm) like 530pm or now-15m",
         val
     )))
 }
 
+// Parses a START-END range such as 09:00-11:00 or 9am-1130am
+pub fn parse_time_range(val: &str) -> Result<(NaiveTime, NaiveTime), TimeKeeperError> {
+    let (start, end) = val.split_once('-').ok_or_else(|| {
+        TimeKeeperError::ParseError(format!(
+            "Invalid time range '{}'. Use START-END like 09:00-11:00",
+            val
+        ))
+    })?;
+
+    let (start, end) = (parse_time_str(start.trim())?, parse_time_str(end.trim())?);
+    if end <= start {
+        return Err(TimeKeeperError::CheckOutBeforeCheckIn);
+    }
+    Ok((start, end))
+}
+
 pub fn get_today() -> NaiveDate {
     Local::now().date_naive()
 }
 
+// The current time in the HH:MM form the time parser accepts
+pub fn get_now() -> String {
+    Local::now().format("%H:%M").to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -225,6 +340,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_durations() {
+        assert_eq!(parse_duration_str("5m").unwrap(), Duration::minutes(5));
+        assert_eq!(parse_duration_str("2h").unwrap(), Duration::hours(2));
+        assert_eq!(parse_duration_str("1h30m").unwrap(), Duration::minutes(90));
+        assert!(parse_duration_str("").is_err());
+        assert!(parse_duration_str("h").is_err());
+        assert!(parse_duration_str("5 minutes").is_err());
+    }
+
+    #[test]
+    fn test_relative_times() {
+        let at = |d, h, m| {
+            NaiveDate::from_ymd_opt(2024, 7, d)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+        };
+        let now = at(8, 17, 30);
+
+        assert_eq!(parse_relative_time("now", now), Some(now));
+        assert_eq!(parse_relative_time("now-15m", now), Some(at(8, 17, 15)));
+        assert_eq!(parse_relative_time("now + 1h", now), Some(at(8, 18, 30)));
+        assert_eq!(parse_relative_time("+30m", now), Some(at(8, 18, 0)));
+        assert_eq!(parse_relative_time("-1h30m", now), Some(at(8, 16, 0)));
+        assert_eq!(
+            parse_relative_time("5 minutes ago", now),
+            Some(at(8, 17, 25))
+        );
+        assert_eq!(parse_relative_time("1 hour ago", now), Some(at(8, 16, 30)));
+        assert_eq!(parse_relative_time("20m ago", now), Some(at(8, 17, 10)));
+        // The date moves back past midnight
+        let now = at(8, 0, 10);
+        assert_eq!(
+            parse_relative_time("20 minutes ago", now),
+            Some(at(7, 23, 50))
+        );
+        assert_eq!(parse_relative_time("now-15m", now), Some(at(7, 23, 55)));
+
+        assert_eq!(parse_relative_time("17:30", now), None);
+        assert_eq!(parse_relative_time("now-", now), None);
+        assert_eq!(parse_relative_time("5 days ago", now), None);
+
+        let date = NaiveDate::from_ymd_opt(2024, 7, 8).unwrap();
+        assert_eq!(
+            parse_datetime_str("17:30", None, date).unwrap(),
+            at(8, 17, 30)
+        );
+        assert_eq!(
+            parse_datetime_str("17:30", Some(date), get_today()).unwrap(),
+            at(8, 17, 30)
+        );
+        assert!(parse_datetime_str("10 minutes ago", None, date).is_ok());
+        // The date of a relative time is the clock's, never the one given
+        assert!(parse_datetime_str("10 minutes ago", Some(date), date).is_err());
+        assert!(parse_time_str("10 minutes ago").is_ok());
+        assert!(parse_time_str("now").is_ok());
+    }
+
     #[test]
     fn test_invalid_formats() {
         // Test invalid time formats
@@ -235,4 +409,21 @@ mod tests {
         assert!(parse_time_str("").is_err()); // Empty string
         assert!(parse_time_str("9:00xyz").is_err()); // Invalid suffix
     }
+
+    #[test]
+    fn test_time_range() {
+        assert_eq!(
+            parse_time_range("09:00-11:00").unwrap(),
+            (
+                NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(11, 0, 0).unwrap()
+            )
+        );
+        assert_eq!(
+            parse_time_range("9am-1pm").unwrap().1,
+            NaiveTime::from_hms_opt(13, 0, 0).unwrap()
+        );
+        assert!(parse_time_range("11:00-09:00").is_err());
+        assert!(parse_time_range("09:00").is_err());
+    }
 }
@@ -0,0 +1,191 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use directories::ProjectDirs;
+use serde::Deserialize;
+
+use crate::app::TimeKeeperError;
+
+// Where each kind of file lives, following the platform conventions
+// (XDG base directories on Linux):
+//   data   - the database
+//   config - user configuration
+//   state  - shell history and other files worth keeping but not backing up
+//   cache  - anything that can be regenerated
+//   logs   - a subdirectory of state
+#[derive(Debug)]
+pub struct Paths {
+    pub data: PathBuf,
+    pub config: PathBuf,
+    pub state: PathBuf,
+    pub cache: PathBuf,
+    pub logs: PathBuf,
+}
+
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+// Keeps every file under one directory instead of the platform locations, as
+// --data-dir and TIMEKEEPER_DATA_DIR do. Only the first call has any effect.
+pub fn set_data_dir(dir: PathBuf) {
+    let _ = DATA_DIR.set(dir);
+}
+
+impl Paths {
+    pub fn new() -> Result<Paths, TimeKeeperError> {
+        if let Some(dir) = DATA_DIR.get() {
+            return Ok(Paths::in_dir(dir));
+        }
+        let dirs = ProjectDirs::from("", "", "timekeeper").ok_or_else(|| {
+            TimeKeeperError::IoError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Could not determine project directory",
+            ))
+        })?;
+
+        // Only Linux has a state directory, elsewhere it lives with local data
+        let state = dirs
+            .state_dir()
+            .unwrap_or_else(|| dirs.data_local_dir())
+            .to_path_buf();
+
+        Ok(Paths {
+            data: dirs.data_dir().to_path_buf(),
+            config: dirs.config_dir().to_path_buf(),
+            logs: state.join("logs"),
+            state,
+            cache: dirs.cache_dir().to_path_buf(),
+        })
+    }
+
+    // A self-contained layout, e.g. for a directory synced between devices
+    // or a phone without the desktop directory conventions
+    pub fn in_dir(dir: &Path) -> Paths {
+        let state = dir.join("state");
+        Paths {
+            data: dir.to_path_buf(),
+            config: dir.to_path_buf(),
+            logs: state.join("logs"),
+            state,
+            cache: dir.join("cache"),
+        }
+    }
+
+    pub fn db_file(&self) -> PathBuf {
+        self.data.join("keeper.db")
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        self.config.join("config.toml")
+    }
+
+    pub fn shell_history_file(&self) -> PathBuf {
+        self.state.join("shell_history")
+    }
+}
+
+pub const MARKER_FILE: &str = ".timekeeper";
+
+// A .timekeeper file pins the tracking context for a directory tree, e.g.
+//   db = "time/keeper.db"
+//   project = "acme"
+// Relative paths are resolved against the directory holding the marker.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MarkerFile {
+    db: Option<PathBuf>,
+    project: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Marker {
+    pub path: PathBuf,
+    pub db: Option<PathBuf>,
+    // Project recorded on entries made inside the tree when -p is not given
+    pub project: Option<String>,
+}
+
+impl Marker {
+    // The nearest marker in `start` or any of its parent directories
+    pub fn find(start: &Path) -> Result<Option<Marker>, TimeKeeperError> {
+        for dir in start.ancestors() {
+            let path = dir.join(MARKER_FILE);
+            if path.is_file() {
+                return Marker::load(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn load(path: &Path) -> Result<Marker, TimeKeeperError> {
+        let contents = std::fs::read_to_string(path)?;
+        let file: MarkerFile = toml::from_str(&contents).map_err(|e| {
+            TimeKeeperError::ParseError(format!("{}: {}", path.display(), e.message()))
+        })?;
+
+        let base = path.parent().unwrap_or(Path::new("."));
+        Ok(Marker {
+            path: path.to_path_buf(),
+            db: file.db.map(|db| base.join(db)),
+            project: file.project,
+        })
+    }
+
+    pub fn from_current_dir() -> Result<Option<Marker>, TimeKeeperError> {
+        Marker::find(&std::env::current_dir()?)
+    }
+}
+
+// Directories are only created once something is written to them
+pub fn ensure_dir(dir: &Path) -> Result<(), TimeKeeperError> {
+    std::fs::create_dir_all(dir).map_err(|e| {
+        TimeKeeperError::IoError(std::io::Error::new(
+            e.kind(),
+            format!("Failed to create directory {}: {}", dir.display(), e),
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marker_lookup() {
+        let root = std::env::temp_dir().join(format!("timekeeper-marker-{}", std::process::id()));
+        let nested = root.join("src").join("deep");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(root.join(MARKER_FILE), "db = \"work.db\"\n").unwrap();
+
+        let marker = Marker::find(&nested).unwrap().unwrap();
+        assert_eq!(marker.path, root.join(MARKER_FILE));
+        assert_eq!(marker.db, Some(root.join("work.db")));
+        assert!(marker.project.is_none());
+
+        std::fs::write(root.join(MARKER_FILE), "project = \"acme\"\n").unwrap();
+        let marker = Marker::find(&nested).unwrap().unwrap();
+        assert!(marker.db.is_none());
+        assert_eq!(marker.project.as_deref(), Some("acme"));
+
+        std::fs::write(root.join(MARKER_FILE), "database = \"work.db\"\n").unwrap();
+        assert!(Marker::find(&nested).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_paths_in_dir() {
+        let paths = Paths::in_dir(Path::new("/sdcard/timekeeper"));
+        assert_eq!(
+            paths.db_file(),
+            PathBuf::from("/sdcard/timekeeper/keeper.db")
+        );
+        assert_eq!(
+            paths.config_file(),
+            PathBuf::from("/sdcard/timekeeper/config.toml")
+        );
+        assert_eq!(
+            paths.shell_history_file(),
+            PathBuf::from("/sdcard/timekeeper/state/shell_history")
+        );
+    }
+}
@@ -0,0 +1,563 @@
+use std::sync::OnceLock;
+
+use chrono::{Datelike, Duration, Months, NaiveDate, Weekday};
+use clap::Args;
+use regex::Regex;
+
+use crate::app::TimeKeeperError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+impl Period {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from <= date && date <= self.to
+    }
+
+    pub fn week_of(date: NaiveDate) -> Period {
+        let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+        Period {
+            from: monday,
+            to: monday + Duration::days(6),
+        }
+    }
+
+    pub fn month_of(date: NaiveDate) -> Period {
+        let first = date.with_day(1).unwrap();
+        Period {
+            from: first,
+            to: first + Months::new(1) - Duration::days(1),
+        }
+    }
+
+    pub fn quarter_of(date: NaiveDate) -> Period {
+        let first_month = (date.month0() / 3) * 3 + 1;
+        let first = NaiveDate::from_ymd_opt(date.year(), first_month, 1).unwrap();
+        Period {
+            from: first,
+            to: first + Months::new(3) - Duration::days(1),
+        }
+    }
+
+    pub fn year_of(date: NaiveDate) -> Period {
+        Period {
+            from: NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap(),
+            to: NaiveDate::from_ymd_opt(date.year(), 12, 31).unwrap(),
+        }
+    }
+}
+
+impl std::fmt::Display for Period {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} to {}",
+            self.from.format("%Y-%m-%d"),
+            self.to.format("%Y-%m-%d")
+        )
+    }
+}
+
+// How often the user gets paid, from pay_period in the config. The cycle
+// decides what the current and previous period expressions cover.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayCycle {
+    Weekly,
+    // Every other week, counted from a first day
+    Biweekly(NaiveDate),
+    // The 1st to the 15th and the 16th to the end of the month
+    SemiMonthly,
+    Monthly,
+    // Fiscal months of so many weeks per quarter, e.g. 4-4-5, in 52-week
+    // years counted from the first day of a fiscal year
+    Fiscal([i64; 3], NaiveDate),
+}
+
+static PAY_CYCLE: OnceLock<PayCycle> = OnceLock::new();
+
+// Only the first call has any effect
+pub fn set_pay_cycle(cycle: PayCycle) {
+    let _ = PAY_CYCLE.set(cycle);
+}
+
+impl PayCycle {
+    // kind is weekly, biweekly, semi-monthly, monthly or a fiscal pattern like
+    // 4-4-5. Biweekly and fiscal cycles need the first day of one of their
+    // periods as YYYY-MM-DD.
+    pub fn parse(kind: &str, start: Option<&str>) -> Result<PayCycle, TimeKeeperError> {
+        let start = || {
+            let start = start.ok_or_else(|| {
+                TimeKeeperError::ParseError(format!(
+                    "Pay period '{}' needs pay_period_start as YYYY-MM-DD",
+                    kind
+                ))
+            })?;
+            NaiveDate::parse_from_str(start, "%Y-%m-%d").map_err(|_| {
+                TimeKeeperError::ParseError(format!(
+                    "Invalid pay_period_start '{}', use YYYY-MM-DD",
+                    start
+                ))
+            })
+        };
+
+        match kind.to_lowercase().as_str() {
+            "weekly" => return Ok(PayCycle::Weekly),
+            "biweekly" => return Ok(PayCycle::Biweekly(start()?)),
+            "semi-monthly" => return Ok(PayCycle::SemiMonthly),
+            "monthly" => return Ok(PayCycle::Monthly),
+            _ => {}
+        }
+
+        let weeks: Vec<i64> = kind.split('-').filter_map(|n| n.parse().ok()).collect();
+        match weeks[..] {
+            [a, b, c] if a + b + c == 13 && weeks.iter().all(|n| *n > 0) => {
+                Ok(PayCycle::Fiscal([a, b, c], start()?))
+            }
+            _ => Err(TimeKeeperError::ParseError(format!(
+                "Invalid pay period '{}'. Use weekly, biweekly, semi-monthly, monthly or 4-4-5",
+                kind
+            ))),
+        }
+    }
+
+    // The pay period the date falls in
+    pub fn period_of(&self, date: NaiveDate) -> Period {
+        match *self {
+            PayCycle::Weekly => Period::week_of(date),
+            PayCycle::Biweekly(start) => {
+                let from = date - Duration::days((date - start).num_days().rem_euclid(14));
+                Period {
+                    from,
+                    to: from + Duration::days(13),
+                }
+            }
+            PayCycle::SemiMonthly => {
+                let month = Period::month_of(date);
+                let sixteenth = month.from + Duration::days(15);
+                if date < sixteenth {
+                    Period {
+                        from: month.from,
+                        to: sixteenth - Duration::days(1),
+                    }
+                } else {
+                    Period {
+                        from: sixteenth,
+                        to: month.to,
+                    }
+                }
+            }
+            PayCycle::Monthly => Period::month_of(date),
+            PayCycle::Fiscal(pattern, start) => {
+                let year = date - Duration::days((date - start).num_days().rem_euclid(364));
+                let week = (date - year).num_days() / 7;
+                // Fiscal months run back to back from the start of the quarter
+                let mut from = year + Duration::weeks(week / 13 * 13);
+                for weeks in pattern {
+                    let to = from + Duration::weeks(weeks) - Duration::days(1);
+                    if date <= to {
+                        return Period { from, to };
+                    }
+                    from = to + Duration::days(1);
+                }
+                unreachable!("the fiscal months cover the whole quarter")
+            }
+        }
+    }
+
+    // The pay period before the one the date falls in
+    pub fn previous(&self, date: NaiveDate) -> Period {
+        self.period_of(self.period_of(date).from - Duration::days(1))
+    }
+}
+
+fn pay_cycle() -> Result<PayCycle, TimeKeeperError> {
+    PAY_CYCLE.get().copied().ok_or_else(|| {
+        TimeKeeperError::ParseError(
+            "No pay period set, add pay_period to the config, e.g. pay_period = \"semi-monthly\""
+                .to_string(),
+        )
+    })
+}
+
+// Flags shared by every command that works on a range of days
+#[derive(Args, Debug, Clone, Default)]
+pub struct PeriodArgs {
+    /// Week to cover: this (when no value is given), last or an ISO week like 2024-W28
+    #[arg(
+        short,
+        long,
+        num_args = 0..=1,
+        default_missing_value = "this",
+        conflicts_with_all = ["month", "period", "from", "to"]
+    )]
+    pub week: Option<String>,
+
+    /// Month to cover: this (when no value is given), last or YYYY-MM
+    #[arg(
+        short,
+        long,
+        num_args = 0..=1,
+        default_missing_value = "this",
+        conflicts_with_all = ["period", "from", "to"]
+    )]
+    pub month: Option<String>,
+
+    /// Period expression like ytd, q2, "last 2 weeks" or 2024-06-01..2024-06-15
+    #[arg(long, conflicts_with_all = ["from", "to"])]
+    pub period: Option<String>,
+
+    /// Start of the range, a date or any period expression
+    #[arg(long)]
+    pub from: Option<String>,
+
+    /// End of the range, a date or any period expression
+    #[arg(long)]
+    pub to: Option<String>,
+}
+
+impl PeriodArgs {
+    pub fn resolve(&self, today: NaiveDate) -> Result<Option<Period>, TimeKeeperError> {
+        if let Some(week) = &self.week {
+            return parse_week(week, today).map(Some);
+        }
+        if let Some(month) = &self.month {
+            return parse_month(month, today).map(Some);
+        }
+        if let Some(period) = &self.period {
+            return parse_period(period, today).map(Some);
+        }
+
+        // An open-ended range runs from the beginning of records or up to today
+        let from = match &self.from {
+            Some(from) => Some(parse_period(from, today)?.from),
+            None => None,
+        };
+        let to = match &self.to {
+            Some(to) => Some(parse_period(to, today)?.to),
+            None => None,
+        };
+        match (from, to) {
+            (None, None) => Ok(None),
+            (from, to) => range(
+                from.unwrap_or(NaiveDate::from_ymd_opt(1, 1, 1).unwrap()),
+                to.unwrap_or(today),
+            )
+            .map(Some),
+        }
+    }
+}
+
+fn range(from: NaiveDate, to: NaiveDate) -> Result<Period, TimeKeeperError> {
+    if from > to {
+        return Err(TimeKeeperError::ParseError(format!(
+            "Period starts on {} after it ends on {}",
+            from.format("%Y-%m-%d"),
+            to.format("%Y-%m-%d")
+        )));
+    }
+    Ok(Period { from, to })
+}
+
+// The period DSL shared by --period, --from and --to:
+//   today, yesterday, ytd, this/last week|month|quarter|year,
+//   last N days|weeks|months (rolling, ending today),
+//   q1-q4, 2024-q2, 2024, 2024-06, 2024-W28, 2024-06-12, A..B ranges and
+//   current or previous for the pay period from the config
+pub fn parse_period(val: &str, today: NaiveDate) -> Result<Period, TimeKeeperError> {
+    let expr = val.trim().to_lowercase();
+    let invalid = || {
+        TimeKeeperError::ParseError(format!(
+            "Invalid period '{}'. Try ytd, q2, \"last 2 weeks\", 2024-06 or 2024-06-01..2024-06-15",
+            val
+        ))
+    };
+
+    if let Some((start, end)) = expr.split_once("..") {
+        return range(
+            parse_period(start, today)?.from,
+            parse_period(end, today)?.to,
+        );
+    }
+
+    let day = |date| Period {
+        from: date,
+        to: date,
+    };
+    match expr.as_str() {
+        "today" => return Ok(day(today)),
+        "yesterday" => return Ok(day(today - Duration::days(1))),
+        "ytd" => {
+            return Ok(Period {
+                from: Period::year_of(today).from,
+                to: today,
+            })
+        }
+        "this week" => return parse_week("this", today),
+        "last week" => return parse_week("last", today),
+        "this month" => return parse_month("this", today),
+        "last month" => return parse_month("last", today),
+        "this quarter" => return Ok(Period::quarter_of(today)),
+        "last quarter" => return Ok(Period::quarter_of(today - Months::new(3))),
+        "this year" => return Ok(Period::year_of(today)),
+        "last year" => return Ok(Period::year_of(today - Months::new(12))),
+        "current" | "this pay period" => return Ok(pay_cycle()?.period_of(today)),
+        "previous" | "last pay period" => return Ok(pay_cycle()?.previous(today)),
+        _ => {}
+    }
+
+    let rolling = Regex::new(r"^last (\d+) (day|week|month)s?$").unwrap();
+    if let Some(caps) = rolling.captures(&expr) {
+        let count: u32 = caps[1].parse().map_err(|_| invalid())?;
+        if count == 0 {
+            return Err(invalid());
+        }
+        let from = match &caps[2] {
+            "day" => today - Duration::days(count as i64 - 1),
+            "week" => today - Duration::days(count as i64 * 7 - 1),
+            _ => today - Months::new(count) + Duration::days(1),
+        };
+        return Ok(Period { from, to: today });
+    }
+
+    let quarter = Regex::new(r"^(?:(\d{4})-?)?q([1-4])$").unwrap();
+    if let Some(caps) = quarter.captures(&expr) {
+        let year = match caps.get(1) {
+            Some(year) => year.as_str().parse().map_err(|_| invalid())?,
+            None => today.year(),
+        };
+        let month = (caps[2].parse::<u32>().map_err(|_| invalid())? - 1) * 3 + 1;
+        let first = NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(invalid)?;
+        return Ok(Period::quarter_of(first));
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(&expr, "%Y-%m-%d") {
+        return Ok(day(date));
+    }
+    if Regex::new(r"^\d{4}$").unwrap().is_match(&expr) {
+        let year = expr.parse().map_err(|_| invalid())?;
+        let first = NaiveDate::from_ymd_opt(year, 1, 1).ok_or_else(invalid)?;
+        return Ok(Period::year_of(first));
+    }
+    if expr.contains('w') {
+        return parse_week(&expr, today).map_err(|_| invalid());
+    }
+    parse_month(&expr, today).map_err(|_| invalid())
+}
+
+// Accepts this, last or an ISO week such as 2024-W28
+pub fn parse_week(val: &str, today: NaiveDate) -> Result<Period, TimeKeeperError> {
+    match val.to_lowercase().as_str() {
+        "this" => return Ok(Period::week_of(today)),
+        "last" => return Ok(Period::week_of(today - Duration::days(7))),
+        _ => {}
+    }
+
+    let re = Regex::new(r"(?i)^(\d{4})-?W(\d{1,2})$").unwrap();
+    re.captures(val)
+        .and_then(|caps| {
+            let year = caps[1].parse().ok()?;
+            let week = caps[2].parse().ok()?;
+            NaiveDate::from_isoywd_opt(year, week, Weekday::Mon)
+        })
+        .map(Period::week_of)
+        .ok_or_else(|| {
+            TimeKeeperError::ParseError(format!(
+                "Invalid week '{}'. Use this, last or YYYY-Www like 2024-W28",
+                val
+            ))
+        })
+}
+
+// Accepts this, last or YYYY-MM
+pub fn parse_month(val: &str, today: NaiveDate) -> Result<Period, TimeKeeperError> {
+    match val.to_lowercase().as_str() {
+        "this" => return Ok(Period::month_of(today)),
+        "last" => return Ok(Period::month_of(today - Months::new(1))),
+        _ => {}
+    }
+
+    NaiveDate::parse_from_str(&format!("{}-01", val), "%Y-%m-%d")
+        .map(Period::month_of)
+        .map_err(|_| {
+            TimeKeeperError::ParseError(format!(
+                "Invalid month '{}'. Use this, last or YYYY-MM like 2024-06",
+                val
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_weeks() {
+        let today = date(2024, 7, 12);
+
+        assert_eq!(
+            parse_week("this", today).unwrap(),
+            Period {
+                from: date(2024, 7, 8),
+                to: date(2024, 7, 14)
+            }
+        );
+        assert_eq!(parse_week("last", today).unwrap().from, date(2024, 7, 1));
+        assert_eq!(
+            parse_week("2024-W28", today).unwrap().from,
+            date(2024, 7, 8)
+        );
+        assert_eq!(
+            parse_week("2021-w01", today).unwrap().from,
+            date(2021, 1, 4)
+        );
+        assert!(parse_week("2024-W54", today).is_err());
+        assert!(parse_week("next", today).is_err());
+    }
+
+    #[test]
+    fn test_months() {
+        let today = date(2024, 3, 31);
+
+        assert_eq!(
+            parse_month("2024-02", today).unwrap(),
+            Period {
+                from: date(2024, 2, 1),
+                to: date(2024, 2, 29)
+            }
+        );
+        assert_eq!(parse_month("this", today).unwrap().to, date(2024, 3, 31));
+        assert_eq!(parse_month("last", today).unwrap().from, date(2024, 2, 1));
+        assert_eq!(
+            parse_month("2024-12", today).unwrap().to,
+            date(2024, 12, 31)
+        );
+        assert!(parse_month("2024-13", today).is_err());
+        assert!(parse_month("june", today).is_err());
+    }
+
+    #[test]
+    fn test_period_expressions() {
+        let today = date(2024, 7, 12);
+        let period = |expr| parse_period(expr, today).unwrap();
+        let span = |from, to| Period { from, to };
+
+        assert_eq!(period("today"), span(today, today));
+        assert_eq!(period("ytd"), span(date(2024, 1, 1), today));
+        assert_eq!(period("q2"), span(date(2024, 4, 1), date(2024, 6, 30)));
+        assert_eq!(
+            period("2023-Q4"),
+            span(date(2023, 10, 1), date(2023, 12, 31))
+        );
+        assert_eq!(
+            period("last quarter"),
+            span(date(2024, 4, 1), date(2024, 6, 30))
+        );
+        assert_eq!(period("last 7 days"), span(date(2024, 7, 6), today));
+        assert_eq!(period("last 2 weeks"), span(date(2024, 6, 29), today));
+        assert_eq!(period("last 1 month"), span(date(2024, 6, 13), today));
+        assert_eq!(period("2023"), span(date(2023, 1, 1), date(2023, 12, 31)));
+        assert_eq!(
+            period("2024-W28"),
+            span(date(2024, 7, 8), date(2024, 7, 14))
+        );
+        assert_eq!(
+            period("2024-06-01..2024-06-15"),
+            span(date(2024, 6, 1), date(2024, 6, 15))
+        );
+        assert_eq!(
+            period("2024-05..q2"),
+            span(date(2024, 5, 1), date(2024, 6, 30))
+        );
+
+        assert!(parse_period("2024-06-15..2024-06-01", today).is_err());
+        assert!(parse_period("last 0 days", today).is_err());
+        assert!(parse_period("q5", today).is_err());
+        assert!(parse_period("soon", today).is_err());
+    }
+
+    #[test]
+    fn test_pay_cycles() {
+        let span = |from, to| Period { from, to };
+        let semi = PayCycle::parse("semi-monthly", None).unwrap();
+        assert_eq!(
+            semi.period_of(date(2024, 2, 15)),
+            span(date(2024, 2, 1), date(2024, 2, 15))
+        );
+        assert_eq!(
+            semi.period_of(date(2024, 2, 16)),
+            span(date(2024, 2, 16), date(2024, 2, 29))
+        );
+        assert_eq!(
+            semi.previous(date(2024, 3, 1)),
+            span(date(2024, 2, 16), date(2024, 2, 29))
+        );
+
+        let biweekly = PayCycle::parse("biweekly", Some("2024-01-05")).unwrap();
+        assert_eq!(
+            biweekly.period_of(date(2024, 1, 4)),
+            span(date(2023, 12, 22), date(2024, 1, 4))
+        );
+        assert_eq!(
+            biweekly.period_of(date(2024, 1, 19)).from,
+            date(2024, 1, 19)
+        );
+
+        // A fiscal year starting on Sunday 2023-12-31
+        let fiscal = PayCycle::parse("4-4-5", Some("2023-12-31")).unwrap();
+        assert_eq!(
+            fiscal.period_of(date(2024, 3, 1)),
+            span(date(2024, 2, 25), date(2024, 3, 30))
+        );
+        assert_eq!(
+            fiscal.period_of(date(2024, 3, 31)),
+            span(date(2024, 3, 31), date(2024, 4, 27))
+        );
+        assert_eq!(
+            fiscal.period_of(date(2024, 12, 29)).from,
+            date(2024, 12, 29)
+        );
+        assert_eq!(
+            fiscal.previous(date(2024, 12, 29)),
+            span(date(2024, 11, 24), date(2024, 12, 28))
+        );
+
+        assert!(PayCycle::parse("biweekly", None).is_err());
+        assert!(PayCycle::parse("4-4-4", Some("2024-01-01")).is_err());
+        assert!(PayCycle::parse("fortnightly", None).is_err());
+    }
+
+    #[test]
+    fn test_period_args() {
+        let today = date(2024, 7, 12);
+        let month = PeriodArgs {
+            month: Some("2024-06".to_string()),
+            ..Default::default()
+        };
+        let from = PeriodArgs {
+            from: Some("2024-07-01".to_string()),
+            ..Default::default()
+        };
+        let to = PeriodArgs {
+            to: Some("q1".to_string()),
+            ..Default::default()
+        };
+
+        assert!(PeriodArgs::default().resolve(today).unwrap().is_none());
+        assert_eq!(month.resolve(today).unwrap().unwrap().to, date(2024, 6, 30));
+        assert_eq!(
+            from.resolve(today).unwrap().unwrap(),
+            Period {
+                from: date(2024, 7, 1),
+                to: today
+            }
+        );
+        assert_eq!(to.resolve(today).unwrap().unwrap().to, date(2024, 3, 31));
+    }
+}
@@ -0,0 +1,371 @@
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use serde::Deserialize;
+
+use crate::app::TimeKeeperError;
+use crate::db::Record;
+use crate::parser::{parse_duration_str, parse_time_str};
+use crate::report::{format_delta, format_duration, round_duration, RoundingMode};
+
+// A day as the rules see it. Rules run in the configured order and each one
+// sees the day as the rules before it left it.
+#[derive(Debug, Clone)]
+pub struct Day {
+    pub check_in: NaiveDateTime,
+    pub check_out: NaiveDateTime,
+    pub breaks: Duration,
+    pub worked: Duration,
+    // Last check-out of the day before, for rest period checks
+    pub previous_check_out: Option<NaiveDateTime>,
+}
+
+impl Day {
+    pub fn new(check_in: NaiveDateTime, check_out: NaiveDateTime, breaks: Duration) -> Day {
+        Day {
+            check_in,
+            check_out,
+            breaks,
+            worked: check_out - check_in - breaks,
+            previous_check_out: None,
+        }
+    }
+}
+
+pub trait Rule {
+    fn name(&self) -> &'static str;
+
+    // Adjusts the day, returning a description of what changed if anything did
+    fn apply(&self, day: &mut Day) -> Option<String>;
+}
+
+// Check-ins shortly after the expected start count from the start itself
+pub struct Grace {
+    pub start: NaiveTime,
+    pub within: Duration,
+}
+
+impl Rule for Grace {
+    fn name(&self) -> &'static str {
+        "grace"
+    }
+
+    fn apply(&self, day: &mut Day) -> Option<String> {
+        let start = day.check_in.date().and_time(self.start);
+        if day.check_in <= start || day.check_in - start > self.within {
+            return None;
+        }
+
+        let late = day.check_in - start;
+        let note = format!(
+            "check-in at {} counted from {} ({})",
+            day.check_in.format("%H:%M"),
+            self.start.format("%H:%M"),
+            format_delta(late)
+        );
+        day.check_in = start;
+        day.worked += late;
+        Some(note)
+    }
+}
+
+// Days longer than `after` must include at least `minimum` of break; any
+// shortfall is deducted from worked time
+pub struct Deduct {
+    pub after: Duration,
+    pub minimum: Duration,
+}
+
+impl Rule for Deduct {
+    fn name(&self) -> &'static str {
+        "deduct"
+    }
+
+    fn apply(&self, day: &mut Day) -> Option<String> {
+        if day.worked <= self.after || day.breaks >= self.minimum {
+            return None;
+        }
+
+        let shortfall = self.minimum - day.breaks;
+        day.breaks = self.minimum;
+        day.worked -= shortfall;
+        Some(format!(
+            "{} break required after {} ({})",
+            format_duration(self.minimum),
+            format_duration(self.after),
+            format_delta(-shortfall)
+        ))
+    }
+}
+
+pub struct Round {
+    pub increment: Duration,
+    pub mode: RoundingMode,
+}
+
+impl Rule for Round {
+    fn name(&self) -> &'static str {
+        "round"
+    }
+
+    fn apply(&self, day: &mut Day) -> Option<String> {
+        let rounded = round_duration(day.worked, self.increment, self.mode);
+        if rounded == day.worked {
+            return None;
+        }
+
+        let note = format!(
+            "{} to {} ({})",
+            format!("{:?}", self.mode).to_lowercase(),
+            format_duration(self.increment),
+            format_delta(rounded - day.worked)
+        );
+        day.worked = rounded;
+        Some(note)
+    }
+}
+
+// Worked time beyond the cap is not counted
+pub struct Cap {
+    pub max: Duration,
+}
+
+impl Rule for Cap {
+    fn name(&self) -> &'static str {
+        "cap"
+    }
+
+    fn apply(&self, day: &mut Day) -> Option<String> {
+        if day.worked <= self.max {
+            return None;
+        }
+
+        let excess = day.worked - self.max;
+        day.worked = self.max;
+        Some(format!(
+            "capped at {} ({})",
+            format_duration(self.max),
+            format_delta(-excess)
+        ))
+    }
+}
+
+// Only warns, a short rest does not change the time worked
+pub struct Rest {
+    pub minimum: Duration,
+}
+
+impl Rule for Rest {
+    fn name(&self) -> &'static str {
+        "rest"
+    }
+
+    fn apply(&self, day: &mut Day) -> Option<String> {
+        let rest = day.check_in - day.previous_check_out?;
+        if rest >= self.minimum {
+            return None;
+        }
+
+        Some(format!(
+            "only {} of rest since the last check-out, {} expected",
+            format_duration(rest),
+            format_duration(self.minimum)
+        ))
+    }
+}
+
+// One [[policy]] table in config.toml, e.g.
+//   [[policy]]
+//   rule = "round"
+//   increment = "15m"
+//   mode = "up"
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "rule", rename_all = "lowercase", deny_unknown_fields)]
+pub enum RuleConfig {
+    Grace {
+        start: String,
+        within: String,
+    },
+    Deduct {
+        after: String,
+        minimum: String,
+    },
+    Round {
+        increment: String,
+        #[serde(default = "default_rounding_mode")]
+        mode: RoundingMode,
+    },
+    Cap {
+        max: String,
+    },
+    Rest {
+        minimum: String,
+    },
+}
+
+fn default_rounding_mode() -> RoundingMode {
+    RoundingMode::Nearest
+}
+
+impl RuleConfig {
+    pub fn build(&self) -> Result<Box<dyn Rule>, TimeKeeperError> {
+        Ok(match self {
+            RuleConfig::Grace { start, within } => Box::new(Grace {
+                start: parse_time_str(start)?,
+                within: parse_duration_str(within)?,
+            }),
+            RuleConfig::Deduct { after, minimum } => Box::new(Deduct {
+                after: parse_duration_str(after)?,
+                minimum: parse_duration_str(minimum)?,
+            }),
+            RuleConfig::Round { increment, mode } => Box::new(Round {
+                increment: parse_duration_str(increment)?,
+                mode: *mode,
+            }),
+            RuleConfig::Cap { max } => Box::new(Cap {
+                max: parse_duration_str(max)?,
+            }),
+            RuleConfig::Rest { minimum } => Box::new(Rest {
+                minimum: parse_duration_str(minimum)?,
+            }),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct Policy {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl Policy {
+    pub fn from_config(rules: &[RuleConfig]) -> Result<Policy, TimeKeeperError> {
+        Ok(Policy {
+            rules: rules
+                .iter()
+                .map(RuleConfig::build)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn push(&mut self, rule: Box<dyn Rule>) {
+        self.rules.push(rule);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // What a closed entry counts for, the rules seeing it as a day of its
+    // own like simulate does. Returns the entry moved so its duration is the
+    // time counted, for totals computed from the records themselves.
+    pub fn count(&self, record: &Record) -> Record {
+        let Some(check_out) = record.check_out else {
+            return record.clone();
+        };
+        let mut day = Day::new(record.check_in, check_out, record.unpaid_break);
+        self.apply(&mut day);
+        Record {
+            check_in: day.check_in,
+            check_out: Some(day.check_in + day.worked + record.unpaid_break),
+            ..record.clone()
+        }
+    }
+
+    pub fn count_records(&self, records: &[Record]) -> Vec<Record> {
+        records.iter().map(|record| self.count(record)).collect()
+    }
+
+    // Runs every rule in order, returning the name and note of each that applied
+    pub fn apply(&self, day: &mut Day) -> Vec<(&'static str, String)> {
+        self.rules
+            .iter()
+            .filter_map(|rule| rule.apply(day).map(|note| (rule.name(), note)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn day(check_in: (u32, u32), check_out: (u32, u32), breaks: i64) -> Day {
+        let date = NaiveDate::from_ymd_opt(2024, 7, 8).unwrap();
+        Day::new(
+            date.and_hms_opt(check_in.0, check_in.1, 0).unwrap(),
+            date.and_hms_opt(check_out.0, check_out.1, 0).unwrap(),
+            Duration::minutes(breaks),
+        )
+    }
+
+    #[test]
+    fn test_rules() {
+        let grace = Grace {
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            within: Duration::minutes(5),
+        };
+        let mut late = day((9, 4), (17, 0), 0);
+        assert!(grace.apply(&mut late).is_some());
+        assert_eq!(late.worked, Duration::hours(8));
+        assert!(grace.apply(&mut day((9, 6), (17, 0), 0)).is_none());
+
+        let deduct = Deduct {
+            after: Duration::hours(6),
+            minimum: Duration::minutes(30),
+        };
+        let mut long = day((9, 0), (17, 0), 10);
+        assert!(deduct.apply(&mut long).is_some());
+        assert_eq!(long.worked, Duration::minutes(450));
+        assert!(deduct.apply(&mut day((9, 0), (14, 0), 0)).is_none());
+
+        let cap = Cap {
+            max: Duration::hours(10),
+        };
+        let mut very_long = day((7, 0), (19, 0), 0);
+        assert!(cap.apply(&mut very_long).is_some());
+        assert_eq!(very_long.worked, Duration::hours(10));
+
+        let rest = Rest {
+            minimum: Duration::hours(11),
+        };
+        let mut early = day((6, 0), (14, 0), 0);
+        assert!(rest.apply(&mut early).is_none());
+        early.previous_check_out = Some(early.check_in - Duration::hours(9));
+        assert!(rest.apply(&mut early).is_some());
+        assert_eq!(early.worked, Duration::hours(8));
+    }
+
+    #[test]
+    fn test_pipeline_order() {
+        let rules: Vec<RuleConfig> = toml::from_str::<toml::Table>(
+            "[[policy]]\n\
+             rule = \"deduct\"\nafter = \"6h\"\nminimum = \"30m\"\n\
+             [[policy]]\n\
+             rule = \"round\"\nincrement = \"15m\"\nmode = \"up\"\n",
+        )
+        .unwrap()["policy"]
+            .clone()
+            .try_into()
+            .unwrap();
+        let policy = Policy::from_config(&rules).unwrap();
+
+        // 8h10m, less the 30m deduction, rounded up to 7h45m
+        let mut worked = day((9, 0), (17, 10), 0);
+        let applied = policy.apply(&mut worked);
+        assert_eq!(
+            applied.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            ["deduct", "round"]
+        );
+        assert_eq!(worked.worked, Duration::minutes(465));
+
+        // Entries are counted through the same rules, each as its own day
+        let at = |hour| Record::test_time(8, hour);
+        let record = |start, minutes| {
+            Record::test_entry(at(start), Some(at(start) + Duration::minutes(minutes)))
+        };
+        let counted = policy.count_records(&[record(9, 490), record(18, 53)]);
+        assert_eq!(counted[0].duration(), Duration::minutes(465));
+        assert_eq!(counted[0].check_in, at(9));
+        assert_eq!(counted[1].duration(), Duration::hours(1));
+        let open = Record::test_entry(at(20), None);
+        assert!(policy.count(&open).is_open());
+    }
+}
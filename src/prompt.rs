@@ -0,0 +1,77 @@
+use std::io::IsTerminal;
+
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Confirm, Input, Select};
+
+use crate::app::TimeKeeperError;
+
+impl From<dialoguer::Error> for TimeKeeperError {
+    fn from(err: dialoguer::Error) -> Self {
+        match err {
+            dialoguer::Error::IO(e) => TimeKeeperError::IoError(e),
+        }
+    }
+}
+
+// Prompts are only shown when both stdin and stderr (where dialoguer draws)
+// are terminals. Scripts and pipes fall back to the default answer.
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+pub fn confirm(prompt: &str, default: bool) -> Result<bool, TimeKeeperError> {
+    if !is_interactive() {
+        return Ok(default);
+    }
+
+    Ok(Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(default)
+        .interact()?)
+}
+
+pub fn select<T: std::fmt::Display>(
+    prompt: &str,
+    items: &[T],
+    default: usize,
+) -> Result<usize, TimeKeeperError> {
+    if !is_interactive() {
+        return Ok(default);
+    }
+
+    Ok(Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(items)
+        .default(default)
+        .interact()?)
+}
+
+// Without a terminal the default is used as-is, and there must be one
+pub fn input<F>(
+    prompt: &str,
+    default: Option<String>,
+    validate: F,
+) -> Result<String, TimeKeeperError>
+where
+    F: Fn(&str) -> Result<(), TimeKeeperError>,
+{
+    if !is_interactive() {
+        return match default {
+            Some(value) => validate(&value).map(|_| value),
+            None => Err(TimeKeeperError::ParseError(format!(
+                "'{}' needs an answer but no terminal is attached",
+                prompt
+            ))),
+        };
+    }
+
+    let theme = ColorfulTheme::default();
+    let mut input = Input::<String>::with_theme(&theme)
+        .with_prompt(prompt)
+        .validate_with(|value: &String| validate(value).map_err(|e| e.to_string()));
+    if let Some(value) = default {
+        input = input.default(value);
+    }
+
+    Ok(input.interact_text()?)
+}
@@ -0,0 +1,148 @@
+use std::path::Path;
+
+use chrono::{Duration, NaiveDate};
+use comfy_table::{Cell, Color, ContentArrangement, Row, Table};
+
+use crate::app::TimeKeeperError;
+use crate::db::Db;
+use crate::report::{format_delta, format_duration};
+
+// Hours as decimals like 7.5 or as H:MM like 7:30
+fn parse_hours(val: &str) -> Option<Duration> {
+    if let Some((hours, minutes)) = val.split_once(':') {
+        let hours: i64 = hours.parse().ok()?;
+        let minutes: i64 = minutes.parse().ok()?;
+        return Some(Duration::hours(hours) + Duration::minutes(minutes));
+    }
+    let hours: f64 = val.parse().ok()?;
+    Some(Duration::minutes((hours * 60.0).round() as i64))
+}
+
+// Reads DATE,HOURS lines, e.g. an export from a corporate time system. A
+// header line is skipped, as are any columns after the second.
+pub fn parse_external_totals(
+    contents: &str,
+) -> Result<Vec<(NaiveDate, Duration)>, TimeKeeperError> {
+    let mut totals: Vec<(NaiveDate, Duration)> = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        if fields.iter().all(|field| field.is_empty()) {
+            continue;
+        }
+
+        let parsed = match fields.as_slice() {
+            [date, hours, ..] => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .zip(parse_hours(hours)),
+            _ => None,
+        };
+        match parsed {
+            // The same day may be split over several lines
+            Some((date, worked)) => match totals.iter_mut().find(|(day, _)| *day == date) {
+                Some((_, total)) => *total += worked,
+                None => totals.push((date, worked)),
+            },
+            None if index == 0 => continue,
+            None => {
+                return Err(TimeKeeperError::ParseError(format!(
+                    "Line {}: expected DATE,HOURS like 2024-07-12,7.5 but got '{}'",
+                    index + 1,
+                    line
+                )))
+            }
+        }
+    }
+
+    totals.sort_by_key(|(date, _)| *date);
+    Ok(totals)
+}
+
+// Lists the days where the external totals and local records differ by more
+// than the tolerance, over the range of days the external file covers
+pub fn handle_reconcile(db: &Db, file: &Path, tolerance: Duration) -> Result<(), TimeKeeperError> {
+    let external = parse_external_totals(&std::fs::read_to_string(file)?)?;
+    let (Some(first), Some(last)) = (external.first(), external.last()) else {
+        println!("No days found in {}", file.display());
+        return Ok(());
+    };
+    let local = db.get_daily_totals(first.0, last.0)?;
+
+    let mut dates: Vec<NaiveDate> = external
+        .iter()
+        .map(|(date, _)| *date)
+        .chain(local.iter().map(|day| day.date))
+        .collect();
+    dates.sort();
+    dates.dedup();
+
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(Row::from(vec![
+            Cell::new("Date").fg(Color::Cyan),
+            Cell::new("External").fg(Color::Green),
+            Cell::new("Local").fg(Color::Yellow),
+            Cell::new("Difference").fg(Color::Red),
+        ]));
+
+    let mut mismatches = 0;
+    for date in &dates {
+        let theirs = external
+            .iter()
+            .find(|(day, _)| day == date)
+            .map(|(_, d)| *d);
+        let ours = local
+            .iter()
+            .find(|day| day.date == *date)
+            .map(|day| day.worked);
+        let difference =
+            ours.unwrap_or_else(Duration::zero) - theirs.unwrap_or_else(Duration::zero);
+        if difference.abs() <= tolerance {
+            continue;
+        }
+
+        mismatches += 1;
+        let show = |d: Option<Duration>| d.map(format_duration).unwrap_or_else(|| "-".to_string());
+        table.add_row(vec![
+            Cell::new(date.format("%a %Y-%m-%d")),
+            Cell::new(show(theirs)),
+            Cell::new(show(ours)),
+            Cell::new(format_delta(difference)),
+        ]);
+    }
+
+    if mismatches > 0 {
+        println!("{table}");
+    }
+    println!(
+        "{} of {} days differ by more than {}",
+        mismatches,
+        dates.len(),
+        format_duration(tolerance)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_external_totals() {
+        let date = |day| NaiveDate::from_ymd_opt(2024, 7, day).unwrap();
+        let totals = parse_external_totals(
+            "date,hours\n2024-07-12,7.5\n2024-07-11, 8:15 ,extra\n\n2024-07-12,0.25\n",
+        )
+        .unwrap();
+        assert_eq!(
+            totals,
+            vec![
+                (date(11), Duration::minutes(8 * 60 + 15)),
+                (date(12), Duration::minutes(7 * 60 + 45)),
+            ]
+        );
+
+        assert!(parse_external_totals("2024-07-12,7.5\nnot a line\n").is_err());
+    }
+}
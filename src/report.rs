@@ -0,0 +1,1558 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use clap::ValueEnum;
+use comfy_table::{Cell, Color, ContentArrangement, Table};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+
+use crate::db::{Note, Record};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Dimension {
+    Day,
+    Week,
+    Month,
+    Weekday,
+    Location,
+    Project,
+    Kind,
+}
+
+impl Dimension {
+    pub fn label(&self, record: &Record) -> String {
+        let date = record.date();
+        match self {
+            Dimension::Day => date.format("%Y-%m-%d").to_string(),
+            Dimension::Week => date.format("%G-W%V").to_string(),
+            Dimension::Month => date.format("%Y-%m").to_string(),
+            Dimension::Weekday => date.format("%a").to_string(),
+            Dimension::Location => record
+                .location
+                .clone()
+                .unwrap_or_else(|| "unset".to_string()),
+            Dimension::Project => record
+                .project
+                .clone()
+                .unwrap_or_else(|| "unset".to_string()),
+            Dimension::Kind => if record.travel { "travel" } else { "work" }.to_string(),
+        }
+    }
+
+    pub fn header(&self) -> &'static str {
+        match self {
+            Dimension::Day => "Day",
+            Dimension::Week => "Week",
+            Dimension::Month => "Month",
+            Dimension::Weekday => "Weekday",
+            Dimension::Location => "Location",
+            Dimension::Project => "Project",
+            Dimension::Kind => "Kind",
+        }
+    }
+
+    // Labels sort lexically except weekdays, which follow the calendar
+    fn sort(&self, labels: &mut [String]) {
+        match self {
+            Dimension::Weekday => labels.sort_by_key(|label| {
+                label
+                    .parse::<chrono::Weekday>()
+                    .map(|day| day.num_days_from_monday())
+                    .unwrap_or(u32::MAX)
+            }),
+            _ => labels.sort(),
+        }
+    }
+}
+
+// Colors projects are picked from when the config does not name one
+const PROJECT_PALETTE: [Color; 10] = [
+    Color::Cyan,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::DarkCyan,
+    Color::DarkGreen,
+    Color::DarkYellow,
+    Color::DarkBlue,
+    Color::DarkMagenta,
+];
+
+pub fn parse_color(name: &str) -> Option<Color> {
+    Some(
+        match name.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "red" => Color::Red,
+            "green" => Color::Green,
+            "yellow" => Color::Yellow,
+            "blue" => Color::Blue,
+            "magenta" => Color::Magenta,
+            "cyan" => Color::Cyan,
+            "white" => Color::White,
+            "grey" | "gray" => Color::Grey,
+            "darkred" => Color::DarkRed,
+            "darkgreen" => Color::DarkGreen,
+            "darkyellow" => Color::DarkYellow,
+            "darkblue" => Color::DarkBlue,
+            "darkmagenta" => Color::DarkMagenta,
+            "darkcyan" => Color::DarkCyan,
+            "darkgrey" | "darkgray" => Color::DarkGrey,
+            _ => return None,
+        },
+    )
+}
+
+// The color each project is shown in: the one set under [colors] in the
+// config, or else one picked from a hash of its name so it stays the same
+// from run to run
+#[derive(Debug, Clone, Default)]
+pub struct ProjectColors {
+    configured: HashMap<String, Color>,
+}
+
+impl ProjectColors {
+    pub fn from_config(colors: &HashMap<String, String>) -> Result<ProjectColors, String> {
+        let configured = colors
+            .iter()
+            .map(|(project, name)| match parse_color(name) {
+                Some(color) => Ok((project.clone(), color)),
+                None => Err(format!(
+                    "Unknown color '{}' for project '{}'",
+                    name, project
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(ProjectColors { configured })
+    }
+
+    pub fn color(&self, project: &str) -> Color {
+        if let Some(color) = self.configured.get(project) {
+            return *color;
+        }
+        // FNV-1a, unlike the std hasher it is fixed across Rust releases
+        let hash = project.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        PROJECT_PALETTE[(hash % PROJECT_PALETTE.len() as u64) as usize]
+    }
+
+    // A cell for a row label, colored when it names a project
+    fn label_cell(&self, dimension: Dimension, label: &str) -> Cell {
+        match dimension {
+            Dimension::Project if label != "unset" => Cell::new(label).fg(self.color(label)),
+            _ => Cell::new(label),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Pivot {
+    pub row_dimension: Dimension,
+    pub column_dimension: Option<Dimension>,
+    pub rows: Vec<String>,
+    pub columns: Vec<String>,
+    cells: HashMap<(String, String), Duration>,
+    // Money earned per row, when hourly rates are configured
+    earnings: Option<HashMap<String, f64>>,
+    colors: ProjectColors,
+}
+
+impl Pivot {
+    pub fn new(records: &[Record], rows: Dimension, columns: Option<Dimension>) -> Pivot {
+        let mut cells: HashMap<(String, String), Duration> = HashMap::new();
+        let mut row_labels = Vec::new();
+        let mut column_labels = Vec::new();
+
+        for record in records {
+            let row = rows.label(record);
+            let column = columns.map(|dim| dim.label(record)).unwrap_or_default();
+
+            if !row_labels.contains(&row) {
+                row_labels.push(row.clone());
+            }
+            if !column_labels.contains(&column) {
+                column_labels.push(column.clone());
+            }
+
+            *cells.entry((row, column)).or_insert_with(Duration::zero) += record.duration();
+        }
+
+        rows.sort(&mut row_labels);
+        if let Some(dim) = columns {
+            dim.sort(&mut column_labels);
+        }
+
+        Pivot {
+            row_dimension: rows,
+            column_dimension: columns,
+            rows: row_labels,
+            columns: column_labels,
+            cells,
+            earnings: None,
+            colors: ProjectColors::default(),
+        }
+    }
+
+    pub fn with_colors(mut self, colors: ProjectColors) -> Pivot {
+        self.colors = colors;
+        self
+    }
+
+    // Adds an earnings column from the hourly rate of each record's project.
+    // Records without a rated project earn nothing.
+    pub fn with_earnings(mut self, records: &[Record], rates: &HashMap<String, f64>) -> Pivot {
+        let mut earnings: HashMap<String, f64> = HashMap::new();
+        for record in records {
+            *earnings
+                .entry(self.row_dimension.label(record))
+                .or_default() += record_earnings(record, record.duration(), rates);
+        }
+        self.earnings = Some(earnings);
+        self
+    }
+
+    pub fn row_earnings(&self, row: &str) -> Option<f64> {
+        self.earnings
+            .as_ref()
+            .map(|earnings| earnings.get(row).copied().unwrap_or_default())
+    }
+
+    pub fn total_earnings(&self) -> Option<f64> {
+        self.earnings
+            .as_ref()
+            .map(|earnings| earnings.values().sum())
+    }
+
+    pub fn cell(&self, row: &str, column: &str) -> Duration {
+        self.cells
+            .get(&(row.to_string(), column.to_string()))
+            .copied()
+            .unwrap_or_else(Duration::zero)
+    }
+
+    pub fn row_total(&self, row: &str) -> Duration {
+        self.columns
+            .iter()
+            .fold(Duration::zero(), |acc, column| acc + self.cell(row, column))
+    }
+
+    pub fn column_total(&self, column: &str) -> Duration {
+        self.rows
+            .iter()
+            .fold(Duration::zero(), |acc, row| acc + self.cell(row, column))
+    }
+
+    pub fn total(&self) -> Duration {
+        self.cells
+            .values()
+            .fold(Duration::zero(), |acc, duration| acc + *duration)
+    }
+
+    pub fn to_table(&self) -> Table {
+        let mut header = vec![Cell::new(self.row_dimension.header()).fg(Color::Cyan)];
+        if self.column_dimension.is_some() {
+            header.extend(self.columns.iter().map(|c| Cell::new(c).fg(Color::Cyan)));
+        }
+        header.push(Cell::new("Total").fg(Color::Magenta));
+        if self.earnings.is_some() {
+            header.push(Cell::new("Earnings").fg(Color::Green));
+        }
+
+        let mut table = Table::new();
+        table
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(header);
+
+        for row in &self.rows {
+            let mut cells = vec![self.colors.label_cell(self.row_dimension, row)];
+            if self.column_dimension.is_some() {
+                cells.extend(
+                    self.columns
+                        .iter()
+                        .map(|column| Cell::new(format_duration(self.cell(row, column)))),
+                );
+            }
+            cells.push(Cell::new(format_duration(self.row_total(row))).fg(Color::Magenta));
+            if let Some(earnings) = self.row_earnings(row) {
+                cells.push(Cell::new(format_money(earnings)).fg(Color::Green));
+            }
+            table.add_row(cells);
+        }
+
+        if self.column_dimension.is_some() || self.earnings.is_some() {
+            let mut totals = vec![Cell::new("Total").fg(Color::Magenta)];
+            if self.column_dimension.is_some() {
+                totals.extend(self.columns.iter().map(|column| {
+                    Cell::new(format_duration(self.column_total(column))).fg(Color::Magenta)
+                }));
+            }
+            totals.push(Cell::new(format_duration(self.total())).fg(Color::Magenta));
+            if let Some(earnings) = self.total_earnings() {
+                totals.push(Cell::new(format_money(earnings)).fg(Color::Green));
+            }
+            table.add_row(totals);
+        }
+
+        table
+    }
+
+    // Hours are written as decimals so spreadsheets can sum them directly
+    pub fn to_csv(&self) -> String {
+        let mut header = vec![self.row_dimension.header().to_string()];
+        if self.column_dimension.is_some() {
+            header.extend(self.columns.iter().cloned());
+        }
+        header.push("Total".to_string());
+        if self.earnings.is_some() {
+            header.push("Earnings".to_string());
+        }
+
+        let mut lines = vec![header.join(",")];
+        for row in &self.rows {
+            let mut fields = vec![row.clone()];
+            if self.column_dimension.is_some() {
+                fields.extend(
+                    self.columns
+                        .iter()
+                        .map(|column| format_hours(self.cell(row, column))),
+                );
+            }
+            fields.push(format_hours(self.row_total(row)));
+            if let Some(earnings) = self.row_earnings(row) {
+                fields.push(format_money(earnings));
+            }
+            lines.push(fields.join(","));
+        }
+
+        lines.join("\n")
+    }
+
+    // Durations are whole minutes so scripts do not have to parse "7h 30m"
+    pub fn to_json(&self) -> Value {
+        let groups: Vec<Value> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let mut group = json!({
+                    "label": row,
+                    "total_minutes": self.row_total(row).num_minutes(),
+                });
+                if self.column_dimension.is_some() {
+                    group["columns"] = self
+                        .columns
+                        .iter()
+                        .map(|column| (column.clone(), json!(self.cell(row, column).num_minutes())))
+                        .collect::<serde_json::Map<_, _>>()
+                        .into();
+                }
+                if let Some(earnings) = self.row_earnings(row) {
+                    group["earnings"] = json!(round_money(earnings));
+                }
+                group
+            })
+            .collect();
+
+        let mut report = json!({
+            "by": self.row_dimension.header().to_lowercase(),
+            "then_by": self.column_dimension.map(|dim| dim.header().to_lowercase()),
+            "rows": groups,
+            "total_minutes": self.total().num_minutes(),
+        });
+        if let Some(earnings) = self.total_earnings() {
+            report["total_earnings"] = json!(round_money(earnings));
+        }
+        report
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoundingMode {
+    #[default]
+    Nearest,
+    Up,
+    Down,
+}
+
+pub fn round_duration(duration: Duration, increment: Duration, mode: RoundingMode) -> Duration {
+    let step = increment.num_seconds();
+    if step <= 0 {
+        return duration;
+    }
+
+    let seconds = duration.num_seconds();
+    let rounded = match mode {
+        RoundingMode::Down => seconds.div_euclid(step),
+        RoundingMode::Up => (seconds + step - 1).div_euclid(step),
+        RoundingMode::Nearest => (seconds + step / 2).div_euclid(step),
+    };
+
+    Duration::seconds(rounded * step)
+}
+
+// Time within start..end covered by worked (non-travel) records
+pub fn covered_time(start: NaiveDateTime, end: NaiveDateTime, records: &[Record]) -> Duration {
+    records
+        .iter()
+        .filter(|record| !record.travel)
+        .map(|record| {
+            let from = record.check_in.max(start);
+            let to = record.end().min(end);
+            (to - from).max(Duration::zero())
+        })
+        .fold(Duration::zero(), |acc, overlap| acc + overlap)
+}
+
+// Joins same-day records separated by at most `gap`, covering the gap as well.
+// Only affects reporting, the stored records are left untouched.
+pub fn merge_gaps(records: &[Record], gap: Duration) -> Vec<Record> {
+    let mut sorted = records.to_vec();
+    sorted.sort_by_key(|record| record.check_in);
+
+    let mut merged: Vec<Record> = Vec::new();
+    for record in sorted {
+        if let Some(last) = merged.last_mut() {
+            let between = record.check_in.signed_duration_since(last.end());
+            if !last.is_open()
+                && !record.is_open()
+                && last.date() == record.date()
+                && last.travel == record.travel
+                && last.project == record.project
+                && between <= gap
+                && record.check_in >= last.check_in
+            {
+                last.check_out = last.check_out.max(record.check_out);
+                last.unpaid_break += record.unpaid_break;
+                continue;
+            }
+        }
+        merged.push(record);
+    }
+
+    merged
+}
+
+pub fn serialize_minutes<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_i64(duration.num_minutes())
+}
+
+pub fn deserialize_minutes<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    i64::deserialize(d).map(Duration::minutes)
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DaySummary {
+    pub date: NaiveDate,
+    pub first_check_in: NaiveDateTime,
+    pub last_check_out: NaiveDateTime,
+    // Travel is tracked apart from worked time and never counted as a break
+    #[serde(rename = "worked_minutes", serialize_with = "serialize_minutes")]
+    #[schemars(with = "i64")]
+    pub worked: Duration,
+    #[serde(rename = "travel_minutes", serialize_with = "serialize_minutes")]
+    #[schemars(with = "i64")]
+    pub travel: Duration,
+}
+
+impl DaySummary {
+    // Gross presence from the first check-in to the last check-out
+    pub fn presence(&self) -> Duration {
+        self.last_check_out
+            .signed_duration_since(self.first_check_in)
+    }
+
+    pub fn break_time(&self) -> Duration {
+        (self.presence() - self.worked - self.travel).max(Duration::zero())
+    }
+}
+
+// Aggregates records per day, ordered by date ascending
+pub fn summarize_days(records: &[Record]) -> Vec<DaySummary> {
+    let mut days: Vec<DaySummary> = Vec::new();
+
+    for record in records {
+        let index = match days.iter().position(|day| day.date == record.date()) {
+            Some(index) => index,
+            None => {
+                days.push(DaySummary {
+                    date: record.date(),
+                    first_check_in: record.check_in,
+                    last_check_out: record.end(),
+                    worked: Duration::zero(),
+                    travel: Duration::zero(),
+                });
+                days.len() - 1
+            }
+        };
+
+        let day = &mut days[index];
+        day.first_check_in = day.first_check_in.min(record.check_in);
+        day.last_check_out = day.last_check_out.max(record.end());
+        if record.travel {
+            day.travel += record.duration();
+        } else {
+            day.worked += record.duration();
+        }
+    }
+
+    days.sort_by_key(|day| day.date);
+    days
+}
+
+// Median of the values, the mean of the middle two for an even count
+fn median(mut values: Vec<i64>) -> Option<i64> {
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    match values.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((values[middle - 1] + values[middle]) / 2),
+        _ => Some(values[middle]),
+    }
+}
+
+// Median first check-in and last check-out over the days, to the minute, and
+// whether that check-out is past midnight, on the day after the check-in
+pub fn typical_times(days: &[DaySummary]) -> Option<(NaiveTime, NaiveTime, bool)> {
+    let offset = |day: &DaySummary, time: NaiveDateTime| {
+        (time - day.date.and_time(NaiveTime::MIN)).num_minutes()
+    };
+    let check_in = median(days.iter().map(|d| offset(d, d.first_check_in)).collect())?;
+    let check_out = median(days.iter().map(|d| offset(d, d.last_check_out)).collect())?;
+
+    let time = |minutes: i64| NaiveTime::MIN + Duration::minutes(minutes);
+    let overnight = check_out >= 24 * 60;
+    Some((time(check_in), time(check_out), overnight))
+}
+
+// Worked time and number of days worked per ISO week, in date order
+pub fn week_totals(days: &[DaySummary]) -> Vec<(String, Duration, usize)> {
+    let mut weeks: Vec<(String, Duration, usize)> = Vec::new();
+    for day in days {
+        let week = day.date.format("%G-W%V").to_string();
+        match weeks.iter_mut().find(|(label, _, _)| *label == week) {
+            Some((_, worked, count)) => {
+                *worked += day.worked;
+                *count += 1;
+            }
+            None => weeks.push((week, day.worked, 1)),
+        }
+    }
+    weeks
+}
+
+// Hours expected per day and per week. With both set, the weekly target is
+// the one the overtime balance is kept against.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Targets {
+    pub daily: Option<Duration>,
+    pub weekly: Option<Duration>,
+}
+
+impl Targets {
+    pub fn is_set(&self) -> bool {
+        self.daily.is_some() || self.weekly.is_some()
+    }
+
+    // Overtime, or a deficit when negative, over the days. Days and weeks
+    // without entries are taken as time off and do not count against it.
+    pub fn balance(&self, days: &[DaySummary]) -> Duration {
+        match (self.weekly, self.daily) {
+            (Some(weekly), _) => week_totals(days)
+                .iter()
+                .fold(Duration::zero(), |acc, (_, worked, _)| {
+                    acc + *worked - weekly
+                }),
+            (None, Some(daily)) => days
+                .iter()
+                .fold(Duration::zero(), |acc, day| acc + day.worked - daily),
+            (None, None) => Duration::zero(),
+        }
+    }
+}
+
+// Colored green for overtime and red for a deficit
+fn delta_cell(delta: Duration) -> Cell {
+    let color = if delta < Duration::zero() {
+        Color::Red
+    } else {
+        Color::Green
+    };
+    Cell::new(format_delta(delta)).fg(color)
+}
+
+pub fn average(total: Duration, count: usize) -> Duration {
+    match count {
+        0 => Duration::zero(),
+        n => total / n as i32,
+    }
+}
+
+// Days with their worked time and, given targets, how far each day and week
+// is off them. Every week ends in a subtotal row with the average per day.
+pub fn period_report_table(days: &[DaySummary], targets: &Targets) -> Table {
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("Day").fg(Color::Cyan),
+            Cell::new("Worked").fg(Color::Yellow),
+            Cell::new("Average").fg(Color::Yellow),
+            Cell::new("vs target").fg(Color::Blue),
+        ]);
+
+    let weeks = week_totals(days);
+    for (week, worked, count) in &weeks {
+        for day in days
+            .iter()
+            .filter(|day| day.date.format("%G-W%V").to_string() == *week)
+        {
+            table.add_row(vec![
+                Cell::new(day.date.format("%a %Y-%m-%d")),
+                Cell::new(format_duration(day.worked)),
+                Cell::new(""),
+                targets
+                    .daily
+                    .map_or_else(|| Cell::new(""), |target| delta_cell(day.worked - target)),
+            ]);
+        }
+        table.add_row(vec![
+            Cell::new(week).fg(Color::Blue),
+            Cell::new(format_duration(*worked)).fg(Color::Blue),
+            Cell::new(format_duration(average(*worked, *count))).fg(Color::Blue),
+            targets
+                .weekly
+                .map_or_else(|| Cell::new(""), |target| delta_cell(*worked - target)),
+        ]);
+    }
+
+    let total = days
+        .iter()
+        .fold(Duration::zero(), |acc, day| acc + day.worked);
+    table.add_row(vec![
+        Cell::new("Total").fg(Color::Magenta),
+        Cell::new(format_duration(total)).fg(Color::Magenta),
+        Cell::new(format_duration(average(total, days.len()))).fg(Color::Magenta),
+        if targets.is_set() {
+            delta_cell(targets.balance(days))
+        } else {
+            Cell::new("")
+        },
+    ]);
+    table
+}
+
+pub fn period_report_json(days: &[DaySummary], targets: &Targets) -> Value {
+    let total = days
+        .iter()
+        .fold(Duration::zero(), |acc, day| acc + day.worked);
+    let weeks: Vec<Value> = week_totals(days)
+        .iter()
+        .map(|(week, worked, count)| {
+            let mut value = json!({
+                "week": week,
+                "worked_minutes": worked.num_minutes(),
+                "days": count,
+                "average_minutes": average(*worked, *count).num_minutes(),
+            });
+            if let Some(target) = targets.weekly {
+                value["target_delta_minutes"] = json!((*worked - target).num_minutes());
+            }
+            value
+        })
+        .collect();
+    let days: Vec<Value> = days
+        .iter()
+        .map(|day| {
+            let mut value = json!(day);
+            if let Some(target) = targets.daily {
+                value["target_delta_minutes"] = json!((day.worked - target).num_minutes());
+            }
+            value
+        })
+        .collect();
+
+    json!({
+        "target_minutes": targets.daily.map(|target| target.num_minutes()),
+        "weekly_target_minutes": targets.weekly.map(|target| target.num_minutes()),
+        "days": days,
+        "weeks": weeks,
+        "total_minutes": total.num_minutes(),
+        "average_minutes": average(total, days.len()).num_minutes(),
+    })
+}
+
+// Chat apps whose incoming webhooks can post a report as a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MessageFormat {
+    /// Slack Block Kit
+    Slack,
+    /// Microsoft Teams Adaptive Card
+    Teams,
+}
+
+// The figures a message leads with, as label and value
+fn message_totals(
+    days: &[DaySummary],
+    targets: &Targets,
+    balance: Option<Duration>,
+) -> Vec<(String, String)> {
+    let total = days
+        .iter()
+        .fold(Duration::zero(), |acc, day| acc + day.worked);
+    let mut totals = vec![
+        ("Worked".to_string(), format_duration(total)),
+        (
+            "Average per day".to_string(),
+            format_duration(average(total, days.len())),
+        ),
+    ];
+    if targets.is_set() {
+        totals.push(("vs target".to_string(), format_delta(targets.balance(days))));
+    }
+    if let Some(balance) = balance {
+        totals.push(("Overtime balance".to_string(), format_delta(balance)));
+    }
+    totals
+}
+
+// Each week's heading with its total, then a line per day
+fn message_weeks(days: &[DaySummary], targets: &Targets) -> Vec<(String, Vec<(String, String)>)> {
+    let with_delta = |worked: Duration, target: Option<Duration>| match target {
+        Some(target) => format!(
+            "{} ({})",
+            format_duration(worked),
+            format_delta(worked - target)
+        ),
+        None => format_duration(worked),
+    };
+    week_totals(days)
+        .into_iter()
+        .map(|(week, worked, _)| {
+            let lines = days
+                .iter()
+                .filter(|day| day.date.format("%G-W%V").to_string() == week)
+                .map(|day| {
+                    (
+                        day.date.format("%a %Y-%m-%d").to_string(),
+                        with_delta(day.worked, targets.daily),
+                    )
+                })
+                .collect();
+            (
+                format!("{}: {}", week, with_delta(worked, targets.weekly)),
+                lines,
+            )
+        })
+        .collect()
+}
+
+// The period report as a Slack message: the totals as fields and a section
+// per week, which keeps each well under Slack's length limit
+pub fn slack_message(
+    title: &str,
+    days: &[DaySummary],
+    targets: &Targets,
+    balance: Option<Duration>,
+) -> Value {
+    let totals = message_totals(days, targets, balance);
+    let mut blocks = vec![
+        json!({"type": "header", "text": {"type": "plain_text", "text": title}}),
+        json!({
+            "type": "section",
+            "fields": totals
+                .iter()
+                .map(|(label, value)| {
+                    json!({"type": "mrkdwn", "text": format!("*{}*\n{}", label, value)})
+                })
+                .collect::<Vec<_>>(),
+        }),
+    ];
+    for (week, lines) in message_weeks(days, targets) {
+        let lines: Vec<String> = lines
+            .iter()
+            .map(|(day, worked)| format!("{}  {}", day, worked))
+            .collect();
+        blocks.push(json!({"type": "divider"}));
+        blocks.push(json!({
+            "type": "section",
+            "text": {"type": "mrkdwn", "text": format!("*{}*\n{}", week, lines.join("\n"))},
+        }));
+    }
+
+    // Notifications and clients without blocks show the text instead
+    json!({
+        "text": format!("{}: {} worked", title, totals[0].1),
+        "blocks": blocks,
+    })
+}
+
+// The period report as a Teams message holding an Adaptive Card, with a
+// fact set for the totals and one per week
+pub fn teams_message(
+    title: &str,
+    days: &[DaySummary],
+    targets: &Targets,
+    balance: Option<Duration>,
+) -> Value {
+    let facts = |facts: &[(String, String)]| {
+        json!({
+            "type": "FactSet",
+            "facts": facts
+                .iter()
+                .map(|(title, value)| json!({"title": title, "value": value}))
+                .collect::<Vec<_>>(),
+        })
+    };
+    let mut body = vec![
+        json!({
+            "type": "TextBlock",
+            "text": title,
+            "size": "Medium",
+            "weight": "Bolder",
+            "wrap": true,
+        }),
+        facts(&message_totals(days, targets, balance)),
+    ];
+    for (week, lines) in message_weeks(days, targets) {
+        body.push(json!({
+            "type": "TextBlock",
+            "text": week,
+            "weight": "Bolder",
+            "separator": true,
+            "wrap": true,
+        }));
+        body.push(facts(&lines));
+    }
+
+    json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "contentUrl": null,
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": body,
+            },
+        }],
+    })
+}
+
+// Share of the total per row, drawn as a bar of up to BAR_WIDTH blocks
+pub fn breakdown_table(pivot: &Pivot) -> Table {
+    const BAR_WIDTH: f64 = 20.0;
+
+    let mut table = Table::new();
+    table
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new(pivot.row_dimension.header()).fg(Color::Cyan),
+            Cell::new("Duration").fg(Color::Yellow),
+            Cell::new("Share").fg(Color::Green),
+            Cell::new(""),
+        ]);
+
+    let total = pivot.total().num_minutes();
+    for row in &pivot.rows {
+        let minutes = pivot.row_total(row).num_minutes();
+        let share = if total > 0 {
+            minutes as f64 / total as f64
+        } else {
+            0.0
+        };
+
+        table.add_row(vec![
+            pivot.colors.label_cell(pivot.row_dimension, row),
+            Cell::new(format_duration(pivot.row_total(row))),
+            Cell::new(format!("{:.1}%", share * 100.0)),
+            Cell::new("█".repeat((share * BAR_WIDTH).round() as usize)).fg(Color::Green),
+        ]);
+    }
+
+    table
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    format!(
+        "{}h {}m",
+        duration.num_minutes() / 60,
+        duration.num_minutes() % 60
+    )
+}
+
+// One block for every two hours, rounded, so a full day reads at a glance
+pub fn duration_bar(duration: Duration) -> String {
+    "\u{2586}".repeat(((duration.num_minutes() + 60) / 120).max(0) as usize)
+}
+
+pub fn format_check_out(record: &Record) -> String {
+    match record.check_out {
+        // Entries running past midnight show how many days later they end
+        Some(check_out) if check_out.date() > record.check_in.date() => format!(
+            "{} (+{})",
+            check_out.format("%H:%M"),
+            (check_out.date() - record.check_in.date()).num_days()
+        ),
+        Some(check_out) => check_out.format("%H:%M").to_string(),
+        None => "open".to_string(),
+    }
+}
+
+pub fn format_delta(delta: Duration) -> String {
+    let sign = if delta < Duration::zero() { "-" } else { "+" };
+    format!("{}{}", sign, format_duration(delta.abs()))
+}
+
+pub fn format_relative_date(date: NaiveDate, today: NaiveDate) -> String {
+    let days = (today - date).num_days();
+    match days {
+        0 => "today".to_string(),
+        1 => "yesterday".to_string(),
+        -1 => "tomorrow".to_string(),
+        d if d < 0 => format!("in {} days", -d),
+        2..=13 => format!("{} days ago", days),
+        14..=59 => format!("{} weeks ago", days / 7),
+        60..=729 => format!("{} months ago", days / 30),
+        _ => format!("{} years ago", days / 365),
+    }
+}
+
+pub fn format_hours(duration: Duration) -> String {
+    format!("{:.2}", duration.num_minutes() as f64 / 60.0)
+}
+
+pub fn format_money(amount: f64) -> String {
+    format!("{:.2}", amount)
+}
+
+fn round_money(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+// What the duration earns at the hourly rate of the record's project
+pub fn record_earnings(record: &Record, duration: Duration, rates: &HashMap<String, f64>) -> f64 {
+    let rate = record
+        .project
+        .as_ref()
+        .and_then(|project| rates.get(project))
+        .copied()
+        .unwrap_or_default();
+    duration.num_seconds() as f64 / 3600.0 * rate
+}
+
+// What one project earned against the hours spent on it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profitability {
+    pub project: String,
+    pub hours: Duration,
+    // None when the project has neither a rate nor a budget
+    pub earned: Option<f64>,
+    // Earned is the fixed-bid budget rather than hours at the rate
+    pub fixed_bid: bool,
+}
+
+impl Profitability {
+    // Effective earnings per hour worked
+    pub fn per_hour(&self) -> Option<f64> {
+        let hours = self.hours.num_seconds() as f64 / 3600.0;
+        self.earned
+            .filter(|_| hours > 0.0)
+            .map(|earned| earned / hours)
+    }
+}
+
+// Per project, a fixed-bid budget is what the hours earned, else they earn
+// the project's hourly rate. Travel and open entries are left out. The best
+// paid projects come first.
+pub fn profitability(
+    records: &[Record],
+    rates: &HashMap<String, f64>,
+    budgets: &HashMap<String, f64>,
+) -> Vec<Profitability> {
+    let mut hours: HashMap<&str, Duration> = HashMap::new();
+    for record in records.iter().filter(|r| !r.travel && !r.is_open()) {
+        if let Some(project) = &record.project {
+            *hours.entry(project).or_insert_with(Duration::zero) += record.duration();
+        }
+    }
+
+    let mut projects: Vec<Profitability> = hours
+        .into_iter()
+        .map(|(project, hours)| {
+            let budget = budgets.get(project).copied();
+            let rate = rates.get(project).copied();
+            Profitability {
+                project: project.to_string(),
+                hours,
+                earned: budget
+                    .or(rate.map(|rate| hours.num_seconds() as f64 / 3600.0 * rate))
+                    .map(round_money),
+                fixed_bid: budget.is_some(),
+            }
+        })
+        .collect();
+    projects.sort_by(|a, b| {
+        b.per_hour()
+            .unwrap_or(f64::MIN)
+            .total_cmp(&a.per_hour().unwrap_or(f64::MIN))
+            .then_with(|| a.project.cmp(&b.project))
+    });
+    projects
+}
+
+// A markdown digest for a weekly review: headline numbers, then each
+// project with the notes on its entries, then the day notes
+pub fn retro_markdown(title: &str, records: &[Record], notes: &[Note]) -> String {
+    let worked: Vec<Record> = records.iter().filter(|r| !r.travel).cloned().collect();
+    let mut lines = vec![format!("# Retro {}", title), String::new()];
+
+    let days = summarize_days(&worked);
+    let total = days
+        .iter()
+        .fold(Duration::zero(), |acc, day| acc + day.worked);
+    lines.push(format!(
+        "- Worked: {} over {} {}",
+        format_duration(total),
+        days.len(),
+        if days.len() == 1 { "day" } else { "days" }
+    ));
+
+    let pivot = Pivot::new(&worked, Dimension::Project, None);
+    let mut projects = pivot.rows.clone();
+    projects.sort_by_key(|project| std::cmp::Reverse(pivot.row_total(project)));
+    if let Some(top) = projects.first() {
+        lines.push(format!(
+            "- Most worked project: {} ({})",
+            top,
+            format_duration(pivot.row_total(top))
+        ));
+    }
+    if let Some(biggest) = days.iter().max_by_key(|day| day.worked) {
+        lines.push(format!(
+            "- Biggest day: {} ({})",
+            biggest.date.format("%a %Y-%m-%d"),
+            format_duration(biggest.worked)
+        ));
+    }
+
+    for project in &projects {
+        lines.push(String::new());
+        lines.push(format!(
+            "## {} ({})",
+            project,
+            format_duration(pivot.row_total(project))
+        ));
+        let mut entries: Vec<&Record> = worked
+            .iter()
+            .filter(|r| Dimension::Project.label(r) == *project)
+            .collect();
+        entries.sort_by_key(|record| record.check_in);
+        let annotated: Vec<String> = entries
+            .iter()
+            .filter_map(|record| {
+                let note = record.note.as_ref()?;
+                Some(format!(
+                    "- {}: {}",
+                    record.date().format("%a %Y-%m-%d"),
+                    note
+                ))
+            })
+            .collect();
+        if annotated.is_empty() {
+            lines.push("- no notes".to_string());
+        }
+        lines.extend(annotated);
+    }
+
+    if !notes.is_empty() {
+        lines.push(String::new());
+        lines.push("## Notes".to_string());
+        for note in notes {
+            lines.push(format!(
+                "- {}: {}",
+                note.date.format("%a %Y-%m-%d"),
+                note.text
+            ));
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+// Thermal receipt printers fit 32 characters per line on 58mm paper
+const RECEIPT_WIDTH: usize = 32;
+
+// Left text and right-aligned value on one receipt line, the text cut short
+// when both do not fit
+fn receipt_line(left: &str, right: &str) -> String {
+    let room = RECEIPT_WIDTH.saturating_sub(right.len() + 1);
+    let left: String = left.chars().take(room).collect();
+    format!("{:<room$} {}", left, right)
+}
+
+// A narrow plain-text summary of one day, meant for printing
+pub fn day_receipt(date: NaiveDate, records: &[Record]) -> String {
+    let double = "=".repeat(RECEIPT_WIDTH);
+    let single = "-".repeat(RECEIPT_WIDTH);
+    let centered = |text: &str| {
+        format!("{:^width$}", text, width = RECEIPT_WIDTH)
+            .trim_end()
+            .to_string()
+    };
+
+    let mut sorted: Vec<&Record> = records.iter().filter(|r| r.date() == date).collect();
+    sorted.sort_by_key(|record| record.check_in);
+
+    let mut lines = vec![
+        double.clone(),
+        centered("WORK RECEIPT"),
+        centered(&date.format("%a %Y-%m-%d").to_string()),
+        double.clone(),
+    ];
+    for record in &sorted {
+        let mut item = format!(
+            "{}-{}",
+            record.check_in.format("%H:%M"),
+            format_check_out(record)
+        );
+        if let Some(project) = &record.project {
+            item = format!("{} {}", item, project);
+        }
+        if record.travel {
+            item = format!("{} travel", item);
+        }
+        lines.push(receipt_line(&item, &format_duration(record.duration())));
+    }
+    if sorted.is_empty() {
+        lines.push(centered("no entries"));
+    }
+    lines.push(single);
+
+    let owned: Vec<Record> = sorted.into_iter().cloned().collect();
+    if let Some(day) = summarize_days(&owned).first() {
+        lines.push(receipt_line("WORKED", &format_duration(day.worked)));
+        lines.push(receipt_line("BREAKS", &format_duration(day.break_time())));
+        if day.travel > Duration::zero() {
+            lines.push(receipt_line("TRAVEL", &format_duration(day.travel)));
+        }
+    }
+    lines.push(double.clone());
+    lines.push(centered("THANK YOU"));
+    lines.push(double);
+
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(day: u32, start: u32, end: u32) -> Record {
+        Record::test_entry(
+            Record::test_time(day, start),
+            Some(Record::test_time(day, end)),
+        )
+    }
+
+    #[test]
+    fn test_single_dimension() {
+        let records = vec![record(8, 9, 12), record(8, 13, 17), record(9, 9, 11)];
+        let pivot = Pivot::new(&records, Dimension::Day, None);
+
+        assert_eq!(pivot.rows, vec!["2024-07-08", "2024-07-09"]);
+        assert_eq!(pivot.row_total("2024-07-08"), Duration::hours(7));
+        assert_eq!(pivot.total(), Duration::hours(9));
+    }
+
+    #[test]
+    fn test_two_dimensions() {
+        // 2024-07-08 is a Monday in week 28, 2024-07-15 a Monday in week 29
+        let records = vec![record(15, 9, 10), record(8, 9, 12), record(9, 9, 11)];
+        let pivot = Pivot::new(&records, Dimension::Weekday, Some(Dimension::Week));
+
+        assert_eq!(pivot.rows, vec!["Mon", "Tue"]);
+        assert_eq!(pivot.columns, vec!["2024-W28", "2024-W29"]);
+        assert_eq!(pivot.cell("Mon", "2024-W28"), Duration::hours(3));
+        assert_eq!(pivot.cell("Tue", "2024-W29"), Duration::zero());
+        assert_eq!(pivot.column_total("2024-W28"), Duration::hours(5));
+    }
+
+    #[test]
+    fn test_day_summary() {
+        let records = vec![record(9, 13, 17), record(8, 9, 12), record(8, 13, 17)];
+        let days = summarize_days(&records);
+
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].first_check_in.format("%H:%M").to_string(), "09:00");
+        assert_eq!(days[0].last_check_out.format("%H:%M").to_string(), "17:00");
+        assert_eq!(days[0].worked, Duration::hours(7));
+        assert_eq!(days[0].presence(), Duration::hours(8));
+        assert_eq!(days[0].break_time(), Duration::hours(1));
+
+        let mut travel = record(9, 8, 9);
+        travel.travel = true;
+        let days = summarize_days(&[travel, record(9, 10, 12)]);
+        assert_eq!(days[0].worked, Duration::hours(2));
+        assert_eq!(days[0].travel, Duration::hours(1));
+        assert_eq!(days[0].break_time(), Duration::hours(1));
+    }
+
+    #[test]
+    fn test_typical_times() {
+        let days = summarize_days(&[record(1, 8, 16), record(8, 9, 17), record(15, 10, 19)]);
+        let time = |h| NaiveTime::from_hms_opt(h, 0, 0).unwrap();
+        assert_eq!(typical_times(&days), Some((time(9), time(17), false)));
+        assert_eq!(
+            typical_times(&days[..2]),
+            Some((
+                time(8) + Duration::minutes(30),
+                time(16) + Duration::minutes(30),
+                false
+            ))
+        );
+        assert_eq!(typical_times(&[]), None);
+
+        // Night shifts end on the next day
+        let night = |day| {
+            let mut shift = record(day, 22, 23);
+            shift.check_out = shift.check_out.map(|t| t + Duration::hours(7));
+            shift
+        };
+        let days = summarize_days(&[night(1), night(8), record(15, 21, 23)]);
+        assert_eq!(typical_times(&days), Some((time(22), time(6), true)));
+    }
+
+    #[test]
+    fn test_target_balance() {
+        // Two days in week 28 and one in week 29
+        let days = summarize_days(&[record(8, 9, 18), record(9, 9, 16), record(15, 9, 17)]);
+        let daily = Targets {
+            daily: Some(Duration::hours(8)),
+            weekly: None,
+        };
+        assert_eq!(daily.balance(&days), Duration::zero());
+
+        let weekly = Targets {
+            weekly: Some(Duration::hours(10)),
+            ..daily
+        };
+        assert_eq!(weekly.balance(&days), Duration::hours(4));
+        assert_eq!(Targets::default().balance(&days), Duration::zero());
+    }
+
+    #[test]
+    fn test_project_colors() {
+        let colors = ProjectColors::from_config(&HashMap::from([(
+            "acme".to_string(),
+            "dark-red".to_string(),
+        )]))
+        .unwrap();
+        assert_eq!(colors.color("acme"), Color::DarkRed);
+        assert_eq!(colors.color("internal"), colors.color("internal"));
+        assert_eq!(
+            ProjectColors::default().color("internal"),
+            colors.color("internal")
+        );
+        assert!(ProjectColors::from_config(&HashMap::from([(
+            "acme".to_string(),
+            "plaid".to_string()
+        )]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_week_totals() {
+        // 2024-07-12 is the Friday of week 28, 2024-07-15 the Monday of week 29
+        let records = vec![record(12, 9, 17), record(11, 9, 13), record(15, 9, 12)];
+        let weeks = week_totals(&summarize_days(&records));
+        assert_eq!(
+            weeks,
+            vec![
+                ("2024-W28".to_string(), Duration::hours(12), 2),
+                ("2024-W29".to_string(), Duration::hours(3), 1),
+            ]
+        );
+        assert_eq!(average(weeks[0].1, weeks[0].2), Duration::hours(6));
+        assert_eq!(average(Duration::hours(1), 0), Duration::zero());
+    }
+
+    #[test]
+    fn test_merge_gaps() {
+        let mut short_break = record(8, 12, 13);
+        short_break.check_in += Duration::minutes(3);
+        let records = vec![record(8, 9, 12), short_break, record(8, 14, 17)];
+
+        let merged = merge_gaps(&records, Duration::minutes(5));
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].duration(), Duration::hours(4));
+        assert_eq!(merged[1].duration(), Duration::hours(3));
+        assert_eq!(merge_gaps(&records, Duration::hours(1)).len(), 1);
+    }
+
+    #[test]
+    fn test_covered_time() {
+        let records = vec![record(8, 9, 12), record(8, 13, 17), record(9, 9, 17)];
+        let at = |hour| Record::test_time(8, hour);
+        assert_eq!(covered_time(at(11), at(14), &records), Duration::hours(2));
+        assert_eq!(covered_time(at(12), at(13), &records), Duration::zero());
+        assert_eq!(covered_time(at(8), at(18), &records), Duration::hours(7));
+    }
+
+    #[test]
+    fn test_rounding() {
+        let quarter = Duration::minutes(15);
+        let raw = Duration::minutes(7 * 60 + 53);
+
+        assert_eq!(
+            round_duration(raw, quarter, RoundingMode::Nearest),
+            Duration::hours(8)
+        );
+        assert_eq!(
+            round_duration(raw, quarter, RoundingMode::Down),
+            Duration::minutes(7 * 60 + 45)
+        );
+        assert_eq!(
+            round_duration(Duration::minutes(7 * 60 + 46), quarter, RoundingMode::Up),
+            Duration::hours(8)
+        );
+        assert_eq!(
+            round_duration(Duration::minutes(7), quarter, RoundingMode::Nearest),
+            Duration::zero()
+        );
+        assert_eq!(
+            round_duration(Duration::hours(2), quarter, RoundingMode::Up),
+            Duration::hours(2)
+        );
+    }
+
+    #[test]
+    fn test_duration_bar() {
+        assert_eq!(
+            duration_bar(Duration::minutes(7 * 60 + 54)),
+            "\u{2586}".repeat(4)
+        );
+        assert_eq!(duration_bar(Duration::minutes(59)), "");
+        assert_eq!(duration_bar(Duration::hours(1)), "\u{2586}");
+    }
+
+    #[test]
+    fn test_relative_dates() {
+        let today = NaiveDate::from_ymd_opt(2024, 7, 12).unwrap();
+        let ago = |days| format_relative_date(today - Duration::days(days), today);
+
+        assert_eq!(ago(0), "today");
+        assert_eq!(ago(1), "yesterday");
+        assert_eq!(ago(2), "2 days ago");
+        assert_eq!(ago(20), "2 weeks ago");
+        assert_eq!(ago(90), "3 months ago");
+        assert_eq!(ago(800), "2 years ago");
+        assert_eq!(ago(-3), "in 3 days");
+    }
+
+    #[test]
+    fn test_json_output() {
+        let records = vec![record(8, 9, 12), record(9, 9, 10)];
+        let pivot = Pivot::new(&records, Dimension::Day, None);
+        assert_eq!(
+            pivot.to_json(),
+            json!({
+                "by": "day",
+                "then_by": null,
+                "rows": [
+                    {"label": "2024-07-08", "total_minutes": 180},
+                    {"label": "2024-07-09", "total_minutes": 60},
+                ],
+                "total_minutes": 240,
+            })
+        );
+
+        let days = summarize_days(&records);
+        let targets = Targets {
+            daily: Some(Duration::hours(2)),
+            weekly: None,
+        };
+        let report = period_report_json(&days, &targets);
+        assert_eq!(report["days"][0]["worked_minutes"], 180);
+        assert_eq!(report["days"][1]["target_delta_minutes"], -60);
+        assert_eq!(report["average_minutes"], 120);
+    }
+
+    #[test]
+    fn test_message_payloads() {
+        let days = summarize_days(&[record(8, 9, 18), record(9, 9, 16), record(15, 9, 17)]);
+        let targets = Targets {
+            daily: Some(Duration::hours(8)),
+            weekly: None,
+        };
+
+        let slack = slack_message("Report", &days, &targets, None);
+        assert_eq!(slack["text"], "Report: 24h 0m worked");
+        assert_eq!(slack["blocks"][0]["type"], "header");
+        assert_eq!(slack["blocks"][1]["fields"].as_array().unwrap().len(), 3);
+        // A divider and a section for each of the two weeks
+        assert_eq!(slack["blocks"].as_array().unwrap().len(), 6);
+        assert_eq!(
+            slack["blocks"][3]["text"]["text"],
+            "*2024-W28: 16h 0m*\nMon 2024-07-08  9h 0m (+1h 0m)\nTue 2024-07-09  7h 0m (-1h 0m)"
+        );
+
+        let teams = teams_message("Report", &days, &targets, Some(Duration::hours(2)));
+        let card = &teams["attachments"][0]["content"];
+        assert_eq!(card["type"], "AdaptiveCard");
+        assert_eq!(card["body"][1]["facts"][3]["value"], "+2h 0m");
+        assert_eq!(card["body"][3]["facts"][1]["title"], "Tue 2024-07-09");
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let records = vec![record(8, 9, 12), record(9, 9, 10)];
+        let pivot = Pivot::new(&records, Dimension::Month, Some(Dimension::Weekday));
+
+        assert_eq!(
+            pivot.to_csv(),
+            "Month,Mon,Tue,Total\n2024-07,3.00,1.00,4.00"
+        );
+    }
+
+    #[test]
+    fn test_earnings() {
+        let billed = |project: &str, day, start, end| Record {
+            project: Some(project.to_string()),
+            ..record(day, start, end)
+        };
+        let records = vec![
+            billed("acme", 8, 9, 12),
+            billed("acme", 9, 9, 10),
+            billed("internal", 9, 13, 17),
+        ];
+        let rates = HashMap::from([("acme".to_string(), 80.0)]);
+        let pivot = Pivot::new(&records, Dimension::Day, None).with_earnings(&records, &rates);
+
+        assert_eq!(pivot.row_earnings("2024-07-08"), Some(240.0));
+        assert_eq!(pivot.row_earnings("2024-07-09"), Some(80.0));
+        assert_eq!(pivot.total_earnings(), Some(320.0));
+        assert_eq!(
+            pivot.to_csv(),
+            "Day,Total,Earnings\n2024-07-08,3.00,240.00\n2024-07-09,5.00,80.00"
+        );
+        assert_eq!(pivot.to_json()["total_earnings"], 320.0);
+        assert_eq!(
+            Pivot::new(&records, Dimension::Day, None).total_earnings(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_profitability() {
+        let billed = |project: &str, day, start, end| Record {
+            project: Some(project.to_string()),
+            ..record(day, start, end)
+        };
+        let records = vec![
+            billed("acme", 8, 9, 17),
+            billed("acme", 9, 9, 11),
+            billed("bid", 9, 13, 17),
+            billed("internal", 10, 9, 10),
+            record(10, 13, 14),
+        ];
+        let rates = HashMap::from([("acme".to_string(), 80.0)]);
+        let budgets = HashMap::from([("bid".to_string(), 600.0)]);
+
+        let projects = profitability(&records, &rates, &budgets);
+        let summary: Vec<(&str, Option<f64>, Option<f64>)> = projects
+            .iter()
+            .map(|p| (p.project.as_str(), p.earned, p.per_hour()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("bid", Some(600.0), Some(150.0)),
+                ("acme", Some(800.0), Some(80.0)),
+                ("internal", None, None),
+            ]
+        );
+        assert!(projects[0].fixed_bid);
+    }
+
+    #[test]
+    fn test_overnight_entry() {
+        let night = Record {
+            check_out: NaiveDate::from_ymd_opt(2024, 7, 9)
+                .unwrap()
+                .and_hms_opt(6, 0, 0),
+            ..record(8, 22, 23)
+        };
+        assert_eq!(night.duration(), Duration::hours(8));
+        assert_eq!(format_check_out(&night), "06:00 (+1)");
+        assert_eq!(format_check_out(&record(8, 9, 12)), "12:00");
+
+        let days = summarize_days(&[night]);
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].worked, Duration::hours(8));
+    }
+
+    #[test]
+    fn test_unpaid_breaks() {
+        let lunch = Record {
+            unpaid_break: Duration::minutes(45),
+            ..record(8, 9, 17)
+        };
+        assert_eq!(lunch.duration(), Duration::minutes(435));
+
+        let days = summarize_days(&[lunch]);
+        assert_eq!(days[0].worked, Duration::minutes(435));
+        assert_eq!(days[0].break_time(), Duration::minutes(45));
+    }
+
+    #[test]
+    fn test_receipt() {
+        let lunch = Record {
+            project: Some("a very long project name".to_string()),
+            ..record(8, 9, 12)
+        };
+        let receipt = day_receipt(lunch.date(), &[record(8, 13, 17), lunch, record(9, 9, 10)]);
+
+        assert!(receipt
+            .lines()
+            .all(|line| line.chars().count() <= RECEIPT_WIDTH));
+        assert!(receipt.contains("09:00-12:00 a very long pr 3h 0m\n13:00-17:00"));
+        assert!(receipt.contains("WORKED                     7h 0m\n"));
+        assert!(receipt.contains("BREAKS                     1h 0m\n"));
+        assert!(!receipt.contains("TRAVEL"));
+    }
+
+    #[test]
+    fn test_retro_markdown() {
+        let acme = |day, start, end, note: Option<&str>| Record {
+            project: Some("acme".to_string()),
+            note: note.map(str::to_string),
+            ..record(day, start, end)
+        };
+        let records = vec![
+            acme(8, 9, 17, Some("shipped the importer")),
+            acme(9, 9, 12, None),
+            record(9, 13, 15),
+        ];
+        let notes = vec![Note {
+            id: 1,
+            date: NaiveDate::from_ymd_opt(2024, 7, 9).unwrap(),
+            text: "planning day".to_string(),
+        }];
+
+        assert_eq!(
+            retro_markdown("2024-W28", &records, &notes),
+            "# Retro 2024-W28\n\n\
+             - Worked: 13h 0m over 2 days\n\
+             - Most worked project: acme (11h 0m)\n\
+             - Biggest day: Mon 2024-07-08 (8h 0m)\n\n\
+             ## acme (11h 0m)\n\
+             - Mon 2024-07-08: shipped the importer\n\n\
+             ## unset (2h 0m)\n\
+             - no notes\n\n\
+             ## Notes\n\
+             - Tue 2024-07-09: planning day\n"
+        );
+    }
+}
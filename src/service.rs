@@ -0,0 +1,264 @@
+use std::path::Path;
+
+use chrono::{Duration, NaiveDateTime};
+
+use crate::app::TimeKeeperError;
+use crate::db::*;
+use crate::period::Period;
+use crate::report::{format_check_out, summarize_days, DaySummary, Dimension, Pivot};
+
+// The core of TimeKeeper as a library: every method works on the one
+// database the struct was opened on and returns what it did rather than
+// printing it, so other front ends can be built on the same rules as the CLI.
+pub struct TimeKeeper {
+    db: Db,
+}
+
+impl TimeKeeper {
+    pub fn new(db: Db) -> TimeKeeper {
+        TimeKeeper { db }
+    }
+
+    // The database the CLI uses: from --db, a .timekeeper marker or the
+    // platform data directory
+    pub fn open_default() -> Result<TimeKeeper, TimeKeeperError> {
+        Ok(TimeKeeper::new(Db::open_default()?))
+    }
+
+    // Creates the database when missing and brings its schema up to date
+    pub fn open(path: &Path) -> Result<TimeKeeper, TimeKeeperError> {
+        Ok(TimeKeeper::new(Db::open(path)?))
+    }
+
+    pub fn open_in_memory() -> Result<TimeKeeper, TimeKeeperError> {
+        Ok(TimeKeeper::new(Db::open_in_memory()?))
+    }
+
+    // The storage underneath, for what the struct has no method of its own for
+    pub fn db(&self) -> &Db {
+        &self.db
+    }
+
+    fn session(
+        at: NaiveDateTime,
+        project: Option<String>,
+        location: Option<String>,
+        travel: bool,
+    ) -> Record {
+        Record {
+            id: 0,
+            check_in: at,
+            check_out: None,
+            location,
+            travel,
+            project,
+            note: None,
+            unpaid_break: Duration::zero(),
+        }
+    }
+
+    // Starts a session at the given time. Only one may be open at a time,
+    // whichever day it started on.
+    pub fn check_in(
+        &self,
+        at: NaiveDateTime,
+        project: Option<String>,
+        location: Option<String>,
+        travel: bool,
+    ) -> Result<Record, TimeKeeperError> {
+        if let Some(open) = self.open_session()? {
+            return Err(TimeKeeperError::AlreadyCheckedIn(open.check_in));
+        }
+        Ok(self
+            .db
+            .save_entry(&TimeKeeper::session(at, project, location, travel))?)
+    }
+
+    // Closes the open session at the given time and starts the next one
+    // then. Returns the closed session and the new one.
+    pub fn switch(
+        &self,
+        at: NaiveDateTime,
+        project: Option<String>,
+        location: Option<String>,
+        travel: bool,
+    ) -> Result<(Record, Record), TimeKeeperError> {
+        let open = self
+            .open_session()?
+            .ok_or(TimeKeeperError::NoCheckInRecord)?;
+        if at <= open.check_in {
+            return Err(TimeKeeperError::CheckOutBeforeCheckIn);
+        }
+
+        let closed = Record {
+            check_out: Some(at),
+            ..open
+        };
+        let opened = self
+            .db
+            .switch_entry(&closed, &TimeKeeper::session(at, project, location, travel))?;
+        Ok((closed, opened))
+    }
+
+    // The session a check-out closes: the latest one still open, however
+    // many days ago it started, such as a night shift or one left running
+    pub fn open_session(&self) -> Result<Option<Record>, TimeKeeperError> {
+        Ok(self.db.get_open_entry()?)
+    }
+
+    // Checks out of the open session by its id. A break still running in it
+    // ends at the check-out.
+    pub fn close(&self, id: i32, at: NaiveDateTime) -> Result<Record, TimeKeeperError> {
+        let record = self
+            .entry(id)?
+            .ok_or_else(|| TimeKeeperError::ParseError(format!("No entry with id {}", id)))?;
+        if !record.is_open() {
+            return Err(TimeKeeperError::ParseError(format!(
+                "Entry {} is already checked out at {}",
+                id,
+                format_check_out(&record)
+            )));
+        }
+        if at <= record.check_in {
+            return Err(TimeKeeperError::CheckOutBeforeCheckIn);
+        }
+
+        self.db.close_entry(&Record {
+            check_out: Some(at),
+            ..record
+        })?;
+        Ok(self
+            .entry(id)?
+            .expect("a closed entry is still in the database"))
+    }
+
+    // Checks out of the open session at the given time
+    pub fn check_out(&self, at: NaiveDateTime) -> Result<Record, TimeKeeperError> {
+        let open = self
+            .open_session()?
+            .ok_or(TimeKeeperError::NoCheckInRecord)?;
+        self.close(open.id, at)
+    }
+
+    // Saves a complete entry, returning it with its id
+    pub fn add(&self, record: &Record) -> Result<Record, TimeKeeperError> {
+        if record
+            .check_out
+            .is_some_and(|check_out| check_out <= record.check_in)
+        {
+            return Err(TimeKeeperError::CheckOutBeforeCheckIn);
+        }
+        Ok(self.db.save_entry(record)?)
+    }
+
+    pub fn entry(&self, id: i32) -> Result<Option<Record>, TimeKeeperError> {
+        Ok(self.db.get_entry(id)?)
+    }
+
+    // Entries whose logical day falls in the period, or all of them
+    pub fn entries(&self, period: Option<Period>) -> Result<Vec<Record>, TimeKeeperError> {
+        Ok(match period {
+            Some(period) => self.db.get_entries_between(period.from, period.to)?,
+            None => self.db.get_all_entries()?,
+        })
+    }
+
+    // Worked and travel time per day with entries
+    pub fn days(&self, period: Option<Period>) -> Result<Vec<DaySummary>, TimeKeeperError> {
+        Ok(summarize_days(&self.entries(period)?))
+    }
+
+    // Tracked time grouped by one dimension, or two for a pivot table
+    pub fn report(
+        &self,
+        period: Option<Period>,
+        rows: Dimension,
+        columns: Option<Dimension>,
+    ) -> Result<Pivot, TimeKeeperError> {
+        Ok(Pivot::new(&self.entries(period)?, rows, columns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions() {
+        let keeper = TimeKeeper::open_in_memory().unwrap();
+        let at = |hour| Record::test_time(8, hour);
+
+        let first = keeper
+            .check_in(at(9), Some("acme".to_string()), None, false)
+            .unwrap();
+        assert!(matches!(
+            keeper.check_in(at(10), None, None, false),
+            Err(TimeKeeperError::AlreadyCheckedIn(_))
+        ));
+        let (closed, opened) = keeper.switch(at(12), None, None, false).unwrap();
+        assert_eq!(closed.id, first.id);
+        assert_eq!(closed.duration(), Duration::hours(3));
+        assert!(matches!(
+            keeper.check_out(at(11)),
+            Err(TimeKeeperError::CheckOutBeforeCheckIn)
+        ));
+        let second = keeper.check_out(at(17)).unwrap();
+        assert_eq!(second.id, opened.id);
+        assert_eq!(second.duration(), Duration::hours(5));
+        assert!(keeper.close(second.id, at(18)).is_err());
+        assert!(matches!(
+            keeper.check_out(at(18)),
+            Err(TimeKeeperError::NoCheckInRecord)
+        ));
+
+        let days = keeper.days(None).unwrap();
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].worked, Duration::hours(8));
+        let pivot = keeper.report(None, Dimension::Project, None).unwrap();
+        assert_eq!(pivot.row_total("acme"), Duration::hours(3));
+    }
+
+    #[test]
+    fn test_session_left_open_for_days() {
+        let keeper = TimeKeeper::open_in_memory().unwrap();
+        let at = Record::test_time;
+
+        // Left open on Friday, still the open session on Monday
+        let friday = keeper.check_in(at(5, 9), None, None, false).unwrap();
+        assert!(matches!(
+            keeper.check_in(at(8, 9), None, None, false),
+            Err(TimeKeeperError::AlreadyCheckedIn(_))
+        ));
+        assert_eq!(keeper.open_session().unwrap().unwrap().id, friday.id);
+        let (closed, opened) = keeper.switch(at(8, 9), None, None, false).unwrap();
+        assert_eq!(closed.id, friday.id);
+        assert_eq!(closed.duration(), Duration::hours(72));
+        assert_eq!(keeper.open_session().unwrap().unwrap().id, opened.id);
+        let monday = keeper.check_out(at(8, 17)).unwrap();
+        assert_eq!(monday.id, opened.id);
+        assert!(keeper.open_session().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_check_out_closes_the_open_session() {
+        let keeper = TimeKeeper::open_in_memory().unwrap();
+        let at = |hour| Record::test_time(8, hour);
+        // A completed record added after the open session is left alone
+        let open = keeper.add(&Record::test_entry(at(9), None)).unwrap();
+        let later = keeper
+            .add(&Record::test_entry(at(13), Some(at(15))))
+            .unwrap();
+        let closed = keeper.check_out(at(12)).unwrap();
+        assert_eq!(closed.id, open.id);
+        assert_eq!(keeper.entry(later.id).unwrap().unwrap().end(), at(15));
+
+        // A completed record is never checked out again
+        let Err(TimeKeeperError::ParseError(message)) = keeper.close(later.id, at(16)) else {
+            panic!("closing a completed record must fail");
+        };
+        assert_eq!(
+            message,
+            format!("Entry {} is already checked out at 15:00", later.id)
+        );
+    }
+}
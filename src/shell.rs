@@ -0,0 +1,163 @@
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Context, Editor, Helper, Highlighter, Hinter, Validator};
+
+use crate::app::*;
+use crate::parser::get_now;
+use crate::paths::{ensure_dir, Paths};
+use crate::period::PeriodArgs;
+use crate::report::Dimension;
+use crate::service::TimeKeeper;
+
+const COMMANDS: [&str; 10] = [
+    "in", "out", "now", "add", "status", "summary", "week", "report", "help", "exit",
+];
+
+#[derive(Helper, Highlighter, Hinter, Validator)]
+struct ShellHelper;
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    // Only the command word is completed, arguments are free-form times
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let prefix = &line[..pos];
+        if prefix.contains(' ') {
+            return Ok((pos, Vec::new()));
+        }
+
+        let candidates = COMMANDS
+            .iter()
+            .filter(|command| command.starts_with(prefix))
+            .map(|command| Pair {
+                display: command.to_string(),
+                replacement: format!("{} ", command),
+            })
+            .collect();
+
+        Ok((0, candidates))
+    }
+}
+
+impl From<ReadlineError> for TimeKeeperError {
+    fn from(err: ReadlineError) -> Self {
+        match err {
+            ReadlineError::Io(e) => TimeKeeperError::IoError(e),
+            other => TimeKeeperError::IoError(std::io::Error::other(other.to_string())),
+        }
+    }
+}
+
+fn print_help() {
+    println!("Commands:");
+    println!("  in [TIME]            check in, defaults to now");
+    println!("  out [TIME]           check out, defaults to now");
+    println!("  now                  check out if checked in, otherwise check in");
+    println!("  add IN OUT [MMDD]    record a completed entry");
+    println!("  status               show the open session, if any");
+    println!("  summary              show all records");
+    println!("  week                 show this week's totals per day");
+    println!("  report [DIMENSION]   totals by day, week, month or weekday");
+    println!("  exit                 leave the shell");
+}
+
+// Runs a single shell line, returning false when the shell should exit
+fn run_line(keeper: &TimeKeeper, line: &str) -> Result<bool, TimeKeeperError> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words.as_slice() {
+        [] => {}
+        ["in"] => handle_check_in(keeper, &get_now(), None, false, None, false, None)?,
+        ["in", time] => handle_check_in(keeper, time, None, false, None, false, None)?,
+        ["out"] => handle_check_out(keeper, &get_now(), None, false, None)?,
+        ["out", time] => handle_check_out(keeper, time, None, false, None)?,
+        ["now"] => handle_toggle(keeper, None)?,
+        ["add", check_in, check_out] => handle_record(
+            keeper, check_in, check_out, None, None, false, None, false, false,
+        )?,
+        ["add", check_in, check_out, date] => handle_record(
+            keeper,
+            check_in,
+            check_out,
+            Some(date.to_string()),
+            None,
+            false,
+            None,
+            false,
+            false,
+        )?,
+        ["status"] => display_status(keeper, false)?,
+        ["summary"] => display_summary(
+            keeper.db(),
+            &PeriodArgs::default(),
+            None,
+            None,
+            false,
+            false,
+        )?,
+        ["week"] => display_week(keeper.db())?,
+        ["report"] => handle_report(
+            keeper.db(),
+            &[Dimension::Day],
+            false,
+            None,
+            &PeriodArgs::default(),
+            false,
+            false,
+        )?,
+        ["report", dimension] => {
+            let dimension = <Dimension as clap::ValueEnum>::from_str(dimension, true)
+                .map_err(TimeKeeperError::ParseError)?;
+            handle_report(
+                keeper.db(),
+                &[dimension],
+                false,
+                None,
+                &PeriodArgs::default(),
+                false,
+                false,
+            )?
+        }
+        ["help"] => print_help(),
+        ["exit"] | ["quit"] => return Ok(false),
+        _ => println!("Unknown command '{}', type help for a list", line.trim()),
+    }
+
+    Ok(true)
+}
+
+pub fn run_shell(keeper: &TimeKeeper) -> Result<(), TimeKeeperError> {
+    let paths = Paths::new()?;
+    let history_path = paths.shell_history_file();
+
+    let mut editor: Editor<ShellHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ShellHelper));
+    // A missing history file just means this is the first session
+    let _ = editor.load_history(&history_path);
+
+    println!("TimeKeeper shell, type help for commands");
+    loop {
+        match editor.readline("tk> ") {
+            Ok(line) => {
+                editor.add_history_entry(line.as_str())?;
+                match run_line(keeper, &line) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    ensure_dir(&paths.state)?;
+    editor.save_history(&history_path)?;
+    Ok(())
+}
@@ -0,0 +1,40 @@
+// The day start is set once per process, so this runs as its own test binary
+// rather than beside the unit tests that expect days to start at midnight.
+
+use chrono::{Duration, NaiveDate};
+use timekeeper::db::{set_day_start, Db, Record};
+
+#[test]
+fn test_day_starts_at() {
+    set_day_start(Duration::hours(4));
+    let db = Db::open_in_memory().unwrap();
+    let day = |d| NaiveDate::from_ymd_opt(2024, 7, d).unwrap();
+    let entry = |d, start, hours| Record {
+        id: 0,
+        check_in: day(d).and_hms_opt(start, 0, 0).unwrap(),
+        check_out: Some(day(d).and_hms_opt(start, 0, 0).unwrap() + Duration::hours(hours)),
+        location: None,
+        travel: false,
+        project: None,
+        note: None,
+        unpaid_break: Duration::zero(),
+    };
+
+    // A late shift and the hours after midnight count towards the same day
+    db.save_entry(&entry(8, 20, 3)).unwrap();
+    let after_midnight = db.save_entry(&entry(9, 1, 2)).unwrap();
+    db.save_entry(&entry(9, 9, 8)).unwrap();
+    assert_eq!(after_midnight.date(), day(8));
+    assert_eq!(db.get_entries_between(day(8), day(8)).unwrap().len(), 2);
+    assert_eq!(db.get_entries_by_date(day(9)).unwrap().len(), 1);
+
+    // Totals skip the cache, which is keyed on calendar days. Asking twice
+    // gives the same answer and lines up with the logical days.
+    for _ in 0..2 {
+        let totals = db.get_daily_totals(day(8), day(9)).unwrap();
+        assert_eq!(totals.len(), 2);
+        assert_eq!(totals[0].date, day(8));
+        assert_eq!(totals[0].worked, Duration::hours(5));
+        assert_eq!(totals[1].worked, Duration::hours(8));
+    }
+}